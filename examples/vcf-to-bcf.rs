@@ -0,0 +1,22 @@
+use std::{env, fs::File, io::BufWriter};
+use vcflib::{bcf::BCFWriter, parser::VCFParser};
+
+/// Converts a VCF file to BGZF compressed BCF, e.g., to check the output with `bcftools view`.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <input.vcf> <output.bcf>", args[0]);
+        std::process::exit(1);
+    }
+
+    // reader
+    let parser = VCFParser::new(File::open(&args[1]).unwrap()).unwrap();
+
+    // writer
+    let output = BufWriter::new(File::create(&args[2]).unwrap());
+    let mut writer = BCFWriter::new(output, &parser.header).unwrap();
+    for dl in parser.reader {
+        writer.write_data_line(&dl.unwrap()).unwrap();
+    }
+    writer.finish().unwrap();
+}
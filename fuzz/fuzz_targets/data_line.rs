@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use vcflib::body::DataLine;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let column_names = vec!["SAMP001".to_string(), "SAMP002".to_string()];
+        if let Ok(dl) = DataLine::new(line, &column_names) {
+            let _ = dl.to_string();
+        }
+        let _ = DataLine::new(line, &[]);
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use vcflib::header::{parse_column_names, parse_header_payload, HeaderLine};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = parse_header_payload(line);
+        let _ = parse_column_names(line);
+        if let Ok(hl) = line.parse::<HeaderLine>() {
+            let _ = hl.to_string();
+        }
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use vcflib::parser::{Limits, VCFParser};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_line_bytes: 1 << 16,
+        max_header_bytes: 1 << 20,
+        max_header_lines: 1 << 10,
+        max_samples: 1 << 10,
+        max_alleles: 1 << 8,
+    };
+    if let Ok(parser) = VCFParser::with_limits(data, limits) {
+        for dl in parser.reader {
+            let _ = dl;
+        }
+    }
+});
@@ -0,0 +1,665 @@
+use crate::{
+    body::{AltType, DataLine, FilterType, FormatType, IdType, InfoType, QualType, SampleType},
+    compression::BgzfWriter,
+    header::{self, Header, HeaderLine},
+    parser::FIXED_COLUMNS,
+    text::{checked_str, Text},
+};
+use linked_hash_map::LinkedHashMap;
+use std::{collections::HashMap, io, io::Write};
+
+/// The magic string at the start of every BCF file, followed by the major and minor version.
+pub const BCF_MAGIC: &[u8] = b"BCF\x02\x02";
+
+// Atomic types of typed values, (CF Sec 6.3.3 of the VCF specification v4.3)
+const BT_NULL: u8 = 0;
+const BT_INT8: u8 = 1;
+const BT_INT16: u8 = 2;
+const BT_INT32: u8 = 3;
+const BT_FLOAT: u8 = 5;
+const BT_CHAR: u8 = 7;
+
+const FLOAT_MISSING: u32 = 0x7F80_0001;
+const FLOAT_VECTOR_END: u32 = 0x7F80_0002;
+
+/// How values of an INFO or FORMAT key are encoded, as defined by its header line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueType {
+    Flag,
+    Integer,
+    Float,
+    String,
+}
+
+/// A single integer of an integer vector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IntValue {
+    Value(i32),
+    Missing,
+    VectorEnd,
+}
+
+/// A writer of BCF2 files. The records are encoded using the types declared in the header and the
+/// output is compressed using BGZF.
+pub struct BCFWriter<W: Write> {
+    writer: BgzfWriter<W>,
+
+    /// Index of each contig in the contig dictionary.
+    contigs: HashMap<String, i32>,
+
+    /// Index of each FILTER, INFO and FORMAT key in the string dictionary.
+    strings: HashMap<String, i32>,
+
+    info_types: HashMap<String, ValueType>,
+    format_types: HashMap<String, ValueType>,
+    sample_count: usize,
+}
+
+impl<W: Write> BCFWriter<W> {
+    pub fn new(writer: W, header: &Header) -> anyhow::Result<BCFWriter<W>> {
+        // PASS is always the first entry of the string dictionary
+        let mut strings = HashMap::new();
+        strings.insert("PASS".to_string(), 0);
+        let mut contigs = HashMap::new();
+        let mut info_types = HashMap::new();
+        let mut format_types = HashMap::new();
+        for hl in &header.header_lines {
+            let id = match hl {
+                HeaderLine::Contig { id, .. } => {
+                    let index = contigs.len() as i32;
+                    if contigs.insert(id.to_string(), index).is_some() {
+                        return Err(anyhow::anyhow!("duplicate contig `{}` in header", id));
+                    }
+                    continue;
+                }
+                HeaderLine::Filter { id, .. } => id,
+                HeaderLine::Info { id, typ, .. } => {
+                    let value_type = match typ {
+                        header::InfoType::Flag => ValueType::Flag,
+                        header::InfoType::Integer => ValueType::Integer,
+                        header::InfoType::Float => ValueType::Float,
+                        header::InfoType::Character | header::InfoType::String => ValueType::String,
+                    };
+                    info_types.insert(id.to_string(), value_type);
+                    id
+                }
+                HeaderLine::Format { id, typ, .. } => {
+                    let value_type = match typ {
+                        header::FormatType::Integer => ValueType::Integer,
+                        header::FormatType::Float => ValueType::Float,
+                        header::FormatType::Character | header::FormatType::String => {
+                            ValueType::String
+                        }
+                    };
+                    format_types.insert(id.to_string(), value_type);
+                    id
+                }
+                _ => continue,
+            };
+            if !strings.contains_key(id) {
+                let index = strings.len() as i32;
+                strings.insert(id.to_string(), index);
+            }
+        }
+
+        let mut bcf_writer = BCFWriter {
+            writer: BgzfWriter::new(writer),
+            contigs,
+            strings,
+            info_types,
+            format_types,
+            sample_count: header.column_names.len(),
+        };
+
+        let mut text = bcf_writer.header_text(header)?;
+        text.push(0);
+        bcf_writer.writer.write_all(BCF_MAGIC)?;
+        bcf_writer
+            .writer
+            .write_all(&(text.len() as u32).to_le_bytes())?;
+        bcf_writer.writer.write_all(&text)?;
+
+        Ok(bcf_writer)
+    }
+
+    pub fn write_data_line(&mut self, dl: &DataLine) -> io::Result<()> {
+        let shared = self.encode_shared(dl).map_err(invalid_data)?;
+        let indiv = self.encode_indiv(dl).map_err(invalid_data)?;
+        self.writer
+            .write_all(&(shared.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&(indiv.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&shared)?;
+        self.writer.write_all(&indiv)
+    }
+
+    /// Writes any buffered records and the BGZF end of file marker, and returns the underlying
+    /// writer. Dropping the writer has the same effect but ignores any errors.
+    pub fn finish(self) -> io::Result<W> {
+        self.writer.finish()
+    }
+
+    /// The textual VCF header stored in the BCF file. `IDX` fields are set on all dictionary
+    /// entries, replacing those of the header, so that their indices are explicit. Free text is
+    /// kept as the bytes read.
+    fn header_text(&self, header: &Header) -> io::Result<Vec<u8>> {
+        let mut text = vec![];
+        writeln!(text, "{}", header.version)?;
+        if !header.header_lines.iter().any(|hl| match hl {
+            HeaderLine::Filter { id, .. } => id == "PASS",
+            _ => false,
+        }) {
+            let pass = HeaderLine::Filter {
+                id: "PASS".to_string(),
+                description: Text::from("All filters passed"),
+                other: LinkedHashMap::new(),
+            };
+            self.with_idx(&pass).write_to(&mut text)?;
+            writeln!(text)?;
+        }
+        for hl in &header.header_lines {
+            self.with_idx(hl).write_to(&mut text)?;
+            writeln!(text)?;
+        }
+        write!(text, "#{}", FIXED_COLUMNS.join("\t"))?;
+        if !header.column_names.is_empty() {
+            write!(text, "\tFORMAT")?;
+            for cn in &header.column_names {
+                write!(text, "\t{}", cn)?;
+            }
+        }
+        writeln!(text)?;
+        Ok(text)
+    }
+
+    /// The header line with the `IDX` of its dictionary entry, if any, as its last attribute.
+    fn with_idx(&self, hl: &HeaderLine) -> HeaderLine {
+        let mut hl = hl.clone();
+        let (index, other) = match &mut hl {
+            HeaderLine::Contig { id, other } => (self.contigs.get(id), other),
+            HeaderLine::Filter { id, other, .. }
+            | HeaderLine::Info { id, other, .. }
+            | HeaderLine::Format { id, other, .. } => (self.strings.get(id), other),
+            _ => return hl,
+        };
+        if let Some(index) = index {
+            other.remove("IDX");
+            other.insert("IDX".to_string(), index.to_string());
+        }
+        hl
+    }
+
+    fn encode_shared(&self, dl: &DataLine) -> anyhow::Result<Vec<u8>> {
+        let mut buf = vec![];
+
+        let contig = self.contigs.get(&dl.chromosome).ok_or_else(|| {
+            anyhow::anyhow!("contig `{}` is not defined in the header", dl.chromosome)
+        })?;
+        if dl.position == 0 || dl.position > i32::MAX as u64 {
+            return Err(anyhow::anyhow!("invalid position `{}`", dl.position));
+        }
+        let position = dl.position as i32 - 1;
+        let rlen = match dl.info_value("END")? {
+            Some(end) => end.parse::<i32>()? - position,
+            None => dl.reference.len() as i32,
+        };
+        let quality = match dl.quality {
+            QualType::Missing => FLOAT_MISSING,
+            QualType::Integer(n) => (n as f32).to_bits(),
+        };
+
+        let info_entries: &[Text] = match &dl.info {
+            InfoType::Missing => &[],
+            InfoType::Entries(entries) => entries,
+        };
+        let alt_entries: &[String] = match &dl.alternative {
+            AltType::Missing => &[],
+            AltType::Entries(entries) => entries,
+        };
+        let format_count = match &dl.format {
+            Some(FormatType::Entries(entries)) => entries.len(),
+            _ => 0,
+        };
+
+        buf.extend_from_slice(&contig.to_le_bytes());
+        buf.extend_from_slice(&position.to_le_bytes());
+        buf.extend_from_slice(&rlen.to_le_bytes());
+        buf.extend_from_slice(&quality.to_le_bytes());
+        let n_allele_info = ((alt_entries.len() as u32 + 1) << 16) | info_entries.len() as u32;
+        buf.extend_from_slice(&n_allele_info.to_le_bytes());
+        let n_fmt_sample = ((format_count as u32) << 24) | self.sample_count as u32;
+        buf.extend_from_slice(&n_fmt_sample.to_le_bytes());
+
+        match &dl.id {
+            IdType::Missing => encode_typed_string(&mut buf, ""),
+            IdType::Entries(entries) => encode_typed_string(&mut buf, &entries.join(";")),
+        }
+
+        encode_typed_string(&mut buf, &dl.reference);
+        for alt in alt_entries {
+            encode_typed_string(&mut buf, alt);
+        }
+
+        let filters = match &dl.filter {
+            FilterType::Missing => vec![],
+            FilterType::Pass => vec![IntValue::Value(0)],
+            FilterType::Entries(entries) => {
+                let mut filters = vec![];
+                for entry in entries {
+                    filters.push(IntValue::Value(self.string_index(entry)?));
+                }
+                filters
+            }
+        };
+        encode_typed_ints(&mut buf, &filters);
+
+        for entry in info_entries {
+            let entry = entry.as_bytes();
+            let (key, value) = match entry.iter().position(|b| *b == b'=') {
+                Some(index) => (checked_str(&entry[..index])?, Some(&entry[index + 1..])),
+                None => (checked_str(entry)?, None),
+            };
+            encode_typed_ints(&mut buf, &[IntValue::Value(self.string_index(key)?)]);
+            let value_type = self.info_types.get(key).ok_or_else(|| {
+                anyhow::anyhow!("INFO key `{}` is not defined in the header", key)
+            })?;
+            match (value_type, value) {
+                (ValueType::Flag, _) | (_, None) => encode_type_descriptor(&mut buf, 0, BT_NULL),
+                (ValueType::Integer, Some(value)) => {
+                    encode_typed_ints(&mut buf, &parse_ints(checked_str(value)?)?);
+                }
+                (ValueType::Float, Some(value)) => {
+                    let floats = parse_floats(checked_str(value)?)?;
+                    encode_type_descriptor(&mut buf, floats.len(), BT_FLOAT);
+                    for f in floats {
+                        buf.extend_from_slice(&f.to_le_bytes());
+                    }
+                }
+                (ValueType::String, Some(value)) => encode_typed_string(&mut buf, value),
+            }
+        }
+
+        Ok(buf)
+    }
+
+    fn encode_indiv(&self, dl: &DataLine) -> anyhow::Result<Vec<u8>> {
+        let mut buf = vec![];
+        let format_entries = match &dl.format {
+            Some(FormatType::Entries(entries)) => entries,
+            _ => return Ok(buf),
+        };
+
+        for (field_index, key) in format_entries.iter().enumerate() {
+            encode_typed_ints(&mut buf, &[IntValue::Value(self.string_index(key)?)]);
+            let values: Vec<Option<&[u8]>> = dl
+                .samples
+                .iter()
+                .map(|s| sample_value(s, field_index))
+                .collect();
+
+            let value_type = if key == "GT" {
+                None
+            } else {
+                Some(*self.format_types.get(key).ok_or_else(|| {
+                    anyhow::anyhow!("FORMAT key `{}` is not defined in the header", key)
+                })?)
+            };
+            match value_type {
+                None => {
+                    let mut vectors = vec![];
+                    for value in values {
+                        vectors.push(match value {
+                            Some(gt) => parse_genotype(checked_str(gt)?)?,
+                            None => vec![IntValue::Missing],
+                        });
+                    }
+                    encode_int_vectors(&mut buf, vectors);
+                }
+                Some(ValueType::Integer) => {
+                    let mut vectors = vec![];
+                    for value in values {
+                        vectors.push(match value {
+                            Some(value) => parse_ints(checked_str(value)?)?,
+                            None => vec![IntValue::Missing],
+                        });
+                    }
+                    encode_int_vectors(&mut buf, vectors);
+                }
+                Some(ValueType::Float) => {
+                    let mut vectors = vec![];
+                    for value in values {
+                        vectors.push(match value {
+                            Some(value) => parse_floats(checked_str(value)?)?,
+                            None => vec![FLOAT_MISSING],
+                        });
+                    }
+                    let width = vectors.iter().map(|v| v.len()).max().unwrap_or(0);
+                    encode_type_descriptor(&mut buf, width, BT_FLOAT);
+                    for vector in vectors {
+                        for i in 0..width {
+                            let f = vector.get(i).copied().unwrap_or(FLOAT_VECTOR_END);
+                            buf.extend_from_slice(&f.to_le_bytes());
+                        }
+                    }
+                }
+                Some(ValueType::Flag) | Some(ValueType::String) => {
+                    let values: Vec<&[u8]> = values.iter().map(|v| v.unwrap_or(b".")).collect();
+                    let width = values.iter().map(|v| v.len()).max().unwrap_or(0);
+                    encode_type_descriptor(&mut buf, width, BT_CHAR);
+                    for value in values {
+                        buf.extend_from_slice(value);
+                        buf.resize(buf.len() + width - value.len(), 0);
+                    }
+                }
+            }
+        }
+        Ok(buf)
+    }
+
+    fn string_index(&self, key: &str) -> anyhow::Result<i32> {
+        self.strings
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("key `{}` is not defined in the header", key))
+    }
+}
+
+fn invalid_data(e: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Returns the value of a sample at the given FORMAT index. Missing samples and trailing fields
+/// that have been dropped are returned as `None`.
+fn sample_value(sample: &SampleType, index: usize) -> Option<&[u8]> {
+    match sample {
+        SampleType::Missing => None,
+        SampleType::Entries(entries) => entries.get(index).map(|e| e.as_bytes()),
+    }
+}
+
+fn parse_ints(value: &str) -> anyhow::Result<Vec<IntValue>> {
+    let mut result = vec![];
+    for v in value.split(',') {
+        if v == "." {
+            result.push(IntValue::Missing);
+        } else {
+            let n = v
+                .parse::<i32>()
+                .map_err(|_| anyhow::anyhow!("invalid Integer value `{}`", v))?;
+            if n < -2_147_483_640 {
+                return Err(anyhow::anyhow!("Integer value `{}` out of range", v));
+            }
+            result.push(IntValue::Value(n));
+        }
+    }
+    Ok(result)
+}
+
+/// Parses comma separated floats, returning their bit patterns.
+fn parse_floats(value: &str) -> anyhow::Result<Vec<u32>> {
+    let mut result = vec![];
+    for v in value.split(',') {
+        if v == "." {
+            result.push(FLOAT_MISSING);
+        } else {
+            let f = v
+                .parse::<f32>()
+                .map_err(|_| anyhow::anyhow!("invalid Float value `{}`", v))?;
+            result.push(f.to_bits());
+        }
+    }
+    Ok(result)
+}
+
+/// Parses a genotype such as `0|1` or `./.` into its BCF representation, where each allele is
+/// stored as `(allele + 1) << 1 | phased` and missing alleles as `0`.
+fn parse_genotype(gt: &str) -> anyhow::Result<Vec<IntValue>> {
+    let mut result = vec![];
+    let mut phased = false;
+    let mut start = 0;
+    let mut push_allele = |allele: &str, phased: bool| -> anyhow::Result<()> {
+        let value = if allele == "." {
+            0
+        } else {
+            let n = allele
+                .parse::<i32>()
+                .map_err(|_| anyhow::anyhow!("invalid genotype `{}`", gt))?;
+            if !(0..=0x3fff_ffff).contains(&n) {
+                return Err(anyhow::anyhow!("invalid genotype `{}`", gt));
+            }
+            (n + 1) << 1
+        };
+        result.push(IntValue::Value(value | phased as i32));
+        Ok(())
+    };
+    for (i, ch) in gt.char_indices() {
+        if ch == '/' || ch == '|' {
+            push_allele(&gt[start..i], phased)?;
+            phased = ch == '|';
+            start = i + 1;
+        }
+    }
+    push_allele(&gt[start..], phased)?;
+    Ok(result)
+}
+
+fn encode_type_descriptor(buf: &mut Vec<u8>, len: usize, typ: u8) {
+    if len < 15 {
+        buf.push((len as u8) << 4 | typ);
+    } else {
+        buf.push(15 << 4 | typ);
+        encode_typed_ints(buf, &[IntValue::Value(len as i32)]);
+    }
+}
+
+fn encode_typed_string<S: AsRef<[u8]> + ?Sized>(buf: &mut Vec<u8>, s: &S) {
+    let s = s.as_ref();
+    encode_type_descriptor(buf, s.len(), BT_CHAR);
+    buf.extend_from_slice(s);
+}
+
+/// Returns the smallest integer type that can hold all given values. The lowest values of each
+/// type are reserved for missing and vector end values.
+fn int_type(values: &[IntValue]) -> u8 {
+    let (min, max) = values
+        .iter()
+        .filter_map(|v| match v {
+            IntValue::Value(n) => Some(*n),
+            _ => None,
+        })
+        .fold((0, 0), |(min, max), n| (n.min(min), n.max(max)));
+    if min >= -120 && max <= i8::MAX as i32 {
+        BT_INT8
+    } else if min >= -32760 && max <= i16::MAX as i32 {
+        BT_INT16
+    } else {
+        BT_INT32
+    }
+}
+
+fn encode_ints(buf: &mut Vec<u8>, typ: u8, values: &[IntValue]) {
+    for v in values {
+        match typ {
+            BT_INT8 => buf.push(match v {
+                IntValue::Value(n) => *n as i8 as u8,
+                IntValue::Missing => 0x80,
+                IntValue::VectorEnd => 0x81,
+            }),
+            BT_INT16 => buf.extend_from_slice(
+                &match v {
+                    IntValue::Value(n) => *n as i16 as u16,
+                    IntValue::Missing => 0x8000,
+                    IntValue::VectorEnd => 0x8001,
+                }
+                .to_le_bytes(),
+            ),
+            _ => buf.extend_from_slice(
+                &match v {
+                    IntValue::Value(n) => *n as u32,
+                    IntValue::Missing => 0x8000_0000,
+                    IntValue::VectorEnd => 0x8000_0001,
+                }
+                .to_le_bytes(),
+            ),
+        }
+    }
+}
+
+fn encode_typed_ints(buf: &mut Vec<u8>, values: &[IntValue]) {
+    if values.is_empty() {
+        encode_type_descriptor(buf, 0, BT_NULL);
+        return;
+    }
+    let typ = int_type(values);
+    encode_type_descriptor(buf, values.len(), typ);
+    encode_ints(buf, typ, values);
+}
+
+/// Encodes one integer vector per sample, padding shorter vectors with vector end values.
+fn encode_int_vectors(buf: &mut Vec<u8>, mut vectors: Vec<Vec<IntValue>>) {
+    let width = vectors.iter().map(|v| v.len()).max().unwrap_or(0);
+    for vector in vectors.iter_mut() {
+        vector.resize(width, IntValue::VectorEnd);
+    }
+    let all_values: Vec<IntValue> = vectors.concat();
+    let typ = int_type(&all_values);
+    encode_type_descriptor(buf, width, typ);
+    encode_ints(buf, typ, &all_values);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bcf::*, header::HeaderLine};
+    use flate2::read::MultiGzDecoder;
+    use std::{io::Read, str::FromStr};
+
+    fn small_header() -> Header {
+        let header_lines = vec![
+            "##contig=<ID=20,length=62435964>",
+            "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\">",
+            "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele Frequency\">",
+            "##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP membership\">",
+            "##FILTER=<ID=q10,Description=\"Quality below 10\">",
+            "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">",
+            "##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Read Depth\">",
+            "##FORMAT=<ID=GP,Number=G,Type=Float,Description=\"Genotype Probabilities\">",
+        ]
+        .into_iter()
+        .map(|l| HeaderLine::from_str(l).unwrap())
+        .collect();
+        Header::new(
+            "VCFv4.3".to_string(),
+            header_lines,
+            vec!["NA00001".to_string(), "NA00002".to_string()],
+        )
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<u8> {
+        let mut decoded = vec![];
+        MultiGzDecoder::new(bytes)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_header() {
+        let header = small_header();
+        let writer = BCFWriter::new(vec![], &header).unwrap();
+        let decoded = decode(&writer.finish().unwrap());
+
+        assert_eq!(&decoded[..5], BCF_MAGIC);
+        let l_text = u32::from_le_bytes([decoded[5], decoded[6], decoded[7], decoded[8]]) as usize;
+        assert_eq!(decoded.len(), 9 + l_text);
+        let text = std::str::from_utf8(&decoded[9..9 + l_text - 1]).unwrap();
+        assert!(text.starts_with("##fileformat=VCFv4.3\n##FILTER=<ID=PASS,"));
+        assert!(text.contains("##contig=<ID=20,length=62435964,IDX=0>\n"));
+        assert!(text
+            .contains("##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\",IDX=1>\n"));
+        assert!(text.contains("##FILTER=<ID=q10,Description=\"Quality below 10\",IDX=4>\n"));
+        // DP shares the dictionary entry of the INFO key with the same ID
+        assert!(text
+            .contains("##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Read Depth\",IDX=1>\n"));
+        assert!(text.ends_with(
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001\tNA00002\n"
+        ));
+        assert_eq!(decoded[9 + l_text - 1], 0);
+    }
+
+    #[test]
+    fn test_existing_idx() {
+        let header_lines = vec![
+            "##contig=<ID=20,length=62435964,IDX=7>",
+            "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\",IDX=3>",
+            "##FILTER=<ID=q10,Description=\"Quality below 10\",IDX=9>",
+            "##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype Quality\",IDX=1>",
+        ]
+        .into_iter()
+        .map(|l| HeaderLine::from_str(l).unwrap())
+        .collect();
+        let header = Header::new("VCFv4.3".to_string(), header_lines, vec![]);
+        let decoded = decode(&BCFWriter::new(vec![], &header).unwrap().finish().unwrap());
+        let text = String::from_utf8_lossy(&decoded);
+        assert!(text.contains("##contig=<ID=20,length=62435964,IDX=0>\n"));
+        assert!(text
+            .contains("##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\",IDX=1>\n"));
+        assert!(text.contains("##FILTER=<ID=q10,Description=\"Quality below 10\",IDX=2>\n"));
+        assert!(text.contains(
+            "##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype Quality\",IDX=3>\n"
+        ));
+        assert_eq!(text.matches("IDX=").count(), 5);
+    }
+
+    #[test]
+    fn test_record() {
+        let header = small_header();
+        let line = DataLine::new(
+            "20\t14370\trs6054257\tG\tA\t29\tPASS\tDP=14;AF=0.5;DB\tGT:DP:GP\t0|1:1:0.1,0.2,0.7\t1/1",
+            &header.column_names,
+        )
+        .unwrap();
+        let mut writer = BCFWriter::new(vec![], &header).unwrap();
+        writer.write_data_line(&line).unwrap();
+        let decoded = decode(&writer.finish().unwrap());
+
+        let l_text = u32::from_le_bytes([decoded[5], decoded[6], decoded[7], decoded[8]]) as usize;
+        let record = &decoded[9 + l_text..];
+        let l_shared = u32::from_le_bytes([record[0], record[1], record[2], record[3]]) as usize;
+        let l_indiv = u32::from_le_bytes([record[4], record[5], record[6], record[7]]) as usize;
+        assert_eq!(record.len(), 8 + l_shared + l_indiv);
+
+        let shared = &record[8..8 + l_shared];
+        let read_u32 =
+            |i: usize| u32::from_le_bytes([shared[i], shared[i + 1], shared[i + 2], shared[i + 3]]);
+        assert_eq!(read_u32(0), 0); // CHROM
+        assert_eq!(read_u32(4), 14369); // POS
+        assert_eq!(read_u32(8), 1); // rlen
+        assert_eq!(f32::from_bits(read_u32(12)), 29.0); // QUAL
+        assert_eq!(read_u32(16), 2 << 16 | 3); // n_allele, n_info
+        assert_eq!(read_u32(20), 3 << 24 | 2); // n_fmt, n_sample
+        assert_eq!(&shared[24..34], b"\x97rs6054257");
+        assert_eq!(&shared[34..38], b"\x17G\x17A");
+        // FILTER: PASS
+        assert_eq!(&shared[38..40], &[0x11, 0]);
+        // INFO: DP=14
+        assert_eq!(&shared[40..44], &[0x11, 1, 0x11, 14]);
+
+        let indiv = &record[8 + l_shared..];
+        // GT: key, descriptor and two alleles per sample
+        assert_eq!(&indiv[..7], &[0x11, 5, 0x21, 2, 5, 4, 4]);
+        // DP: key, descriptor, value of first sample and missing value of second sample
+        assert_eq!(&indiv[7..11], &[0x11, 1, 0x11, 1]);
+        assert_eq!(indiv[11], 0x80);
+    }
+
+    #[test]
+    fn test_undefined_key() {
+        let header = small_header();
+        let line = DataLine::new(
+            "20\t14370\t.\tG\tA\t29\tPASS\tNS=3\tGT\t0|1\t1/1",
+            &header.column_names,
+        )
+        .unwrap();
+        let mut writer = BCFWriter::new(vec![], &header).unwrap();
+        assert!(writer.write_data_line(&line).is_err());
+    }
+}
@@ -1,11 +1,16 @@
-use crate::parser::FIXED_COLUMNS;
+use crate::{
+    header::{AltId, Header, Version},
+    parser::FIXED_COLUMNS,
+    text::{checked_str, write_joined, Text},
+};
 use std::{
     fmt::{Display, Error, Formatter},
+    io::{self, Write},
     str::FromStr,
 };
 
 /// A data line of the VCF file.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DataLine {
     /// An identifier from the reference genome or an angle-bracketed ID String (“<ID>”)
     /// pointing to a contig in the assembly file (cf. the ##assembly line in the header).
@@ -23,23 +28,23 @@ pub struct DataLine {
     /// Comma separated list of alternate non-reference alleles.
     pub alternative: AltType,
 
-    ///
+    /// Phred-scaled quality score for the assertion made in ALT.
     pub quality: QualType,
 
-    ///
+    /// PASS if this position has passed all filters, otherwise a list of the filters that failed.
     pub filter: FilterType,
 
-    ///
+    /// Semi-colon separated list of additional information, as key=value pairs or flags.
     pub info: InfoType,
 
-    ///
+    /// Colon separated list of the data types of the sample columns, if any.
     pub format: Option<FormatType>,
 
-    ///
+    /// The values of each sample, in the order of the FORMAT column.
     pub samples: Vec<SampleType>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum IdType {
     Missing,
     Entries(Vec<String>),
@@ -70,7 +75,7 @@ impl Display for IdType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AltType {
     Missing,
     Entries(Vec<String>),
@@ -101,7 +106,7 @@ impl Display for AltType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum QualType {
     Missing,
     Integer(u32),
@@ -132,7 +137,7 @@ impl Display for QualType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FilterType {
     Missing,
     Pass,
@@ -167,38 +172,61 @@ impl Display for FilterType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// The INFO column. Entries are kept as bytes, as their values are free text.
+#[derive(Debug, Clone, PartialEq)]
 pub enum InfoType {
     Missing,
-    Entries(Vec<String>),
+    Entries(Vec<Text>),
 }
 
-impl FromStr for InfoType {
-    type Err = anyhow::Error;
-
-    fn from_str(info_str: &str) -> anyhow::Result<Self> {
-        if info_str.is_empty() {
+impl InfoType {
+    pub fn from_bytes(info: &[u8]) -> anyhow::Result<Self> {
+        if info.is_empty() {
             return Err(anyhow::anyhow!("info cannot be empty"));
         }
-        let info = if info_str == "." {
+        let info = if info == b"." {
             InfoType::Missing
         } else {
-            InfoType::Entries(info_str.split(';').map(|s| s.to_string()).collect())
+            InfoType::Entries(info.split(|b| *b == b';').map(Text::from).collect())
         };
         Ok(info)
     }
+
+    /// Writes the column as the bytes read.
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            InfoType::Missing => writer.write_all(b"."),
+            InfoType::Entries(entries) => write_joined(writer, entries, b';'),
+        }
+    }
+}
+
+impl FromStr for InfoType {
+    type Err = anyhow::Error;
+
+    fn from_str(info_str: &str) -> anyhow::Result<Self> {
+        InfoType::from_bytes(info_str.as_bytes())
+    }
 }
 
 impl Display for InfoType {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             InfoType::Missing => write!(f, "."),
-            InfoType::Entries(s) => write!(f, "{}", s.join(";")),
+            InfoType::Entries(entries) => {
+                for (index, entry) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ";")?;
+                    }
+                    write!(f, "{}", entry)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FormatType {
     Missing,
     Entries(Vec<String>),
@@ -229,27 +257,40 @@ impl Display for FormatType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// A sample column. Values are kept as bytes, as String values are free text.
+#[derive(Debug, Clone, PartialEq)]
 pub enum SampleType {
     Missing,
-    Entries(Vec<String>),
+    Entries(Vec<Text>),
 }
 
 impl SampleType {
-    fn new(sample_vec: Vec<&str>) -> anyhow::Result<Vec<Self>> {
-        let mut result = vec![];
-        for sample_str in sample_vec {
-            if sample_str.is_empty() {
-                return Err(anyhow::anyhow!("sample cannot be empty"));
-            }
-            let sample = if sample_str == "." {
-                SampleType::Missing
-            } else {
-                SampleType::Entries(sample_str.split(':').map(|s| s.to_string()).collect())
-            };
-            result.push(sample);
+    pub fn from_bytes(sample: &[u8]) -> anyhow::Result<Self> {
+        if sample.is_empty() {
+            return Err(anyhow::anyhow!("sample cannot be empty"));
+        }
+        let sample = if sample == b"." {
+            SampleType::Missing
+        } else {
+            SampleType::Entries(sample.split(|b| *b == b':').map(Text::from).collect())
+        };
+        Ok(sample)
+    }
+
+    /// Writes the column as the bytes read.
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            SampleType::Missing => writer.write_all(b"."),
+            SampleType::Entries(entries) => write_joined(writer, entries, b':'),
         }
-        Ok(result)
+    }
+}
+
+impl FromStr for SampleType {
+    type Err = anyhow::Error;
+
+    fn from_str(sample_str: &str) -> anyhow::Result<Self> {
+        SampleType::from_bytes(sample_str.as_bytes())
     }
 }
 
@@ -257,21 +298,149 @@ impl Display for SampleType {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             SampleType::Missing => write!(f, "."),
-            SampleType::Entries(s) => write!(f, "{}", s.join(":")),
+            SampleType::Entries(entries) => {
+                for (index, entry) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ":")?;
+                    }
+                    write!(f, "{}", entry)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The type of an alternate allele, relative to the reference allele.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantType {
+    /// A single base substitution, e.g., `REF=A ALT=G`.
+    Snv,
+
+    /// A substitution of several bases, e.g., `REF=AC ALT=GT`.
+    Mnp,
+
+    /// Example: `REF=A ALT=AT`.
+    Insertion,
+
+    /// Example: `REF=AT ALT=A`.
+    Deletion,
+
+    /// Any other change of the sequence, e.g., `REF=AC ALT=GTT`.
+    Complex,
+
+    /// A structural variant, e.g., `<DEL:ME>`, see `Header::alt_line` for its declaration.
+    Symbolic(Vec<AltId>),
+
+    /// Examples: `G]17:198982]` and the single breakend `G.`.
+    Breakend,
+
+    /// The `*` allele, missing because of an overlapping deletion.
+    Missing,
+
+    /// The gVCF `<*>` or `<NON_REF>` allele, i.e., any allele not given.
+    NonRef,
+
+    /// An allele equal to the reference allele.
+    Reference,
+}
+
+impl VariantType {
+    pub fn new(reference: &str, alt: &str) -> Self {
+        match alt {
+            "*" => return VariantType::Missing,
+            "<*>" | "<NON_REF>" => return VariantType::NonRef,
+            _ => {}
+        }
+        if alt.contains('[') || alt.contains(']') {
+            return VariantType::Breakend;
+        }
+        if alt.len() > 1 && (alt.starts_with('.') || alt.ends_with('.')) {
+            return VariantType::Breakend;
+        }
+        if let Some(ids) = alt.strip_prefix('<').and_then(|a| a.strip_suffix('>')) {
+            return VariantType::Symbolic(
+                AltId::new_alt_ids(ids).unwrap_or_else(|_| vec![AltId::Other(ids.to_string())]),
+            );
+        }
+        let (reference, alt) = (reference.as_bytes(), alt.as_bytes());
+        if reference.len() == alt.len() {
+            let differences = reference
+                .iter()
+                .zip(alt)
+                .filter(|(r, a)| !r.eq_ignore_ascii_case(a))
+                .count();
+            return match differences {
+                0 => VariantType::Reference,
+                1 => VariantType::Snv,
+                _ => VariantType::Mnp,
+            };
+        }
+        let (short, long) = if reference.len() < alt.len() {
+            (reference, alt)
+        } else {
+            (alt, reference)
+        };
+        let prefix = short
+            .iter()
+            .zip(long)
+            .take_while(|(s, l)| s.eq_ignore_ascii_case(l))
+            .count();
+        let suffix = short
+            .iter()
+            .rev()
+            .zip(long.iter().rev())
+            .take_while(|(s, l)| s.eq_ignore_ascii_case(l))
+            .count();
+        match prefix + suffix >= short.len() {
+            true if reference.len() < alt.len() => VariantType::Insertion,
+            true => VariantType::Deletion,
+            false => VariantType::Complex,
         }
     }
+
+    /// Whether the allele is a variant of the data line, i.e., not `*`, `<*>`, `<NON_REF>` or the
+    /// reference allele.
+    pub fn is_variant(&self) -> bool {
+        !matches!(
+            self,
+            VariantType::Missing | VariantType::NonRef | VariantType::Reference
+        )
+    }
 }
 
 impl DataLine {
     pub fn new(line_str: &str, column_names: &[String]) -> anyhow::Result<DataLine> {
-        let parts: Vec<&str> = line_str.split('\t').collect();
+        DataLine::with_max_alleles(line_str.as_bytes(), column_names, usize::MAX)
+    }
+
+    /// Parses a data line from bytes, see `with_max_alleles`.
+    pub fn from_bytes(line: &[u8], column_names: &[String]) -> anyhow::Result<DataLine> {
+        DataLine::with_max_alleles(line, column_names, usize::MAX)
+    }
 
+    /// Parses a data line, failing before its alleles are collected if it has more than
+    /// `max_alleles` alternate alleles. Columns are only split up to the expected number, so no
+    /// line allocates more than its columns. The INFO and sample columns are kept as bytes, while
+    /// the other columns fail with their name if they are not valid UTF-8.
+    pub fn with_max_alleles(
+        line: &[u8],
+        column_names: &[String],
+        max_alleles: usize,
+    ) -> anyhow::Result<DataLine> {
         let expected_len = if !column_names.is_empty() {
             // + 1 is for "FORMAT" column
             FIXED_COLUMNS.len() + column_names.len() + 1
         } else {
             FIXED_COLUMNS.len()
         };
+        let parts: Vec<&[u8]> = line.splitn(expected_len + 1, |b| *b == b'\t').collect();
+        if parts.len() > expected_len {
+            return Err(anyhow::anyhow!(
+                "invalid number of columns found, expected {}, found more",
+                expected_len
+            ));
+        }
         if parts.len() != expected_len {
             return Err(anyhow::anyhow!(
                 "invalid number of columns found, expected {}, found {}",
@@ -279,33 +448,141 @@ impl DataLine {
                 parts.len()
             ));
         }
+        let column = |index: usize| {
+            std::str::from_utf8(parts[index]).map_err(|_| {
+                let name = FIXED_COLUMNS.get(index).copied().unwrap_or("FORMAT");
+                anyhow::anyhow!("invalid UTF-8 in column `{}`", name)
+            })
+        };
+
+        let alt = column(4)?;
+        let alleles = alt.bytes().filter(|b| *b == b',').count() + 1;
+        if alt != "." && alleles > max_alleles {
+            return Err(anyhow::anyhow!(
+                "{} alternate alleles at {}:{}, more than the limit of {}",
+                alleles,
+                column(0)?,
+                column(1)?,
+                max_alleles
+            ));
+        }
 
         let format: Option<FormatType> = if expected_len > 8 {
-            Some(parts[8].parse()?)
+            Some(column(8)?.parse()?)
         } else {
             None
         };
 
         let samples: Vec<SampleType> = if expected_len > 9 {
-            SampleType::new((&parts[9..]).to_vec())?
+            parts[9..]
+                .iter()
+                .map(|sample| SampleType::from_bytes(sample))
+                .collect::<anyhow::Result<_>>()?
         } else {
             vec![]
         };
 
         Ok(DataLine {
-            chromosome: parts[0].parse()?,
-            position: parts[1].parse()?,
-            id: parts[2].parse()?,
-            reference: parts[3].parse()?,
-            alternative: parts[4].parse()?,
-            quality: parts[5].parse()?,
-            filter: parts[6].parse()?,
-            info: parts[7].parse()?,
+            chromosome: column(0)?.parse()?,
+            position: column(1)?.parse()?,
+            id: column(2)?.parse()?,
+            reference: column(3)?.parse()?,
+            alternative: alt.parse()?,
+            quality: column(5)?.parse()?,
+            filter: column(6)?.parse()?,
+            info: InfoType::from_bytes(parts[7])?,
             format,
             samples,
         })
     }
 
+    /// Returns the bytes of the value of the given key in the INFO column, if any. Flags have no
+    /// value.
+    pub fn info_bytes(&self, key: &str) -> Option<&[u8]> {
+        match &self.info {
+            InfoType::Entries(entries) => entries.iter().find_map(|e| {
+                let e = e.as_bytes();
+                let index = e.iter().position(|b| *b == b'=')?;
+                (&e[..index] == key.as_bytes()).then(|| &e[index + 1..])
+            }),
+            InfoType::Missing => None,
+        }
+    }
+
+    /// Returns the value of the given key in the INFO column, if any, failing if it is not valid
+    /// UTF-8. Flags have no value.
+    pub fn info_value(&self, key: &str) -> anyhow::Result<Option<&str>> {
+        self.info_bytes(key).map(checked_str).transpose()
+    }
+
+    /// The type of each alternate allele, in order.
+    pub fn variant_types(&self) -> Vec<VariantType> {
+        match &self.alternative {
+            AltType::Entries(alts) => alts
+                .iter()
+                .map(|alt| VariantType::new(&self.reference, alt))
+                .collect(),
+            AltType::Missing => vec![],
+        }
+    }
+
+    /// The IDs of the symbolic alleles not declared by an `##ALT` line of the header, in order
+    /// and without duplicates, see `Header::alt_line`.
+    pub fn undeclared_alt_ids(&self, header: &Header) -> Vec<Vec<AltId>> {
+        let mut undeclared: Vec<Vec<AltId>> = vec![];
+        for variant_type in self.variant_types() {
+            if let VariantType::Symbolic(ids) = variant_type {
+                if header.alt_line(&ids).is_none() && !undeclared.contains(&ids) {
+                    undeclared.push(ids);
+                }
+            }
+        }
+        undeclared
+    }
+
+    /// The last reference position of the data line. Symbolic `DEL`, `DUP`, `INV` and `CNV`
+    /// alleles span `POS` plus their SVLEN, which is per allele and takes precedence over
+    /// `INFO/END` from VCFv4.4. In earlier versions `INFO/END` is given and SVLEN is only used
+    /// without it. Any other data line ends with the last base of `REF`.
+    pub fn end(&self, version: &Version) -> u64 {
+        let reference_end = self.position + (self.reference.len() as u64).saturating_sub(1);
+        let info_end = self
+            .info_value("END")
+            .ok()
+            .flatten()
+            .and_then(|end| end.parse::<u64>().ok());
+        let svlen_end = self.svlen_end();
+        let end = if version.number().is_some_and(|number| number >= (4, 4)) {
+            svlen_end.or(info_end)
+        } else {
+            info_end.or(svlen_end)
+        };
+        end.unwrap_or(reference_end).max(self.position)
+    }
+
+    /// The end of the longest symbolic structural variant allele with a SVLEN.
+    fn svlen_end(&self) -> Option<u64> {
+        let svlens: Vec<&str> = self.info_value("SVLEN").ok()??.split(',').collect();
+        self.variant_types()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, variant_type)| match variant_type {
+                VariantType::Symbolic(ids)
+                    if matches!(
+                        ids.first(),
+                        Some(AltId::DEL | AltId::DUP | AltId::INV | AltId::CNV)
+                    ) =>
+                {
+                    // before VCFv4.4, SVLEN may have a single value and is negative for deletions
+                    let svlen = svlens.get(index).or_else(|| svlens.first())?;
+                    svlen.parse::<i64>().ok()
+                }
+                _ => None,
+            })
+            .map(|svlen| self.position + svlen.unsigned_abs())
+            .max()
+    }
+
     pub fn format_index(&self, entry: &str) -> Option<usize> {
         if let Some(format) = &self.format {
             match format {
@@ -316,32 +593,44 @@ impl DataLine {
             None
         }
     }
-}
 
-impl Display for DataLine {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "{}", self.chromosome)?;
-        write!(f, "\t{}", self.position)?;
-        write!(f, "\t{}", self.id)?;
-        write!(f, "\t{}", self.reference)?;
-        write!(f, "\t{}", self.alternative)?;
-        write!(f, "\t{}", self.quality)?;
-        write!(f, "\t{}", self.filter)?;
-        write!(f, "\t{}", self.info)?;
+    /// Writes the data line, with the INFO and sample columns as the bytes read.
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{}", self.chromosome)?;
+        write!(writer, "\t{}", self.position)?;
+        write!(writer, "\t{}", self.id)?;
+        write!(writer, "\t{}", self.reference)?;
+        write!(writer, "\t{}", self.alternative)?;
+        write!(writer, "\t{}", self.quality)?;
+        write!(writer, "\t{}\t", self.filter)?;
+        self.info.write_to(writer)?;
 
         if let Some(form) = &self.format {
-            write!(f, "\t{}", form)?;
+            write!(writer, "\t{}", form)?;
             for sample in self.samples.iter() {
-                write!(f, "\t{}", sample)?;
+                writer.write_all(b"\t")?;
+                sample.write_to(writer)?;
             }
         }
         Ok(())
     }
 }
 
+impl Display for DataLine {
+    /// Writes the data line, replacing bytes that are not valid UTF-8 with `U+FFFD`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let mut bytes = vec![];
+        self.write_to(&mut bytes).map_err(|_| Error)?;
+        f.write_str(&String::from_utf8_lossy(&bytes))
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::body::DataLine;
+    use crate::{
+        body::{DataLine, VariantType},
+        header::{AltId, Header, HeaderLine, Version},
+    };
 
     #[test]
     fn test_valid() {
@@ -370,4 +659,67 @@ mod test {
         println!("{:?}", actual_line);
         assert!(actual_line.is_err());
     }
+
+    #[test]
+    fn test_variant_types() {
+        let line_str =
+            "1\t100\t.\tAC\tAT,GT,ACT,A,GTT,<DEL:ME>,A[2:5[,A.,*,<*>,<NON_REF>,ac\t.\t.\t.";
+        let dl = DataLine::new(line_str, &[]).unwrap();
+        assert_eq!(
+            dl.variant_types(),
+            vec![
+                VariantType::Snv,
+                VariantType::Mnp,
+                VariantType::Insertion,
+                VariantType::Deletion,
+                VariantType::Complex,
+                VariantType::Symbolic(vec![AltId::DEL, AltId::Other("ME".to_string())]),
+                VariantType::Breakend,
+                VariantType::Breakend,
+                VariantType::Missing,
+                VariantType::NonRef,
+                VariantType::NonRef,
+                VariantType::Reference,
+            ]
+        );
+        assert!(!VariantType::new("A", "*").is_variant());
+        assert!(VariantType::new("A", "<DUP>").is_variant());
+    }
+
+    #[test]
+    fn test_undeclared_alt_ids() {
+        let alt_line = HeaderLine::Alt {
+            id: AltId::new_alt_ids("INS:ME").unwrap(),
+            description: "Insertion of a mobile element".into(),
+        };
+        let header = Header::new("VCFv4.3".to_string(), vec![alt_line], vec![]);
+        let line_str = "1\t100\t.\tA\t<INS:ME:ALU>,<DEL>,<CN2>,<DEL>,<*>,G\t.\t.\t.";
+        let dl = DataLine::new(line_str, &[]).unwrap();
+        assert_eq!(
+            dl.undeclared_alt_ids(&header),
+            vec![vec![AltId::DEL], vec![AltId::Other("CN2".to_string())]]
+        );
+    }
+
+    #[test]
+    fn test_end() {
+        let end = |line_str: &str, version: &str| {
+            let version = Version {
+                value: version.to_string(),
+            };
+            DataLine::new(line_str, &[]).unwrap().end(&version)
+        };
+        assert_eq!(end("1\t100\t.\tACGT\tA\t.\t.\t.", "VCFv4.3"), 103);
+        assert_eq!(end("1\t100\t.\tA\t<*>\t.\t.\tEND=150", "VCFv4.3"), 150);
+
+        // deletions have a negative SVLEN before VCFv4.4
+        let line_str = "1\t100\t.\tN\t<DEL>\t.\t.\tSVLEN=-50";
+        assert_eq!(end(line_str, "VCFv4.2"), 150);
+        let line_str = "1\t100\t.\tN\t<DEL>\t.\t.\tEND=120;SVLEN=-50";
+        assert_eq!(end(line_str, "VCFv4.3"), 120);
+        assert_eq!(end(line_str, "VCFv4.4"), 150);
+        let line_str = "1\t100\t.\tN\t<INS>,<DUP>\t.\t.\tSVLEN=300,40";
+        assert_eq!(end(line_str, "VCFv4.4"), 140);
+        assert_eq!(end("1\t100\t.\tN\t<INS>\t.\t.\tSVLEN=300", "VCFv4.4"), 100);
+    }
 }
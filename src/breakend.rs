@@ -0,0 +1,318 @@
+use crate::body::{AltType, DataLine, IdType, VariantType};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Error, Formatter},
+    str::FromStr,
+};
+
+/// How the sequence of a mate is joined to the bases of a breakend, named after the notation
+/// with `t` the bases and `p` the mate position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// `t[p[`, the sequence right of `p` is joined after `t`.
+    AfterRight,
+
+    /// `t]p]`, the reverse complement of the sequence left of `p` is joined after `t`.
+    AfterLeft,
+
+    /// `]p]t`, the sequence left of `p` is joined before `t`.
+    BeforeLeft,
+
+    /// `[p[t`, the reverse complement of the sequence right of `p` is joined before `t`.
+    BeforeRight,
+}
+
+/// The other side of a breakend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mate {
+    /// The chromosome, in angle brackets if it is a contig of the assembly file, e.g., `<ctg1>`.
+    pub chromosome: String,
+    pub position: u64,
+
+    /// Whether the joined sequence extends to the right of the position, `[p[`, rather than to
+    /// the left, `]p]`.
+    pub extends_right: bool,
+}
+
+/// A breakend allele, e.g., `G]17:198982]` or the single breakend `.A`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakend {
+    /// The reference base and any inserted sequence, e.g., `GTA` in `GTA[2:10[`, in IUPAC codes.
+    pub bases: String,
+
+    /// Whether the bases come before the join, as in `t[p[`, `t]p]` and `t.`.
+    pub bases_first: bool,
+
+    /// The mate, none for single breakends.
+    pub mate: Option<Mate>,
+}
+
+impl Breakend {
+    /// The orientation of the join, or none for single breakends.
+    pub fn orientation(&self) -> Option<Orientation> {
+        let mate = self.mate.as_ref()?;
+        let orientation = match (self.bases_first, mate.extends_right) {
+            (true, true) => Orientation::AfterRight,
+            (true, false) => Orientation::AfterLeft,
+            (false, false) => Orientation::BeforeLeft,
+            (false, true) => Orientation::BeforeRight,
+        };
+        Some(orientation)
+    }
+
+    /// The bases inserted at the join, i.e., the bases without the reference base.
+    pub fn inserted_sequence(&self) -> &str {
+        if self.bases.is_empty() {
+            ""
+        } else if self.bases_first {
+            &self.bases[1..]
+        } else {
+            &self.bases[..self.bases.len() - 1]
+        }
+    }
+
+    pub fn is_single(&self) -> bool {
+        self.mate.is_none()
+    }
+}
+
+impl FromStr for Breakend {
+    type Err = anyhow::Error;
+
+    fn from_str(breakend_str: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid breakend `{}`", breakend_str);
+        let is_bases = |bases: &str| {
+            !bases.is_empty()
+                && bases
+                    .bytes()
+                    .all(|b| b"ACGTURYSWKMBDHVN".contains(&b.to_ascii_uppercase()))
+        };
+        let bracket = match breakend_str.find(['[', ']']) {
+            Some(index) => breakend_str.as_bytes()[index] as char,
+            None => {
+                let (bases, bases_first) = match breakend_str.strip_prefix('.') {
+                    Some(bases) => (bases, false),
+                    None => (breakend_str.strip_suffix('.').ok_or_else(invalid)?, true),
+                };
+                if !is_bases(bases) {
+                    return Err(invalid());
+                }
+                return Ok(Breakend {
+                    bases: bases.to_string(),
+                    bases_first,
+                    mate: None,
+                });
+            }
+        };
+        let parts: Vec<&str> = breakend_str.split(bracket).collect();
+        let (bases, mate_str) = match parts.as_slice() {
+            [bases, mate, ""] if is_bases(bases) => (*bases, *mate),
+            ["", mate, bases] if is_bases(bases) => (*bases, *mate),
+            _ => return Err(invalid()),
+        };
+        // the mate always has a position, so contig names containing `:`, e.g.,
+        // `HLA-A*01:01:01:01`, end at the last `:`, unless they are in angle brackets
+        let (chromosome, position) = if mate_str.starts_with('<') {
+            let index = mate_str.find('>').ok_or_else(invalid)?;
+            let (chromosome, position) = mate_str.split_at(index + 1);
+            (chromosome, position.strip_prefix(':').ok_or_else(invalid)?)
+        } else {
+            mate_str.rsplit_once(':').ok_or_else(invalid)?
+        };
+        if chromosome.is_empty()
+            || position.is_empty()
+            || !position.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let mate = Mate {
+            chromosome: chromosome.to_string(),
+            position: position.parse().map_err(|_| invalid())?,
+            extends_right: bracket == '[',
+        };
+        Ok(Breakend {
+            bases: bases.to_string(),
+            bases_first: parts[0] == bases,
+            mate: Some(mate),
+        })
+    }
+}
+
+impl Display for Breakend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let mate = match &self.mate {
+            Some(mate) => mate,
+            None if self.bases_first => return write!(f, "{}.", self.bases),
+            None => return write!(f, ".{}", self.bases),
+        };
+        let bracket = if mate.extends_right { '[' } else { ']' };
+        let mate = format!(
+            "{}{}:{}{}",
+            bracket, mate.chromosome, mate.position, bracket
+        );
+        if self.bases_first {
+            write!(f, "{}{}", self.bases, mate)
+        } else {
+            write!(f, "{}{}", mate, self.bases)
+        }
+    }
+}
+
+impl DataLine {
+    /// The breakend alleles of the data line, in order, skipping any other allele.
+    pub fn breakends(&self) -> anyhow::Result<Vec<Breakend>> {
+        let alts = match &self.alternative {
+            AltType::Entries(alts) => alts,
+            AltType::Missing => return Ok(vec![]),
+        };
+        alts.iter()
+            .filter(|alt| VariantType::new(&self.reference, alt) == VariantType::Breakend)
+            .map(|alt| alt.parse())
+            .collect()
+    }
+}
+
+/// The breakend data lines of a set, by index, paired with their mates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatePairs {
+    /// Pairs of mates, each once, with the first index the smaller.
+    pub pairs: Vec<(usize, usize)>,
+
+    /// Breakend data lines without a mate in the set.
+    pub unpaired: Vec<usize>,
+}
+
+/// Pairs breakend data lines, e.g., of a chromosome or a whole file, with their mates. Mates are
+/// found by ID through `INFO/MATEID` or, for data lines without it, as the only two breakends with
+/// the same `INFO/EVENT`.
+pub fn pair_mates(lines: &[DataLine]) -> MatePairs {
+    let breakends: Vec<usize> = (0..lines.len())
+        .filter(|i| lines[*i].variant_types().contains(&VariantType::Breakend))
+        .collect();
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    for i in &breakends {
+        if let IdType::Entries(entries) = &lines[*i].id {
+            for id in entries {
+                ids.insert(id, *i);
+            }
+        }
+    }
+
+    let mut pairs: HashSet<(usize, usize)> = HashSet::new();
+    let mut events: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for i in &breakends {
+        let dl = &lines[*i];
+        // IDs are valid UTF-8, so a mate ID that is not cannot match any
+        match dl.info_bytes("MATEID") {
+            Some(mate_ids) => {
+                for mate_id in mate_ids.split(|b| *b == b',') {
+                    let mate = std::str::from_utf8(mate_id).ok().and_then(|id| ids.get(id));
+                    if let Some(j) = mate.filter(|j| *j != i) {
+                        pairs.insert((*i.min(j), *i.max(j)));
+                    }
+                }
+            }
+            None => {
+                if let Some(event) = dl.info_bytes("EVENT") {
+                    events.entry(event).or_default().push(*i);
+                }
+            }
+        }
+    }
+    for members in events.values() {
+        if let [i, j] = members.as_slice() {
+            pairs.insert((*i, *j));
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    let mut paired = vec![false; lines.len()];
+    for (i, j) in &pairs {
+        paired[*i] = true;
+        paired[*j] = true;
+    }
+    let unpaired = breakends.into_iter().filter(|i| !paired[*i]).collect();
+    MatePairs { pairs, unpaired }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{breakend::*, parser::VCFParser};
+
+    #[test]
+    fn test_breakend() {
+        let breakend = Breakend::from_str("G]17:198982]").unwrap();
+        assert_eq!(breakend.orientation(), Some(Orientation::AfterLeft));
+        let mate = breakend.mate.as_ref().unwrap();
+        assert_eq!((mate.chromosome.as_str(), mate.position), ("17", 198982));
+        assert_eq!(breakend.inserted_sequence(), "");
+
+        let orientations = [
+            ("GTA[2:10[", Orientation::AfterRight, "TA"),
+            (
+                "]13:123456]AGTNNNNNCAT",
+                Orientation::BeforeLeft,
+                "AGTNNNNNCA",
+            ),
+            ("[<ctg1>:7[T", Orientation::BeforeRight, ""),
+            ("G]HLA-A*01:01:01:01:25]", Orientation::AfterLeft, ""),
+            ("[<HLA:1>:7[T", Orientation::BeforeRight, ""),
+        ];
+        for (breakend_str, orientation, inserted) in &orientations {
+            let breakend = Breakend::from_str(breakend_str).unwrap();
+            let mate = breakend.mate.as_ref().unwrap();
+            assert!(breakend_str.contains(&format!("{}:{}", mate.chromosome, mate.position)));
+            assert_eq!(breakend.orientation(), Some(*orientation));
+            assert_eq!(breakend.inserted_sequence(), *inserted);
+            assert_eq!(breakend.to_string(), *breakend_str);
+        }
+
+        let single = Breakend::from_str(".A").unwrap();
+        assert!(single.is_single() && !single.bases_first);
+        assert_eq!(single.orientation(), None);
+        assert_eq!(Breakend::from_str("CT.").unwrap().to_string(), "CT.");
+
+        for invalid in &[
+            "G",
+            ".",
+            "G[17:5]",
+            "G[17:5[A",
+            "[17:x[A",
+            "G[:5[",
+            "..A",
+            "é[2:10[",
+            "G*[2:10[",
+            "G[17:+5[",
+            "[<ctg1:7[T",
+            "[<ctg1>7[T",
+        ] {
+            assert!(Breakend::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_pair_mates() {
+        let vcf = "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+2\t321681\tbnd_W\tG\tG]17:198982]\t6\tPASS\tSVTYPE=BND;MATEID=bnd_Y
+2\t321682\tbnd_V\tT\t]13:123456]T\t6\tPASS\tSVTYPE=BND;EVENT=INV0
+2\t400000\t.\tA\tG\t6\tPASS\t.
+13\t123456\tbnd_U\tC\tC[2:321682[\t6\tPASS\tSVTYPE=BND;EVENT=INV0
+13\t123457\tbnd_X\tA\t[17:198983[A\t6\tPASS\tSVTYPE=BND;MATEID=bnd_Z
+17\t198982\tbnd_Y\tA\tA]2:321681]\t6\tPASS\tSVTYPE=BND;MATEID=bnd_W
+17\t198983\tbnd_Z\tC\t[13:123457[C\t6\tPASS\tSVTYPE=BND;MATEID=bnd_X
+17\t200000\t.\tG\tG.\t6\tPASS\tSVTYPE=BND";
+        let lines: Vec<DataLine> = VCFParser::new(vcf.as_bytes())
+            .unwrap()
+            .reader
+            .into_iter()
+            .map(|dl| dl.unwrap())
+            .collect();
+        assert_eq!(lines[0].breakends().unwrap().len(), 1);
+        assert!(lines[2].breakends().unwrap().is_empty());
+
+        let mate_pairs = pair_mates(&lines);
+        assert_eq!(mate_pairs.pairs, vec![(0, 5), (1, 3), (4, 6)]);
+        assert_eq!(mate_pairs.unpaired, vec![7]);
+    }
+}
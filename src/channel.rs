@@ -0,0 +1,306 @@
+use crate::crypto::{open, random_bytes, seal, Key};
+use std::{
+    convert::TryInto,
+    io,
+    io::{Read, Write},
+};
+
+/// The maximum number of plaintext bytes in a frame.
+pub const FRAME_LEN: usize = 1 << 16;
+
+/// The maximum number of bytes a cipher may add to a frame. Longer frames are rejected before
+/// they are read, so a hostile host cannot make a reader allocate more than a frame.
+pub const MAX_FRAME_OVERHEAD: usize = 64;
+
+/// Length in bytes of the random id every stream starts with.
+pub const STREAM_ID_LEN: usize = 16;
+
+/// Protects the frames of a byte channel with an untrusted host, e.g., with an enclave sealing
+/// key or a session key negotiated with the data owner.
+pub trait ChannelCipher {
+    fn seal_frame(&self, frame: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    fn open_frame(&self, sealed: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Seals frames with AES-256-GCM.
+impl ChannelCipher for Key {
+    fn seal_frame(&self, frame: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+        seal(self, frame, aad)
+    }
+
+    fn open_frame(&self, sealed: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+        open(self, sealed, aad)
+    }
+}
+
+/// Leaves frames unprotected, for trusted channels that still need bounded reads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plaintext;
+
+impl ChannelCipher for Plaintext {
+    fn seal_frame(&self, frame: &[u8], _aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(frame.to_vec())
+    }
+
+    fn open_frame(&self, sealed: &[u8], _aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(sealed.to_vec())
+    }
+}
+
+/// A writer sending everything written to it as sealed frames of at most `FRAME_LEN` bytes. The
+/// stream starts with a random id of `STREAM_ID_LEN` bytes. Each frame is its sealed length, as a
+/// little endian `u32`, whether it is the last frame, as a byte, and the sealed bytes. The stream
+/// id, the index of every frame and whether it is the last one are authenticated, so frames
+/// cannot be reordered, dropped, truncated or moved between streams sealed with the same key
+/// without detection. A final empty frame marks the end.
+pub struct ChannelWriter<W: Write, C: ChannelCipher> {
+    writer: Option<W>,
+    cipher: C,
+    stream_id: [u8; STREAM_ID_LEN],
+    buffer: Vec<u8>,
+    frame_index: u64,
+}
+
+impl<W: Write, C: ChannelCipher> ChannelWriter<W, C> {
+    pub fn new(writer: W, cipher: C) -> Self {
+        ChannelWriter {
+            writer: Some(writer),
+            cipher,
+            stream_id: random_bytes(),
+            buffer: Vec::with_capacity(FRAME_LEN),
+            frame_index: 0,
+        }
+    }
+
+    /// Sends any buffered bytes, followed by the final empty frame, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_last_frame()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_frame(&mut self, last: bool) -> io::Result<()> {
+        if self.buffer.is_empty() && !last {
+            return Ok(());
+        }
+        let aad = frame_associated_data(&self.stream_id, self.frame_index, last);
+        let sealed = self
+            .cipher
+            .seal_frame(&self.buffer, &aad)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if let Some(writer) = self.writer.as_mut() {
+            if self.frame_index == 0 {
+                writer.write_all(&self.stream_id)?;
+            }
+            writer.write_all(&(sealed.len() as u32).to_le_bytes())?;
+            writer.write_all(&[last as u8])?;
+            writer.write_all(&sealed)?;
+        }
+        self.buffer.clear();
+        self.frame_index += 1;
+        Ok(())
+    }
+
+    fn write_last_frame(&mut self) -> io::Result<()> {
+        self.write_frame(false)?;
+        self.write_frame(true)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, C: ChannelCipher> Write for ChannelWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(FRAME_LEN - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == FRAME_LEN {
+            self.write_frame(false)?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_frame(false)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, C: ChannelCipher> Drop for ChannelWriter<W, C> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_last_frame();
+        }
+    }
+}
+
+/// A reader opening the frames written by `ChannelWriter`. Frames are read one at a time and
+/// frames longer than `FRAME_LEN` plus `MAX_FRAME_OVERHEAD` bytes are rejected, so memory use is
+/// bounded whatever the host sends. A stream ending before its last frame is an error.
+pub struct ChannelReader<R: Read, C: ChannelCipher> {
+    reader: R,
+    cipher: C,
+    /// The id of the stream, read along with the first frame.
+    stream_id: [u8; STREAM_ID_LEN],
+    frame: Vec<u8>,
+    position: usize,
+    frame_index: u64,
+    finished: bool,
+}
+
+impl<R: Read, C: ChannelCipher> ChannelReader<R, C> {
+    pub fn new(reader: R, cipher: C) -> Self {
+        ChannelReader {
+            reader,
+            cipher,
+            stream_id: [0; STREAM_ID_LEN],
+            frame: vec![],
+            position: 0,
+            frame_index: 0,
+            finished: false,
+        }
+    }
+
+    fn read_frame(&mut self) -> io::Result<()> {
+        if self.frame_index == 0 {
+            self.reader
+                .read_exact(&mut self.stream_id)
+                .map_err(|_| invalid_data("truncated channel, (missing stream id)"))?;
+        }
+        let mut frame_header = [0u8; 5];
+        self.reader
+            .read_exact(&mut frame_header)
+            .map_err(|_| invalid_data("truncated channel, (missing last frame)"))?;
+        let sealed_len = u32::from_le_bytes(frame_header[..4].try_into().unwrap()) as usize;
+        if sealed_len > FRAME_LEN + MAX_FRAME_OVERHEAD {
+            return Err(invalid_data("invalid channel frame, (frame too large)"));
+        }
+        let last = frame_header[4] != 0;
+        let mut sealed = vec![0u8; sealed_len];
+        self.reader
+            .read_exact(&mut sealed)
+            .map_err(|_| invalid_data("truncated channel, (truncated frame)"))?;
+        self.frame = self
+            .cipher
+            .open_frame(
+                &sealed,
+                &frame_associated_data(&self.stream_id, self.frame_index, last),
+            )
+            .map_err(|_| invalid_data("invalid channel frame, (authentication failed)"))?;
+        self.finished = last;
+        if self.frame.len() > FRAME_LEN {
+            return Err(invalid_data("invalid channel frame, (frame too large)"));
+        }
+        self.position = 0;
+        self.frame_index += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read, C: ChannelCipher> Read for ChannelReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.frame.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.read_frame()?;
+        }
+        let len = buf.len().min(self.frame.len() - self.position);
+        buf[..len].copy_from_slice(&self.frame[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// The associated data of a frame: the id of its stream, its index, as a little endian `u64`, and
+/// whether it is the last one.
+fn frame_associated_data(stream_id: &[u8], index: u64, last: bool) -> Vec<u8> {
+    let mut aad = stream_id.to_vec();
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad.push(last as u8);
+    aad
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        channel::*,
+        parser::{Limits, Utf8Mode, VCFParser, VCFWriter},
+        test_util::{read, SMALL_VCF},
+    };
+    use std::io::BufReader;
+
+    #[test]
+    fn test_roundtrip() {
+        let vcf = read(SMALL_VCF);
+        let key = Key::generate();
+        let mut writer = ChannelWriter::new(vec![], key.clone());
+        writer.write_all(vcf.as_bytes()).unwrap();
+        let sealed = writer.finish().unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("rs6054257"));
+
+        let reader = BufReader::new(ChannelReader::new(sealed.as_slice(), key.clone()));
+        let parser = VCFParser::from_source(reader, Limits::default(), Utf8Mode::Strict).unwrap();
+        let mut writer =
+            VCFWriter::new(ChannelWriter::new(vec![], key.clone()), &parser.header).unwrap();
+        for dl in parser.reader {
+            writer.write_data_line(&dl.unwrap()).unwrap();
+        }
+        let sealed = writer.finish().unwrap().finish().unwrap();
+        let mut output = String::new();
+        ChannelReader::new(sealed.as_slice(), key)
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, vcf);
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = Key::generate();
+        let mut writer = ChannelWriter::new(vec![], key.clone());
+        writer.write_all(&[b'A'; FRAME_LEN + 10]).unwrap();
+        let sealed = writer.finish().unwrap();
+
+        // truncated after the first frame
+        let first_frame_len = STREAM_ID_LEN + 5 + FRAME_LEN + 28;
+        let mut output = vec![];
+        assert!(ChannelReader::new(&sealed[..first_frame_len], key.clone())
+            .read_to_end(&mut output)
+            .is_err());
+
+        // the last flag is authenticated
+        let mut tampered = sealed[..first_frame_len].to_vec();
+        tampered[STREAM_ID_LEN + 4] = 1;
+        assert!(ChannelReader::new(tampered.as_slice(), key.clone())
+            .read_to_end(&mut output)
+            .is_err());
+
+        // a frame of another stream sealed with the same key
+        let mut writer = ChannelWriter::new(vec![], key.clone());
+        writer.write_all(&[b'A'; FRAME_LEN + 10]).unwrap();
+        let other = writer.finish().unwrap();
+        assert_ne!(other[..STREAM_ID_LEN], sealed[..STREAM_ID_LEN]);
+        let mut spliced = sealed[..first_frame_len].to_vec();
+        spliced.extend_from_slice(&other[first_frame_len..]);
+        assert!(ChannelReader::new(spliced.as_slice(), key)
+            .read_to_end(&mut output)
+            .is_err());
+
+        // a frame longer than the limit is rejected before it is read
+        let mut giant = [0u8; STREAM_ID_LEN].to_vec();
+        giant.extend_from_slice(&(u32::MAX).to_le_bytes());
+        giant.push(0);
+        assert!(ChannelReader::new(giant.as_slice(), Plaintext)
+            .read_to_end(&mut output)
+            .is_err());
+    }
+}
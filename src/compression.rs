@@ -1,26 +1,202 @@
 use std::io::prelude::*;
 
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression, Crc,
+};
+use std::{convert::TryInto, io};
+
+/// Maximum number of uncompressed bytes stored in a single BGZF block.
+pub const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file.
+pub const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
 
 /// Encodes given bytes using the gzip format.
 pub fn gz_encode(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
     let mut e = GzEncoder::new(Vec::new(), Compression::default());
-    e.write_all(&bytes[..])?;
+    e.write_all(bytes)?;
     Ok(e.finish()?)
 }
 
 /// Decodes given bytes using the gzip format.
 pub fn gz_decode(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let mut gz = GzDecoder::new(&bytes[..]);
+    let mut gz = GzDecoder::new(bytes);
+    let mut result = Vec::new();
+    gz.read_to_end(&mut result)?;
+    Ok(result)
+}
+
+/// Decodes given bytes using the gzip format, failing if they decode to more than `limit` bytes,
+/// so that untrusted input cannot make the decoder allocate more.
+pub fn gz_decode_limited(bytes: &[u8], limit: usize) -> anyhow::Result<Vec<u8>> {
+    let mut gz = GzDecoder::new(bytes).take(limit as u64 + 1);
     let mut result = Vec::new();
     gz.read_to_end(&mut result)?;
+    if result.len() > limit {
+        return Err(anyhow::anyhow!(
+            "gzip data too large, expected at most {} bytes",
+            limit
+        ));
+    }
     Ok(result)
 }
 
+/// Encodes given bytes as a single BGZF block, i.e., a gzip member carrying the `BC` extra
+/// subfield with the total block size. At most `BGZF_BLOCK_SIZE` bytes can be encoded at once.
+pub fn bgzf_encode_block(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    if bytes.len() > BGZF_BLOCK_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "BGZF block too large, expected at most {} bytes, found {}",
+                BGZF_BLOCK_SIZE,
+                bytes.len()
+            ),
+        ));
+    }
+
+    let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+    e.write_all(bytes)?;
+    let compressed = e.finish()?;
+
+    let mut crc = Crc::new();
+    crc.update(bytes);
+
+    // header (18 bytes) + compressed data + crc32 (4 bytes) + input size (4 bytes)
+    let block_size = 18 + compressed.len() + 8;
+    let mut block = Vec::with_capacity(block_size);
+    block.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0x00]);
+    block.extend_from_slice(b"BC");
+    block.extend_from_slice(&2u16.to_le_bytes());
+    block.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+    block.extend_from_slice(&compressed);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+/// Reads and decodes the next BGZF block, returning its uncompressed bytes and its compressed
+/// size, or `None` at the end of the input.
+pub fn bgzf_decode_block<R: Read>(reader: &mut R) -> io::Result<Option<(Vec<u8>, usize)>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut header = [0u8; 12];
+    match reader.read(&mut header[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut header[1..])?,
+    }
+    if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+        return Err(invalid(
+            "invalid BGZF block, (not a gzip member with extra fields)",
+        ));
+    }
+    let mut extra = vec![0u8; u16::from_le_bytes([header[10], header[11]]) as usize];
+    reader.read_exact(&mut extra)?;
+    let mut block_size = None;
+    let mut subfields = extra.as_slice();
+    while subfields.len() >= 4 {
+        let len = u16::from_le_bytes([subfields[2], subfields[3]]) as usize;
+        if subfields[..2] == *b"BC" && len == 2 && subfields.len() >= 6 {
+            block_size = Some(u16::from_le_bytes([subfields[4], subfields[5]]) as usize + 1);
+        }
+        subfields = &subfields[(4 + len).min(subfields.len())..];
+    }
+    let block_size = block_size.ok_or_else(|| invalid("invalid BGZF block, (no BC field)"))?;
+    let remaining = block_size
+        .checked_sub(12 + extra.len())
+        .filter(|r| *r >= 8)
+        .ok_or_else(|| invalid("invalid BGZF block, (block too small)"))?;
+    let mut compressed = vec![0u8; remaining];
+    reader.read_exact(&mut compressed)?;
+    let (compressed, trailer) = compressed.split_at(remaining - 8);
+    let mut bytes = vec![];
+    DeflateDecoder::new(compressed).read_to_end(&mut bytes)?;
+    if bytes.len() != u32::from_le_bytes(trailer[4..].try_into().unwrap()) as usize {
+        return Err(invalid("invalid BGZF block, (size mismatch)"));
+    }
+    Ok(Some((bytes, block_size)))
+}
+
+/// A writer compressing everything written to it using the BGZF format, a series of gzip
+/// members that can be decompressed independently. The output is also a valid gzip file.
+pub struct BgzfWriter<W: Write> {
+    writer: Option<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BgzfWriter {
+            writer: Some(writer),
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+
+    /// Compresses and writes any buffered bytes, followed by the BGZF end of file marker, and
+    /// returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_eof()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let block = bgzf_encode_block(&self.buffer)?;
+        self.buffer.clear();
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&block)?;
+        }
+        Ok(())
+    }
+
+    fn write_eof(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&BGZF_EOF)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_eof();
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use crate::compression::*;
+    use flate2::read::MultiGzDecoder;
 
     #[test]
     fn test_hello() {
@@ -35,6 +211,34 @@ mod test {
         let input = b"1234567890".repeat(1000).to_vec();
         let encoded = gz_encode(&input).unwrap();
         let decoded = gz_decode(&encoded).unwrap();
-        assert_eq!(input, decoded.as_slice())
+        assert_eq!(input, decoded.as_slice());
+        assert_eq!(gz_decode_limited(&encoded, input.len()).unwrap(), input);
+        assert!(gz_decode_limited(&encoded, input.len() - 1).is_err());
+    }
+
+    #[test]
+    fn test_bgzf() {
+        let input = b"1234567890".repeat(20000).to_vec();
+        let mut writer = BgzfWriter::new(vec![]);
+        writer.write_all(&input).unwrap();
+        let encoded = writer.finish().unwrap();
+        assert!(encoded.ends_with(&BGZF_EOF));
+
+        let mut decoded = vec![];
+        MultiGzDecoder::new(&encoded[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(input, decoded);
+
+        let mut reader = &encoded[..];
+        let mut blocks = vec![];
+        while let Some((bytes, size)) = bgzf_decode_block(&mut reader).unwrap() {
+            blocks.push((bytes.len(), size));
+        }
+        assert_eq!(blocks.len(), 5);
+        assert_eq!(blocks[0].0, BGZF_BLOCK_SIZE);
+        assert_eq!(blocks[4], (0, BGZF_EOF.len()));
+        assert_eq!(blocks.iter().map(|b| b.1).sum::<usize>(), encoded.len());
+        assert!(bgzf_decode_block(&mut &gz_encode(&input).unwrap()[..]).is_err());
     }
 }
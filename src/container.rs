@@ -0,0 +1,461 @@
+use crate::{
+    compression::{bgzf_encode_block, gz_decode_limited, BGZF_BLOCK_SIZE, BGZF_EOF},
+    crypto::{open, random_bytes, seal, Key, NONCE_LEN},
+};
+use std::{
+    convert::TryInto,
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+/// The bytes every encrypted container starts with.
+pub const CONTAINER_MAGIC: &[u8; 8] = b"VCFCRYPT";
+
+/// The version of the container format.
+pub const CONTAINER_VERSION: u8 = 2;
+
+/// Length in bytes of the random id of every container.
+const FILE_ID_LEN: usize = 16;
+
+/// Length in bytes of the container header: the magic, the version, the key id and the file id.
+const HEADER_LEN: usize = 8 + 1 + 16 + FILE_ID_LEN;
+
+/// Length in bytes of the header of every chunk: the sealed and plaintext lengths.
+const CHUNK_HEADER_LEN: usize = 8;
+
+/// The length of the smallest sealed chunk, the final empty one: an empty BGZF block, a nonce and
+/// a tag. Shorter chunks are rejected when the container is opened, so that the chunk table built
+/// from the unauthenticated chunk headers stays smaller than the container.
+const MIN_SEALED_LEN: usize = BGZF_EOF.len() + NONCE_LEN + 16;
+
+/// The maximum number of bytes compression and sealing may add to a chunk: a BGZF block is at
+/// most 64 KiB and the cipher adds a nonce and a tag. Longer chunks are rejected before they are
+/// read, so a hostile host cannot make a reader allocate more than a chunk.
+const MAX_CHUNK_OVERHEAD: usize = 0x10000 - BGZF_BLOCK_SIZE + 64;
+
+/// A writer storing everything written to it in an encrypted container. The plaintext is split
+/// in chunks of at most `BGZF_BLOCK_SIZE` bytes, and every chunk is compressed as a BGZF block
+/// and then sealed with AES-256-GCM. Each chunk is stored as its sealed length and plaintext
+/// length, as little endian `u32`s, followed by the sealed block. The random id of the container,
+/// the index of every chunk and whether it is the last one are authenticated, so chunks cannot be
+/// reordered, dropped, truncated or moved between containers encrypted with the same key without
+/// detection. A final empty chunk marks the end of the container.
+pub struct EncryptedWriter<W: Write> {
+    writer: Option<W>,
+    key: Key,
+    header: Vec<u8>,
+    buffer: Vec<u8>,
+    chunk_index: u64,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    /// Creates a container encrypted with the given key and writes its header.
+    pub fn new(mut writer: W, key: Key) -> io::Result<Self> {
+        let header = container_header(&key);
+        writer.write_all(&header)?;
+        Ok(EncryptedWriter {
+            writer: Some(writer),
+            key,
+            header,
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            chunk_index: 0,
+        })
+    }
+
+    /// Encrypts and writes any buffered bytes, followed by the final empty chunk, and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_last_chunk()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        if self.buffer.is_empty() && !last {
+            return Ok(());
+        }
+        let block = bgzf_encode_block(&self.buffer)?;
+        let aad = chunk_associated_data(
+            &self.header,
+            self.chunk_index,
+            self.buffer.len() as u32,
+            last,
+        );
+        let sealed = seal(&self.key, &block, &aad).map_err(invalid_data)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&(sealed.len() as u32).to_le_bytes())?;
+            writer.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+            writer.write_all(&sealed)?;
+        }
+        self.buffer.clear();
+        self.chunk_index += 1;
+        Ok(())
+    }
+
+    fn write_last_chunk(&mut self) -> io::Result<()> {
+        self.write_chunk(false)?;
+        self.write_chunk(true)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_chunk(false)?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk(false)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for EncryptedWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_last_chunk();
+        }
+    }
+}
+
+/// The location of a chunk in a container.
+#[derive(Debug, Clone)]
+struct Chunk {
+    /// Offset of the sealed block in the container.
+    offset: u64,
+    sealed_len: u32,
+    /// Offset of the first plaintext byte of the chunk.
+    start: u64,
+    len: u32,
+}
+
+/// A reader decrypting a container written by `EncryptedWriter`. Only the chunk headers are read
+/// when opening the container, and chunks are decrypted when read, so seeking to a position only
+/// decrypts the chunk containing it. Positions are offsets in the plaintext, e.g., the offsets of
+/// data lines recorded in an index.
+pub struct EncryptedReader<R: Read + Seek> {
+    reader: R,
+    key: Key,
+    header: Vec<u8>,
+    chunks: Vec<Chunk>,
+    len: u64,
+    position: u64,
+    /// The index and plaintext of the last decrypted chunk.
+    current: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> EncryptedReader<R> {
+    /// Opens a container encrypted with the given key. The last chunk is authenticated, so a
+    /// truncated container is rejected.
+    pub fn new(mut reader: R, key: Key) -> anyhow::Result<Self> {
+        let mut header = vec![0u8; HEADER_LEN];
+        reader.seek(SeekFrom::Start(0))?;
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| anyhow::anyhow!("invalid encrypted container, (header too short)"))?;
+        if &header[..8] != CONTAINER_MAGIC {
+            return Err(anyhow::anyhow!(
+                "invalid encrypted container, (wrong magic)"
+            ));
+        }
+        if header[8] != CONTAINER_VERSION {
+            return Err(anyhow::anyhow!(
+                "unsupported encrypted container version `{}`",
+                header[8]
+            ));
+        }
+        let key_id = String::from_utf8_lossy(&header[9..HEADER_LEN - FILE_ID_LEN]).to_string();
+        if key_id != key.id() {
+            return Err(anyhow::anyhow!(
+                "container is encrypted with key `{}`, not with the given key `{}`",
+                key_id,
+                key.id()
+            ));
+        }
+
+        let end = reader.seek(SeekFrom::End(0))?;
+        let mut offset = HEADER_LEN as u64;
+        let mut start = 0;
+        let mut chunks = vec![];
+        while offset < end {
+            let mut chunk_header = [0u8; CHUNK_HEADER_LEN];
+            reader.seek(SeekFrom::Start(offset))?;
+            reader
+                .read_exact(&mut chunk_header)
+                .map_err(|_| anyhow::anyhow!("invalid encrypted container, (truncated chunk)"))?;
+            let sealed_len = u32::from_le_bytes(chunk_header[..4].try_into().unwrap());
+            let len = u32::from_le_bytes(chunk_header[4..].try_into().unwrap());
+            if (sealed_len as usize) < MIN_SEALED_LEN {
+                return Err(anyhow::anyhow!(
+                    "invalid encrypted container, (chunk {} too small)",
+                    chunks.len()
+                ));
+            }
+            if len as usize > BGZF_BLOCK_SIZE {
+                return Err(anyhow::anyhow!(
+                    "invalid encrypted container, (chunk too large)"
+                ));
+            }
+            chunks.push(Chunk {
+                offset: offset + CHUNK_HEADER_LEN as u64,
+                sealed_len,
+                start,
+                len,
+            });
+            offset += CHUNK_HEADER_LEN as u64 + sealed_len as u64;
+            start += len as u64;
+        }
+        if offset != end {
+            return Err(anyhow::anyhow!(
+                "invalid encrypted container, (truncated chunk)"
+            ));
+        }
+
+        let mut encrypted_reader = EncryptedReader {
+            reader,
+            key,
+            header,
+            chunks,
+            len: start,
+            position: 0,
+            current: None,
+        };
+        match encrypted_reader.chunks.len() {
+            0 => Err(anyhow::anyhow!("invalid encrypted container, (truncated)")),
+            n => {
+                encrypted_reader.load_chunk(n - 1)?;
+                Ok(encrypted_reader)
+            }
+        }
+    }
+
+    /// The length of the plaintext.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decrypts and decompresses the chunk with the given index, unless it is already loaded.
+    fn load_chunk(&mut self, index: usize) -> io::Result<()> {
+        if matches!(self.current, Some((current, _)) if current == index) {
+            return Ok(());
+        }
+        let chunk = self.chunks[index].clone();
+        if chunk.sealed_len as usize > BGZF_BLOCK_SIZE + MAX_CHUNK_OVERHEAD {
+            return Err(invalid_data(anyhow::anyhow!(
+                "invalid encrypted container, (chunk {} too large)",
+                index
+            )));
+        }
+        let mut sealed = vec![0u8; chunk.sealed_len as usize];
+        self.reader.seek(SeekFrom::Start(chunk.offset))?;
+        self.reader.read_exact(&mut sealed)?;
+        let last = index == self.chunks.len() - 1;
+        let aad = chunk_associated_data(&self.header, index as u64, chunk.len, last);
+        let block = open(&self.key, &sealed, &aad).map_err(|e| {
+            invalid_data(anyhow::anyhow!("could not decrypt chunk {}: {}", index, e))
+        })?;
+        let plaintext = gz_decode_limited(&block, BGZF_BLOCK_SIZE).map_err(invalid_data)?;
+        if plaintext.len() != chunk.len as usize {
+            return Err(invalid_data(anyhow::anyhow!(
+                "invalid length of chunk {}",
+                index
+            )));
+        }
+        self.current = Some((index, plaintext));
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for EncryptedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        // the chunk containing the position, skipping empty chunks
+        let index = self
+            .chunks
+            .partition_point(|chunk| chunk.start + chunk.len as u64 <= self.position);
+        self.load_chunk(index)?;
+        let start = (self.position - self.chunks[index].start) as usize;
+        let plaintext = &self.current.as_ref().unwrap().1;
+        let len = buf.len().min(plaintext.len() - start);
+        buf[..len].copy_from_slice(&plaintext[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for EncryptedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// The header of a new container, with a random file id.
+fn container_header(key: &Key) -> Vec<u8> {
+    let mut header = CONTAINER_MAGIC.to_vec();
+    header.push(CONTAINER_VERSION);
+    header.extend_from_slice(key.id().as_bytes());
+    header.extend_from_slice(&random_bytes::<FILE_ID_LEN>());
+    header
+}
+
+/// The data authenticated along with every chunk. It binds the chunk to the container header,
+/// including the file id, its position in the container, its plaintext length and whether it is the last chunk.
+fn chunk_associated_data(header: &[u8], index: u64, len: u32, last: bool) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad.extend_from_slice(&len.to_le_bytes());
+    aad.push(last as u8);
+    aad
+}
+
+fn invalid_data<E: Into<anyhow::Error>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.into().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        container::*,
+        parser::VCFParser,
+        test_util::{read, SMALL_VCF},
+    };
+    use std::io::Cursor;
+
+    fn encrypt(key: &Key, plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptedWriter::new(vec![], key.clone()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let key = Key::generate();
+        let input = b"1234567890".repeat(20000);
+        let encrypted = encrypt(&key, &input);
+        assert!(encrypted.starts_with(CONTAINER_MAGIC));
+
+        let mut reader = EncryptedReader::new(Cursor::new(&encrypted), key.clone()).unwrap();
+        assert_eq!(reader.len(), input.len() as u64);
+        let mut decrypted = vec![];
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, input);
+
+        // random access across chunk boundaries
+        let mut buf = [0u8; 10];
+        reader
+            .seek(SeekFrom::Start(BGZF_BLOCK_SIZE as u64 - 5))
+            .unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &input[BGZF_BLOCK_SIZE - 5..BGZF_BLOCK_SIZE + 5]);
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+
+        assert!(EncryptedReader::new(Cursor::new(&encrypted), Key::generate()).is_err());
+        let empty = encrypt(&key, b"");
+        assert!(EncryptedReader::new(Cursor::new(&empty), key)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_region_query() {
+        let vcf = read(SMALL_VCF);
+        let key = Key::generate();
+        let encrypted = encrypt(&key, vcf.as_bytes());
+        let offset = vcf.find("20\t17330").unwrap() as u64;
+
+        let reader = EncryptedReader::new(Cursor::new(encrypted), key).unwrap();
+        let mut parser = VCFParser::new(reader).unwrap();
+        assert_eq!(
+            parser.header.column_names,
+            vec!["NA00001", "NA00002", "NA00003"]
+        );
+        parser.reader.reader.seek(SeekFrom::Start(offset)).unwrap();
+        let positions: Vec<u64> = parser
+            .reader
+            .iter()
+            .map(|dl| dl.unwrap().position)
+            .collect();
+        assert_eq!(positions, vec![17330, 1110696, 1230237, 1234567]);
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = Key::generate();
+        let input = b"1234567890".repeat(20000);
+        let encrypted = encrypt(&key, &input);
+
+        // modified ciphertext
+        let mut modified = encrypted.clone();
+        modified[HEADER_LEN + CHUNK_HEADER_LEN + 20] ^= 1;
+        let mut reader = EncryptedReader::new(Cursor::new(modified), key.clone()).unwrap();
+        assert!(reader.read_to_end(&mut vec![]).is_err());
+
+        // truncated after the first chunk, with the remaining chunks dropped
+        let first_len =
+            u32::from_le_bytes(encrypted[HEADER_LEN..HEADER_LEN + 4].try_into().unwrap()) as usize;
+        let truncated = &encrypted[..HEADER_LEN + CHUNK_HEADER_LEN + first_len];
+        assert!(EncryptedReader::new(Cursor::new(truncated), key.clone()).is_err());
+
+        // a chunk longer than any sealed BGZF block is rejected before it is read
+        let mut oversized = encrypted[..HEADER_LEN].to_vec();
+        let sealed_len = BGZF_BLOCK_SIZE + MAX_CHUNK_OVERHEAD + 1;
+        oversized.extend_from_slice(&(sealed_len as u32).to_le_bytes());
+        oversized.extend_from_slice(&10u32.to_le_bytes());
+        oversized.resize(oversized.len() + sealed_len, 0);
+        let error = EncryptedReader::new(Cursor::new(oversized), key.clone())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("chunk 0 too large"));
+
+        // chunk headers shorter than any sealed chunk are rejected before the table grows past
+        // the size of the container
+        let mut tiny = encrypted[..HEADER_LEN].to_vec();
+        for _ in 0..1000 {
+            tiny.extend_from_slice(&0u32.to_le_bytes());
+            tiny.extend_from_slice(&0u32.to_le_bytes());
+        }
+        let error = EncryptedReader::new(Cursor::new(tiny), key.clone())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("chunk 0 too small"));
+
+        // truncated in the middle of a chunk
+        let truncated = &encrypted[..encrypted.len() - 1];
+        assert!(EncryptedReader::new(Cursor::new(truncated), key.clone()).is_err());
+
+        // a chunk of another container encrypted with the same key
+        let other = encrypt(&key, &input);
+        assert_ne!(other[..HEADER_LEN], encrypted[..HEADER_LEN]);
+        let mut spliced = encrypted[..HEADER_LEN + CHUNK_HEADER_LEN + first_len].to_vec();
+        spliced.extend_from_slice(&other[HEADER_LEN + CHUNK_HEADER_LEN + first_len..]);
+        assert!(EncryptedReader::new(Cursor::new(&spliced), key).is_err());
+    }
+}
@@ -0,0 +1,607 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    aes::{cipher::BlockEncrypt, Aes256},
+    Aes256Gcm, Nonce,
+};
+use aes_gcm_siv::Aes256GcmSiv;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use linked_hash_map::LinkedHashMap;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{Debug, Error, Formatter},
+    str::FromStr,
+};
+
+/// The name of the cipher used by `seal` and `open`, as recorded in `##ENCRYPTED` header lines.
+pub const CIPHER: &str = "AES-256-GCM";
+
+/// Length in bytes of the nonce prepended to every ciphertext.
+pub const NONCE_LEN: usize = 12;
+
+/// The name of the format-preserving cipher used by `fpe_encrypt_number` and
+/// `fpe_decrypt_number`, as recorded in `##ENCRYPTED` header lines.
+pub const FPE_CIPHER: &str = "FF1-AES-256";
+
+/// The smallest number of digits for which FF1 has the one million possible values required by
+/// NIST SP 800-38G. Shorter numbers are encrypted too, but are easily guessed.
+pub const FPE_MIN_DIGITS: usize = 6;
+
+/// The largest number of digits encrypted by `fpe_encrypt_number`.
+pub const FPE_MAX_DIGITS: usize = 72;
+
+/// The name of the deterministic cipher used by `encrypt_value_deterministic` and
+/// `decrypt_value_deterministic`, as recorded in `##ENCRYPTED` header lines.
+pub const DETERMINISTIC_CIPHER: &str = "AES-256-GCM-SIV";
+
+/// Length in bytes of the random tweaks generated by `generate_tweak`.
+pub const TWEAK_LEN: usize = 16;
+
+/// A 256-bit secret key.
+#[derive(Clone, PartialEq)]
+pub struct Key {
+    bytes: [u8; 32],
+}
+
+impl Key {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Key { bytes }
+    }
+
+    /// Generates a new random key.
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&Aes256Gcm::generate_key(OsRng));
+        Key { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+
+    /// A short public identifier of the key, derived from its SHA-256 digest. It allows finding
+    /// the key needed to decrypt a file without revealing the key itself.
+    pub fn id(&self) -> String {
+        let digest = Sha256::digest(self.bytes);
+        hex_encode(&digest[..8])
+    }
+
+    /// Returns the key as a lowercase hex string. The inverse of `Key::from_str`.
+    pub fn to_hex(&self) -> String {
+        hex_encode(&self.bytes)
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(hex_str: &str) -> anyhow::Result<Self> {
+        let hex_str = hex_str.trim();
+        if hex_str.len() != 64 || !hex_str.is_ascii() {
+            return Err(anyhow::anyhow!(
+                "invalid key, expected 64 hex characters, found {}",
+                hex_str.len()
+            ));
+        }
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(
+            &hex_decode(hex_str)
+                .map_err(|_| anyhow::anyhow!("invalid key, (non hex character found)"))?,
+        );
+        Ok(Key { bytes })
+    }
+}
+
+/// Keys are never printed.
+impl Debug for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Key(..)")
+    }
+}
+
+/// The keys of the samples of a file, by sample name.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    keys: LinkedHashMap<String, Key>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Keyring::default()
+    }
+
+    /// Adds the key of a sample, returning its previous key, if any.
+    pub fn insert(&mut self, sample: String, key: Key) -> Option<Key> {
+        self.keys.insert(sample, key)
+    }
+
+    pub fn get(&self, sample: &str) -> Option<&Key> {
+        self.keys.get(sample)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl FromStr for Keyring {
+    type Err = anyhow::Error;
+
+    /// Parses one sample per line, its name followed by whitespace and its key in hex. Empty
+    /// lines and lines starting with `#` are ignored.
+    fn from_str(keyring_str: &str) -> anyhow::Result<Self> {
+        let mut keyring = Keyring::new();
+        for line in keyring_str.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (sample, key) = line
+                .rsplit_once(|c: char| c.is_whitespace())
+                .ok_or_else(|| anyhow::anyhow!("invalid keyring line `{}`", line))?;
+            if keyring
+                .insert(sample.trim().to_string(), key.parse()?)
+                .is_some()
+            {
+                return Err(anyhow::anyhow!(
+                    "duplicate sample `{}` in keyring",
+                    sample.trim()
+                ));
+            }
+        }
+        Ok(keyring)
+    }
+}
+
+/// Encrypts the given plaintext using AES-256-GCM with a random nonce. The associated data is
+/// authenticated but not encrypted. Returns the nonce followed by the ciphertext and tag.
+pub fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(key.as_bytes().into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+    let mut result = nonce.to_vec();
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+/// Decrypts bytes produced by `seal`, failing if they or the associated data were modified.
+pub fn open(key: &Key, sealed: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow::anyhow!("invalid ciphertext, (too short)"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(key.as_bytes().into());
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("decryption failed, (authentication tag mismatch)"))
+}
+
+/// Encrypts a value of a VCF field. The result is base64 encoded using the URL safe alphabet
+/// without padding, so it contains none of the separators used in INFO, FORMAT or sample columns.
+pub fn encrypt_value(key: &Key, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+    Ok(URL_SAFE_NO_PAD.encode(seal(key, value.as_bytes(), aad)?))
+}
+
+/// Decrypts a value encrypted with `encrypt_value`.
+pub fn decrypt_value(key: &Key, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+    let sealed = URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| anyhow::anyhow!("invalid ciphertext `{}`, (not base64)", value))?;
+    Ok(String::from_utf8(open(key, &sealed, aad)?)?)
+}
+
+/// Encrypts a value of a VCF field deterministically, using AES-256-GCM-SIV with a fixed nonce and
+/// a subkey derived from the given key. Equal values and associated data always give equal
+/// ciphertexts, which allows looking up encrypted values, but also reveals which values are equal.
+/// The result is base64 encoded like the result of `encrypt_value`.
+pub fn encrypt_value_deterministic(key: &Key, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+    let cipher = Aes256GcmSiv::new(&deterministic_subkey(key).into());
+    let ciphertext = cipher
+        .encrypt(
+            &Default::default(),
+            Payload {
+                msg: value.as_bytes(),
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+    Ok(URL_SAFE_NO_PAD.encode(ciphertext))
+}
+
+/// Decrypts a value encrypted with `encrypt_value_deterministic`.
+pub fn decrypt_value_deterministic(key: &Key, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+    let ciphertext = URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| anyhow::anyhow!("invalid ciphertext `{}`, (not base64)", value))?;
+    let cipher = Aes256GcmSiv::new(&deterministic_subkey(key).into());
+    let plaintext = cipher
+        .decrypt(
+            &Default::default(),
+            Payload {
+                msg: &ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("decryption failed, (authentication tag mismatch)"))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// The key of the deterministic cipher, so that the same key is never used with both AES-GCM and
+/// AES-GCM-SIV.
+fn deterministic_subkey(key: &Key) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(DETERMINISTIC_CIPHER.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+/// Generates a random tweak for `fpe_encrypt_number`.
+pub fn generate_tweak() -> [u8; TWEAK_LEN] {
+    random_bytes()
+}
+
+/// Generates random bytes from the random number generator of the operating system, e.g., for
+/// the ids of files and streams.
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Encrypts the digits of a decimal number with FF1, the format-preserving cipher of NIST SP
+/// 800-38G, so that the result is a number of the same shape. Signs, the decimal point and the
+/// exponent are kept as they are, and the leading digit of a multi-digit integer part never
+/// becomes zero. Results out of the range of the number's type, e.g., integers above `i32::MAX`,
+/// are encrypted again until they are in range. The same key, tweak and number always give the
+/// same result, so every value should be encrypted with a distinct tweak. The result is not
+/// authenticated, and numbers of fewer than `FPE_MIN_DIGITS` digits have too few possible
+/// ciphertexts to be hidden well.
+pub fn fpe_encrypt_number(key: &Key, number: &str, tweak: &[u8]) -> anyhow::Result<String> {
+    fpe_cycle_walk(key, number, tweak, true)
+}
+
+/// Decrypts a number encrypted with `fpe_encrypt_number`.
+pub fn fpe_decrypt_number(key: &Key, number: &str, tweak: &[u8]) -> anyhow::Result<String> {
+    fpe_cycle_walk(key, number, tweak, false)
+}
+
+/// Encrypts the digits of a number with FF1 until the result is of the same shape and type, which
+/// terminates because FF1 is a permutation of the digit strings of the same length.
+fn fpe_cycle_walk(key: &Key, number: &str, tweak: &[u8], encrypt: bool) -> anyhow::Result<String> {
+    let mantissa_len = number.find(['e', 'E']).unwrap_or(number.len());
+    let integer_start = number[..mantissa_len]
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(0);
+    let integer_len = number[integer_start..mantissa_len]
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(mantissa_len - integer_start);
+    // an existing leading zero is kept, so it is never confused with an encrypted one
+    let keep_leading_zero = integer_len > 1 && number[integer_start..].starts_with('0');
+    let positions: Vec<usize> = number[..mantissa_len]
+        .char_indices()
+        .filter(|(index, c)| c.is_ascii_digit() && !(keep_leading_zero && *index == integer_start))
+        .map(|(index, _)| index)
+        .collect();
+    if positions.is_empty() {
+        return Ok(number.to_string());
+    }
+    if positions.len() > FPE_MAX_DIGITS {
+        return Err(anyhow::anyhow!(
+            "number `{}` has more than {} digits to encrypt",
+            number,
+            FPE_MAX_DIGITS
+        ));
+    }
+
+    let cipher = Aes256::new(&fpe_subkey(key).into());
+    let mut digits: Vec<u8> = positions
+        .iter()
+        .map(|index| number.as_bytes()[*index] - b'0')
+        .collect();
+    let mut result = number.as_bytes().to_vec();
+    loop {
+        digits = ff1(&cipher, &digits, tweak, encrypt);
+        for (index, digit) in positions.iter().zip(&digits) {
+            result[*index] = b'0' + digit;
+        }
+        let leading_zero = integer_len > 1 && result[integer_start] == b'0';
+        let result = String::from_utf8(result.clone())?;
+        if leading_zero == keep_leading_zero && number_type(&result) == number_type(number) {
+            return Ok(result);
+        }
+    }
+}
+
+/// Whether a number parses as an `i32` and as a finite `f32`, i.e., as a VCF Integer and Float.
+fn number_type(number: &str) -> (bool, bool) {
+    (
+        number.parse::<i32>().is_ok(),
+        number.parse::<f32>().is_ok_and(|f| f.is_finite()),
+    )
+}
+
+/// The key of the format-preserving cipher, so that the same key is never used with both AES-GCM
+/// and FF1.
+fn fpe_subkey(key: &Key) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(FPE_CIPHER.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+/// Encrypts (or decrypts) a string of decimal digits with FF1, following algorithms 7 and 8 of
+/// NIST SP 800-38G with a radix of 10. At most `FPE_MAX_DIGITS` digits are supported, so that
+/// every number fits in a `u128`.
+fn ff1(cipher: &Aes256, digits: &[u8], tweak: &[u8], encrypt: bool) -> Vec<u8> {
+    let n = digits.len();
+    let (u, v) = (n / 2, n - n / 2);
+    // the number of bytes of a number of `v` digits, `ceil(ceil(v * log2(10)) / 8)`
+    let b = (128 - (10u128.pow(v as u32) - 1).leading_zeros() as usize).div_ceil(8);
+    let d = 4 * b.div_ceil(4) + 4;
+
+    let mut p = vec![1, 2, 1, 0, 0, 10, 10, (u % 256) as u8];
+    p.extend_from_slice(&(n as u32).to_be_bytes());
+    p.extend_from_slice(&(tweak.len() as u32).to_be_bytes());
+    let padding = (16 - (tweak.len() + b + 1) % 16) % 16;
+
+    let (mut a, mut b_half) = (digits[..u].to_vec(), digits[u..].to_vec());
+    for round in 0..10u8 {
+        let i = if encrypt { round } else { 9 - round };
+        let m = if i % 2 == 0 { u } else { v };
+        let modulus = 10u128.pow(m as u32);
+        // the half that is not changed by the round
+        let other = if encrypt { &b_half } else { &a };
+
+        let mut q = tweak.to_vec();
+        q.resize(tweak.len() + padding, 0);
+        q.push(i);
+        q.extend_from_slice(&num(other).to_be_bytes()[16 - b..]);
+        let mut r = [0u8; 16];
+        for block in p.chunks(16).chain(q.chunks(16)) {
+            for (r_byte, byte) in r.iter_mut().zip(block) {
+                *r_byte ^= byte;
+            }
+            cipher.encrypt_block((&mut r).into());
+        }
+        let mut s = r.to_vec();
+        let mut j = 1u128;
+        while s.len() < d {
+            let mut block = (u128::from_be_bytes(r) ^ j).to_be_bytes();
+            cipher.encrypt_block((&mut block).into());
+            s.extend_from_slice(&block);
+            j += 1;
+        }
+        let y = s[..d]
+            .iter()
+            .fold(0u128, |y, byte| (y * 256 + *byte as u128) % modulus);
+
+        if encrypt {
+            let c = (num(&a) + y) % modulus;
+            a = std::mem::replace(&mut b_half, str_m(c, m));
+        } else {
+            let c = (num(&b_half) + modulus - y) % modulus;
+            b_half = std::mem::replace(&mut a, str_m(c, m));
+        }
+    }
+    a.extend_from_slice(&b_half);
+    a
+}
+
+/// The number of a string of decimal digits, `NUM_10(X)` in NIST SP 800-38G.
+fn num(digits: &[u8]) -> u128 {
+    digits
+        .iter()
+        .fold(0u128, |number, digit| number * 10 + *digit as u128)
+}
+
+/// The `m` decimal digits of a number, `STR^m_10(x)` in NIST SP 800-38G.
+fn str_m(mut number: u128, m: usize) -> Vec<u8> {
+    let mut digits = vec![0u8; m];
+    for digit in digits.iter_mut().rev() {
+        *digit = (number % 10) as u8;
+        number /= 10;
+    }
+    digits
+}
+
+/// Returns the given bytes as a lowercase hex string.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parses a hex string into bytes.
+pub fn hex_decode(hex_str: &str) -> anyhow::Result<Vec<u8>> {
+    if !hex_str.len().is_multiple_of(2) || !hex_str.is_ascii() {
+        return Err(anyhow::anyhow!("invalid hex string `{}`", hex_str));
+    }
+    (0..hex_str.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex_str[i..i + 2], 16)
+                .map_err(|_| anyhow::anyhow!("invalid hex string `{}`", hex_str))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crypto::*;
+
+    #[test]
+    fn test_key() {
+        let key = Key::generate();
+        let parsed = Key::from_str(&key.to_hex()).unwrap();
+        assert_eq!(key, parsed);
+        assert_eq!(format!("{:?}", key), "Key(..)");
+
+        assert_eq!(key.id().len(), 16);
+        assert_eq!(key.id(), parsed.id());
+        assert_ne!(key.id(), Key::generate().id());
+
+        assert!(Key::from_str("00").is_err());
+        assert!(Key::from_str(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_value() {
+        let key = Key::generate();
+        let encrypted = encrypt_value(&key, "0.03,0.97,0", b"FORMAT/GP").unwrap();
+        assert!(encrypted
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        let decrypted = decrypt_value(&key, &encrypted, b"FORMAT/GP").unwrap();
+        assert_eq!(decrypted, "0.03,0.97,0");
+
+        // wrong associated data
+        assert!(decrypt_value(&key, &encrypted, b"FORMAT/PL").is_err());
+        // wrong key
+        assert!(decrypt_value(&Key::generate(), &encrypted, b"FORMAT/GP").is_err());
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let key = Key::generate();
+        let encrypted = encrypt_value_deterministic(&key, "rs11449", b"ID").unwrap();
+        assert_eq!(
+            encrypted,
+            encrypt_value_deterministic(&key, "rs11449", b"ID").unwrap()
+        );
+        assert_ne!(
+            encrypted,
+            encrypt_value_deterministic(&key, "rs84825", b"ID").unwrap()
+        );
+        assert_ne!(
+            encrypted,
+            encrypt_value_deterministic(&Key::generate(), "rs11449", b"ID").unwrap()
+        );
+        assert_eq!(
+            decrypt_value_deterministic(&key, &encrypted, b"ID").unwrap(),
+            "rs11449"
+        );
+        assert!(decrypt_value_deterministic(&key, &encrypted, b"INFO/ID").is_err());
+        // keys are not shared with the randomized cipher
+        assert!(decrypt_value(&key, &encrypted, b"ID").is_err());
+    }
+
+    #[test]
+    fn test_fpe() {
+        let key = Key::generate();
+        let tweak = generate_tweak();
+        for number in &[
+            "0", "7", "10", "-42", "1291018", "0.03", "0.97", "1e-10", "-3.5E+2", "007", ".",
+        ] {
+            let encrypted = fpe_encrypt_number(&key, number, &tweak).unwrap();
+            assert_eq!(encrypted.len(), number.len());
+            assert_eq!(
+                encrypted.parse::<f64>().is_ok(),
+                number.parse::<f64>().is_ok()
+            );
+            assert_eq!(
+                encrypted.parse::<i32>().is_ok(),
+                number.parse::<i32>().is_ok()
+            );
+            assert_eq!(
+                fpe_decrypt_number(&key, &encrypted, &tweak).unwrap(),
+                *number
+            );
+        }
+
+        // no leading zeros are introduced
+        for i in 0..100 {
+            let tweak = [i as u8; TWEAK_LEN];
+            let encrypted = fpe_encrypt_number(&key, "1291018", &tweak).unwrap();
+            assert!(!encrypted.starts_with('0'));
+        }
+
+        // numbers stay in the range of their type
+        for i in 0..100 {
+            let tweak = [i as u8; TWEAK_LEN];
+            for number in &[
+                "2147483647",
+                "-2147483648",
+                "2000000000",
+                "3.4e38",
+                "-3.0E38",
+            ] {
+                let encrypted = fpe_encrypt_number(&key, number, &tweak).unwrap();
+                assert_eq!(
+                    number_type(&encrypted),
+                    number_type(number),
+                    "{}",
+                    encrypted
+                );
+                assert_eq!(
+                    fpe_decrypt_number(&key, &encrypted, &tweak).unwrap(),
+                    *number
+                );
+            }
+        }
+
+        // different tweaks give different results
+        assert_ne!(
+            fpe_encrypt_number(&key, "1291018", b"a").unwrap(),
+            fpe_encrypt_number(&key, "1291018", b"b").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ff1() {
+        // samples 7 and 8 of the FF1 examples published by NIST
+        let key =
+            hex_decode("2b7e151628aed2a6abf7158809cf4f3cef4359d8d580aa4f7f036d6f04fc6a94").unwrap();
+        let cipher = Aes256::new_from_slice(&key).unwrap();
+        let digits: Vec<u8> = (0..10).collect();
+        let tweak = hex_decode("39383736353433323130").unwrap();
+        for (tweak, expected) in [(&[][..], "6657667009"), (&tweak[..], "1001623463")] {
+            let encrypted = ff1(&cipher, &digits, tweak, true);
+            let encrypted_str: String = encrypted.iter().map(|d| (b'0' + d) as char).collect();
+            assert_eq!(encrypted_str, expected);
+            assert_eq!(ff1(&cipher, &encrypted, tweak, false), digits);
+        }
+    }
+
+    #[test]
+    fn test_keyring() {
+        let (first, second) = (Key::generate(), Key::generate());
+        let keyring_str = format!(
+            "# sample keys\nSAMP001\t{}\n\nSAMP 002 {}\n",
+            first.to_hex(),
+            second.to_hex()
+        );
+        let keyring = Keyring::from_str(&keyring_str).unwrap();
+        assert_eq!(keyring.len(), 2);
+        assert_eq!(keyring.get("SAMP001"), Some(&first));
+        assert_eq!(keyring.get("SAMP 002"), Some(&second));
+        assert_eq!(keyring.get("SAMP003"), None);
+
+        assert!(Keyring::from_str("SAMP001").is_err());
+        let duplicate = format!("S {}\nS {}", first.to_hex(), second.to_hex());
+        assert!(Keyring::from_str(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(&[0, 15, 255]), "000fff");
+        assert_eq!(hex_decode("000fff").unwrap(), vec![0, 15, 255]);
+        assert!(hex_decode("0").is_err());
+        assert!(hex_decode("zz").is_err());
+    }
+}
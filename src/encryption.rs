@@ -0,0 +1,1647 @@
+use crate::{
+    body::{DataLine, FormatType, IdType, InfoType, SampleType},
+    crypto::{
+        decrypt_value, decrypt_value_deterministic, encrypt_value, encrypt_value_deterministic,
+        fpe_decrypt_number, fpe_encrypt_number, generate_tweak, hex_decode, hex_encode, Key,
+        Keyring, CIPHER, DETERMINISTIC_CIPHER, FPE_CIPHER,
+    },
+    header::{self, EncryptionScope, Header, HeaderLine, Number},
+    parser::{transform, VCFParser},
+    text::{checked_str, Text},
+};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    fmt::{Display, Error, Formatter},
+    io::{BufRead, Write},
+    str::FromStr,
+};
+
+/// A field of a data line whose values can be encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The entries of the ID column.
+    Id,
+
+    /// The value of an INFO key.
+    Info(String),
+
+    /// The values of a FORMAT key, in every sample.
+    Format(String),
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    /// Parses fields using the bcftools notation, e.g., `ID`, `INFO/DP` or `FORMAT/GP`.
+    fn from_str(field_str: &str) -> anyhow::Result<Self> {
+        let field = if field_str == "ID" {
+            Field::Id
+        } else if let Some(id) = field_str.strip_prefix("INFO/") {
+            Field::Info(id.to_string())
+        } else if let Some(id) = field_str.strip_prefix("FORMAT/") {
+            Field::Format(id.to_string())
+        } else {
+            return Err(anyhow::anyhow!(
+                "invalid field `{}`, (fields must be `ID` or start with `INFO/` or `FORMAT/`)",
+                field_str
+            ));
+        };
+        if field.id().is_empty() {
+            return Err(anyhow::anyhow!("invalid field `{}`, (empty id)", field_str));
+        }
+        Ok(field)
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Field::Id => write!(f, "ID"),
+            Field::Info(id) => write!(f, "INFO/{}", id),
+            Field::Format(id) => write!(f, "FORMAT/{}", id),
+        }
+    }
+}
+
+impl Field {
+    pub fn id(&self) -> &str {
+        match self {
+            Field::Id => "ID",
+            Field::Info(id) | Field::Format(id) => id,
+        }
+    }
+
+    pub fn scope(&self) -> EncryptionScope {
+        match self {
+            Field::Id => EncryptionScope::Id,
+            Field::Info(_) => EncryptionScope::Info,
+            Field::Format(_) => EncryptionScope::Format,
+        }
+    }
+}
+
+/// How the values of encrypted fields are encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Values are encrypted with AES-256-GCM and base64 encoded. The header lines of the
+    /// encrypted fields are changed to `Type=String` and `Number=.`.
+    Authenticated,
+
+    /// The digits of Integer and Float values are encrypted with FF1 so that every value is still
+    /// a number of the same type and vectors keep their length. Header lines are left unchanged.
+    /// The values are not authenticated, so modified values decrypt to other numbers without
+    /// error, and their sign, number of digits and exponent are kept. Values of fewer than
+    /// `FPE_MIN_DIGITS` digits have so few possible ciphertexts that they are easily guessed.
+    FormatPreserving,
+
+    /// Values are encrypted with AES-256-GCM-SIV and base64 encoded, like `Authenticated`, but
+    /// equal values of a field always give equal ciphertexts, regardless of their record. This
+    /// allows filtering encrypted files by value, e.g., by IDs encrypted with `encrypt_id_list`,
+    /// at the cost of revealing which values are equal.
+    Deterministic,
+}
+
+/// The cipher of an encrypted field, along with its parameters.
+#[derive(Debug, Clone)]
+enum Cipher {
+    Authenticated,
+    FormatPreserving { tweak: Vec<u8> },
+    Deterministic,
+}
+
+impl Cipher {
+    fn name(&self) -> &'static str {
+        match self {
+            Cipher::Authenticated => CIPHER,
+            Cipher::FormatPreserving { .. } => FPE_CIPHER,
+            Cipher::Deterministic => DETERMINISTIC_CIPHER,
+        }
+    }
+
+    /// Encrypts a value of the given field. Vectors are encrypted element by element by the
+    /// format-preserving cipher, so their length is kept. The deterministic cipher only
+    /// authenticates the field, not the record, so that equal values give equal ciphertexts.
+    fn encrypt(&self, key: &Key, field: &Field, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+        match self {
+            Cipher::Authenticated => encrypt_value(key, value, aad),
+            Cipher::Deterministic => {
+                encrypt_value_deterministic(key, value, field.to_string().as_bytes())
+            }
+            Cipher::FormatPreserving { tweak } => Ok(value
+                .split(',')
+                .enumerate()
+                .map(|(index, element)| {
+                    fpe_encrypt_number(key, element, &element_tweak(tweak, aad, index))
+                })
+                .collect::<anyhow::Result<Vec<String>>>()?
+                .join(",")),
+        }
+    }
+
+    fn decrypt(&self, key: &Key, field: &Field, value: &str, aad: &[u8]) -> anyhow::Result<String> {
+        match self {
+            Cipher::Authenticated => decrypt_value(key, value, aad),
+            Cipher::Deterministic => {
+                decrypt_value_deterministic(key, value, field.to_string().as_bytes())
+            }
+            Cipher::FormatPreserving { tweak } => Ok(value
+                .split(',')
+                .enumerate()
+                .map(|(index, element)| {
+                    fpe_decrypt_number(key, element, &element_tweak(tweak, aad, index))
+                })
+                .collect::<anyhow::Result<Vec<String>>>()?
+                .join(",")),
+        }
+    }
+}
+
+/// The tweak of a vector element, binding its ciphertext to the file, field, record, sample and
+/// position in the vector.
+fn element_tweak(tweak: &[u8], aad: &[u8], index: usize) -> Vec<u8> {
+    let mut result = tweak.to_vec();
+    result.extend_from_slice(aad);
+    result.extend_from_slice(format!(":{}", index).as_bytes());
+    result
+}
+
+/// A field described by an `##ENCRYPTED` header line.
+struct EncryptedField {
+    field: Field,
+    original_type: Option<String>,
+    original_number: Option<Number>,
+    cipher: String,
+    key_id: String,
+    other: LinkedHashMap<String, String>,
+}
+
+/// Encrypts the values of selected INFO and FORMAT fields while keeping the file parsable by
+/// standard tools. See `Mode` for how values are encrypted.
+#[derive(Debug)]
+pub struct Encryptor {
+    key: Key,
+    fields: Vec<Field>,
+    cipher: Cipher,
+}
+
+impl Encryptor {
+    pub fn new(key: Key, fields: Vec<Field>) -> Self {
+        Encryptor::with_mode(key, fields, Mode::Authenticated)
+    }
+
+    /// Creates an encryptor using the given mode. A random tweak is generated for the
+    /// format-preserving mode, so encrypting the same file twice gives different results.
+    pub fn with_mode(key: Key, fields: Vec<Field>, mode: Mode) -> Self {
+        let cipher = match mode {
+            Mode::Authenticated => Cipher::Authenticated,
+            Mode::Deterministic => Cipher::Deterministic,
+            Mode::FormatPreserving => Cipher::FormatPreserving {
+                tweak: generate_tweak().to_vec(),
+            },
+        };
+        Encryptor {
+            key,
+            fields,
+            cipher,
+        }
+    }
+
+    /// Reads all data lines of the parser, encrypts them and writes them to the given writer.
+    pub fn encrypt<R: BufRead, W: Write>(
+        &self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.encrypt_header(&mut parser.header)?;
+        transform(parser, writer, |mut dl| {
+            self.encrypt_data_line(&mut dl)?;
+            Ok([dl])
+        })
+    }
+
+    /// Changes the definitions of the encrypted fields so that they can hold ciphertexts, unless
+    /// the format-preserving mode is used. The original definitions are recorded in `##ENCRYPTED`
+    /// header lines.
+    pub fn encrypt_header(&self, header: &mut Header) -> anyhow::Result<()> {
+        let mut markers = vec![];
+        for field in &self.fields {
+            if header.is_encrypted(&field.scope(), field.id()) {
+                return Err(anyhow::anyhow!("field `{}` is already encrypted", field));
+            }
+            let format_preserving = matches!(self.cipher, Cipher::FormatPreserving { .. });
+            if *field == Field::Id {
+                if format_preserving {
+                    return Err(format_preserving_error(field, "String"));
+                }
+                markers.push(HeaderLine::Encrypted {
+                    id: field.id().to_string(),
+                    scope: field.scope(),
+                    original_type: None,
+                    original_number: None,
+                    cipher: self.cipher.name().to_string(),
+                    key_id: self.key.id(),
+                    other: LinkedHashMap::new(),
+                });
+                continue;
+            }
+            let header_line = field_definition(header, field)
+                .ok_or_else(|| anyhow::anyhow!("field `{}` is not defined in the header", field))?;
+            let (original_type, original_number) = match header_line {
+                HeaderLine::Info { number, typ, .. } => {
+                    if *typ == header::InfoType::Flag {
+                        return Err(anyhow::anyhow!("flag `{}` cannot be encrypted", field));
+                    }
+                    let original = (typ.to_string(), number.clone());
+                    if !format_preserving {
+                        *number = Number::Unknown;
+                        *typ = header::InfoType::String;
+                    } else if *typ != header::InfoType::Integer && *typ != header::InfoType::Float {
+                        return Err(format_preserving_error(field, &original.0));
+                    }
+                    original
+                }
+                HeaderLine::Format {
+                    id, number, typ, ..
+                } => {
+                    if id == "GT" {
+                        return Err(anyhow::anyhow!("genotypes cannot be encrypted"));
+                    }
+                    let original = (typ.to_string(), number.clone());
+                    if !format_preserving {
+                        *number = Number::Unknown;
+                        *typ = header::FormatType::String;
+                    } else if *typ != header::FormatType::Integer
+                        && *typ != header::FormatType::Float
+                    {
+                        return Err(format_preserving_error(field, &original.0));
+                    }
+                    original
+                }
+                _ => continue,
+            };
+            let mut other = LinkedHashMap::new();
+            if let Cipher::FormatPreserving { tweak } = &self.cipher {
+                other.insert("Tweak".to_string(), hex_encode(tweak));
+            }
+            markers.push(HeaderLine::Encrypted {
+                id: field.id().to_string(),
+                scope: field.scope(),
+                original_type: Some(original_type),
+                original_number: Some(original_number),
+                cipher: self.cipher.name().to_string(),
+                key_id: self.key.id(),
+                other,
+            });
+        }
+        header.header_lines.append(&mut markers);
+        Ok(())
+    }
+
+    /// Encrypts the values of the selected fields in the given data line.
+    pub fn encrypt_data_line(&self, dl: &mut DataLine) -> anyhow::Result<()> {
+        for field in &self.fields {
+            let (key, cipher) = (&self.key, &self.cipher);
+            map_field_values(dl, field, |value, aad, _| {
+                cipher
+                    .encrypt(key, field, checked_str(value)?, aad)
+                    .map(Text::from)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Decrypts files produced by `Encryptor`. The encrypted fields and their original definitions
+/// are read from the `##ENCRYPTED` header lines, so the decrypted file is identical to the one
+/// that was encrypted.
+#[derive(Debug)]
+pub struct Decryptor {
+    key: Key,
+    fields: Vec<(Field, Cipher)>,
+}
+
+impl Decryptor {
+    pub fn new(key: Key) -> Self {
+        Decryptor {
+            key,
+            fields: vec![],
+        }
+    }
+
+    /// Reads all data lines of the parser, decrypts them and writes them to the given writer.
+    pub fn decrypt<R: BufRead, W: Write>(
+        &mut self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.decrypt_header(&mut parser.header)?;
+        transform(parser, writer, |mut dl| {
+            self.decrypt_data_line(&mut dl)?;
+            Ok([dl])
+        })
+    }
+
+    /// Restores the original definitions of the encrypted fields and removes the `##ENCRYPTED`
+    /// header lines. The encrypted fields are remembered for decrypting data lines.
+    pub fn decrypt_header(&mut self, header: &mut Header) -> anyhow::Result<()> {
+        self.fields.clear();
+        for (encrypted, cipher) in encrypted_ciphers(header, &self.key)? {
+            let field = encrypted.field;
+            if field == Field::Id {
+                self.fields.push((field, cipher));
+                continue;
+            }
+            let (original_type, original_number) =
+                match (encrypted.original_type, encrypted.original_number) {
+                    (Some(t), Some(n)) => (t, n),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "original definition of encrypted field `{}` is missing",
+                            field
+                        ))
+                    }
+                };
+            match field_definition(header, &field) {
+                Some(HeaderLine::Info { number, typ, .. }) => {
+                    *number = original_number;
+                    *typ = original_type.parse()?;
+                }
+                Some(HeaderLine::Format { number, typ, .. }) => {
+                    *number = original_number;
+                    *typ = original_type.parse()?;
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "encrypted field `{}` is not defined in the header",
+                        field
+                    ))
+                }
+            }
+            self.fields.push((field, cipher));
+        }
+        header
+            .header_lines
+            .retain(|hl| !matches!(hl, HeaderLine::Encrypted { .. }) || is_per_sample(hl));
+        Ok(())
+    }
+
+    /// Decrypts the values of the encrypted fields in the given data line.
+    pub fn decrypt_data_line(&self, dl: &mut DataLine) -> anyhow::Result<()> {
+        let position = format!("{}:{}", dl.chromosome, dl.position);
+        for (field, cipher) in &self.fields {
+            let key = &self.key;
+            map_field_values(dl, field, |value, aad, _| {
+                cipher
+                    .decrypt(key, field, checked_str(value)?, aad)
+                    .map(Text::from)
+                    .map_err(|e| {
+                        anyhow::anyhow!("could not decrypt `{}` at {}: {}", field, position, e)
+                    })
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn format_preserving_error(field: &Field, typ: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "field `{}` of type `{}` cannot be encrypted preserving its format, (only Integer and Float fields can)",
+        field,
+        typ
+    )
+}
+
+/// Re-encrypts the fields encrypted by `Encryptor` from an old key to a new one, one data line at
+/// a time, so that decrypted values only ever exist in memory. Every value is authenticated with
+/// the old key before being encrypted again, except for format-preserving values, which carry no
+/// authentication tag. Sample columns encrypted by `SampleEncryptor` are left as they are.
+#[derive(Debug)]
+pub struct Rekeyer {
+    old_key: Key,
+    new_key: Key,
+    fields: Vec<(Field, Cipher)>,
+}
+
+impl Rekeyer {
+    pub fn new(old_key: Key, new_key: Key) -> Self {
+        Rekeyer {
+            old_key,
+            new_key,
+            fields: vec![],
+        }
+    }
+
+    /// Reads all data lines of the parser, re-encrypts them and writes them to the given writer.
+    pub fn rekey<R: BufRead, W: Write>(
+        &mut self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.rekey_header(&mut parser.header)?;
+        transform(parser, writer, |mut dl| {
+            self.rekey_data_line(&mut dl)?;
+            Ok([dl])
+        })
+    }
+
+    /// Replaces the id of the old key with the id of the new key in the `##ENCRYPTED` header
+    /// lines. All encrypted fields must be encrypted with the old key.
+    pub fn rekey_header(&mut self, header: &mut Header) -> anyhow::Result<()> {
+        self.fields = encrypted_ciphers(header, &self.old_key)?
+            .into_iter()
+            .map(|(encrypted, cipher)| (encrypted.field, cipher))
+            .collect();
+        if self.fields.is_empty() {
+            return Err(anyhow::anyhow!(
+                "no fields are encrypted, nothing to re-key"
+            ));
+        }
+        for hl in header.header_lines.iter_mut() {
+            let per_sample = is_per_sample(hl);
+            if let HeaderLine::Encrypted { key_id, .. } = hl {
+                if !per_sample {
+                    *key_id = self.new_key.id();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-encrypts the values of the encrypted fields in the given data line, failing if any of
+    /// them was modified.
+    pub fn rekey_data_line(&self, dl: &mut DataLine) -> anyhow::Result<()> {
+        let position = format!("{}:{}", dl.chromosome, dl.position);
+        for (field, cipher) in &self.fields {
+            map_field_values(dl, field, |value, aad, _| {
+                let plaintext = cipher
+                    .decrypt(&self.old_key, field, checked_str(value)?, aad)
+                    .map_err(|e| {
+                        anyhow::anyhow!("could not decrypt `{}` at {}: {}", field, position, e)
+                    })?;
+                cipher
+                    .encrypt(&self.new_key, field, &plaintext, aad)
+                    .map(Text::from)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Encrypts a list of IDs, one per line, the same way `Mode::Deterministic` encrypts the ID column.
+/// An ID file used for filtering, e.g., by `bcftools view -i 'ID=@ids.txt'`, can thus be applied
+/// to encrypted files without decrypting them. Empty lines are skipped.
+pub fn encrypt_id_list<R: BufRead, W: Write>(
+    key: &Key,
+    reader: R,
+    mut writer: W,
+) -> anyhow::Result<()> {
+    let aad = Field::Id.to_string();
+    for line in reader.lines() {
+        let line = line?;
+        let id = line.trim();
+        if id.is_empty() {
+            continue;
+        }
+        writeln!(
+            writer,
+            "{}",
+            encrypt_value_deterministic(key, id, aad.as_bytes())?
+        )?;
+    }
+    Ok(())
+}
+
+/// The FORMAT key holding the ciphertexts of entire sample entries.
+pub const ENCRYPTED_SAMPLE_KEY: &str = "ENC";
+
+/// What `SampleEncryptor` encrypts in every sample column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SampleFields {
+    /// Entire sample entries. The ciphertext of every entry is stored in the `ENC` FORMAT key, and
+    /// all other values of the sample are replaced with missing values.
+    All,
+
+    /// The values of the given FORMAT keys only.
+    Keys(Vec<String>),
+}
+
+/// Encrypts every sample column with the key of its sample, so that each participant of a file
+/// can only decrypt their own column. Values are encrypted with AES-256-GCM.
+#[derive(Debug)]
+pub struct SampleEncryptor {
+    keyring: Keyring,
+    fields: SampleFields,
+    /// The keys of the sample columns, in order. Set by `encrypt_header`.
+    keys: Vec<Key>,
+}
+
+impl SampleEncryptor {
+    pub fn new(keyring: Keyring, fields: SampleFields) -> Self {
+        SampleEncryptor {
+            keyring,
+            fields,
+            keys: vec![],
+        }
+    }
+
+    /// Reads all data lines of the parser, encrypts them and writes them to the given writer.
+    pub fn encrypt<R: BufRead, W: Write>(
+        &mut self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.encrypt_header(&mut parser.header)?;
+        transform(parser, writer, |mut dl| {
+            self.encrypt_data_line(&mut dl)?;
+            Ok([dl])
+        })
+    }
+
+    /// Looks up the key of every sample, which must be in the keyring, and records which key
+    /// encrypts which sample in `##ENCRYPTED` header lines. Encrypted FORMAT keys are changed to
+    /// `Type=String` and `Number=.`, and the `ENC` FORMAT key is defined when encrypting entire
+    /// entries.
+    pub fn encrypt_header(&mut self, header: &mut Header) -> anyhow::Result<()> {
+        self.keys.clear();
+        for sample in &header.column_names {
+            if header.is_encrypted(&EncryptionScope::Sample, sample) {
+                return Err(anyhow::anyhow!("sample `{}` is already encrypted", sample));
+            }
+            let key = self
+                .keyring
+                .get(sample)
+                .ok_or_else(|| anyhow::anyhow!("no key for sample `{}` in the keyring", sample))?;
+            self.keys.push(key.clone());
+        }
+
+        let mut markers = vec![];
+        match &self.fields {
+            SampleFields::All => {
+                let field = Field::Format(ENCRYPTED_SAMPLE_KEY.to_string());
+                if field_definition(header, &field).is_some() {
+                    return Err(anyhow::anyhow!(
+                        "FORMAT key `{}` is already defined",
+                        ENCRYPTED_SAMPLE_KEY
+                    ));
+                }
+                header.header_lines.push(HeaderLine::Format {
+                    id: ENCRYPTED_SAMPLE_KEY.to_string(),
+                    number: Number::Integer(1),
+                    typ: header::FormatType::String,
+                    description: Text::from("Encrypted sample values"),
+                    other: LinkedHashMap::new(),
+                });
+                for (sample, key) in header.column_names.iter().zip(&self.keys) {
+                    markers.push(HeaderLine::Encrypted {
+                        id: sample.to_string(),
+                        scope: EncryptionScope::Sample,
+                        original_type: None,
+                        original_number: None,
+                        cipher: CIPHER.to_string(),
+                        key_id: key.id(),
+                        other: LinkedHashMap::new(),
+                    });
+                }
+            }
+            SampleFields::Keys(ids) => {
+                for id in ids {
+                    let field = Field::Format(id.to_string());
+                    if header.is_encrypted(&EncryptionScope::Format, id) {
+                        return Err(anyhow::anyhow!("field `{}` is already encrypted", field));
+                    }
+                    if id == "GT" {
+                        return Err(anyhow::anyhow!("genotypes cannot be encrypted"));
+                    }
+                    let (original_type, original_number) = match field_definition(header, &field) {
+                        Some(HeaderLine::Format { number, typ, .. }) => {
+                            let original = (typ.to_string(), number.clone());
+                            *number = Number::Unknown;
+                            *typ = header::FormatType::String;
+                            original
+                        }
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "field `{}` is not defined in the header",
+                                field
+                            ))
+                        }
+                    };
+                    for (sample, key) in header.column_names.iter().zip(&self.keys) {
+                        let mut other = LinkedHashMap::new();
+                        other.insert("Sample".to_string(), sample.to_string());
+                        markers.push(HeaderLine::Encrypted {
+                            id: id.to_string(),
+                            scope: EncryptionScope::Format,
+                            original_type: Some(original_type.clone()),
+                            original_number: Some(original_number.clone()),
+                            cipher: CIPHER.to_string(),
+                            key_id: key.id(),
+                            other,
+                        });
+                    }
+                }
+            }
+        }
+        header.header_lines.append(&mut markers);
+        Ok(())
+    }
+
+    /// Encrypts the sample columns of the given data line.
+    pub fn encrypt_data_line(&self, dl: &mut DataLine) -> anyhow::Result<()> {
+        match &self.fields {
+            SampleFields::All => {
+                let format = match &mut dl.format {
+                    Some(format) => format,
+                    None => return Ok(()),
+                };
+                let format_str = format.to_string();
+                let mut entries = match format {
+                    FormatType::Entries(entries) => entries.clone(),
+                    FormatType::Missing => vec![],
+                };
+                let width = entries.len();
+                entries.push(ENCRYPTED_SAMPLE_KEY.to_string());
+                *format = FormatType::Entries(entries);
+
+                for index in 0..dl.samples.len() {
+                    let aad = sample_associated_data(dl, &format_str, index);
+                    let mut plaintext = vec![];
+                    dl.samples[index].write_to(&mut plaintext)?;
+                    let mut masked = vec![Text::from("."); width];
+                    let ciphertext =
+                        encrypt_value(&self.keys[index], checked_str(&plaintext)?, &aad)?;
+                    masked.push(Text::from(ciphertext));
+                    dl.samples[index] = SampleType::Entries(masked);
+                }
+            }
+            SampleFields::Keys(ids) => {
+                for id in ids {
+                    let field = Field::Format(id.to_string());
+                    map_field_values(dl, &field, |value, aad, sample| match sample {
+                        Some(index) => encrypt_value(&self.keys[index], checked_str(value)?, aad)
+                            .map(Text::from),
+                        None => Ok(Text::from(value)),
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decrypts the sample columns encrypted by `SampleEncryptor` whose keys are in the keyring. The
+/// keyring may hold the keys of only some samples, in which case the other columns are left
+/// encrypted and the file stays valid.
+#[derive(Debug)]
+pub struct SampleDecryptor {
+    keyring: Keyring,
+    /// The keys of the sample columns that can be decrypted, in order. Set by `decrypt_header`.
+    keys: Vec<Option<Key>>,
+    /// Whether entire sample entries are encrypted.
+    entries: bool,
+    /// Whether the `ENC` FORMAT key is removed, because no sample entry remains encrypted.
+    remove_entries_key: bool,
+    /// The encrypted FORMAT keys.
+    fields: Vec<Field>,
+}
+
+impl SampleDecryptor {
+    pub fn new(keyring: Keyring) -> Self {
+        SampleDecryptor {
+            keyring,
+            keys: vec![],
+            entries: false,
+            remove_entries_key: false,
+            fields: vec![],
+        }
+    }
+
+    /// Reads all data lines of the parser, decrypts them and writes them to the given writer.
+    pub fn decrypt<R: BufRead, W: Write>(
+        &mut self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.decrypt_header(&mut parser.header)?;
+        transform(parser, writer, |mut dl| {
+            self.decrypt_data_line(&mut dl)?;
+            Ok([dl])
+        })
+    }
+
+    /// Removes the `##ENCRYPTED` header lines of the samples that can be decrypted. Encrypted
+    /// FORMAT keys get their original definitions back, and the `ENC` key is removed, only once
+    /// no sample remains encrypted.
+    pub fn decrypt_header(&mut self, header: &mut Header) -> anyhow::Result<()> {
+        self.keys = vec![None; header.column_names.len()];
+        self.entries = false;
+        self.fields.clear();
+        let mut entries_remaining = false;
+        let mut fields_remaining = vec![];
+        let mut originals = vec![];
+        let mut decrypted = vec![];
+
+        for (line_index, hl) in header.header_lines.iter().enumerate() {
+            if !is_per_sample(hl) {
+                continue;
+            }
+            let (id, scope, original_type, original_number, cipher, key_id, other) = match hl {
+                HeaderLine::Encrypted {
+                    id,
+                    scope,
+                    original_type,
+                    original_number,
+                    cipher,
+                    key_id,
+                    other,
+                } => (
+                    id,
+                    scope,
+                    original_type,
+                    original_number,
+                    cipher,
+                    key_id,
+                    other,
+                ),
+                _ => continue,
+            };
+            let sample = match scope {
+                EncryptionScope::Sample => id,
+                _ => &other["Sample"],
+            };
+            let field = Field::Format(id.to_string());
+            let key = match self.keyring.get(sample) {
+                Some(key) => key,
+                None => {
+                    match scope {
+                        EncryptionScope::Sample => entries_remaining = true,
+                        _ => fields_remaining.push(field),
+                    }
+                    continue;
+                }
+            };
+            if cipher != CIPHER {
+                return Err(anyhow::anyhow!(
+                    "sample `{}` is encrypted with unsupported cipher `{}`",
+                    sample,
+                    cipher
+                ));
+            }
+            if *key_id != key.id() {
+                return Err(anyhow::anyhow!(
+                    "sample `{}` is encrypted with key `{}`, not with the given key `{}`",
+                    sample,
+                    key_id,
+                    key.id()
+                ));
+            }
+            let index = header
+                .column_names
+                .iter()
+                .position(|name| name == sample)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("encrypted sample `{}` is not in the file", sample)
+                })?;
+            self.keys[index] = Some(key.clone());
+            match scope {
+                EncryptionScope::Sample => self.entries = true,
+                _ => {
+                    if !self.fields.contains(&field) {
+                        originals.push((
+                            field.clone(),
+                            original_type.clone(),
+                            original_number.clone(),
+                        ));
+                        self.fields.push(field);
+                    }
+                }
+            }
+            decrypted.push(line_index);
+        }
+
+        for (field, original_type, original_number) in originals {
+            if fields_remaining.contains(&field) {
+                continue;
+            }
+            let (original_type, original_number) = match (original_type, original_number) {
+                (Some(t), Some(n)) => (t, n),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "original definition of encrypted field `{}` is missing",
+                        field
+                    ))
+                }
+            };
+            match field_definition(header, &field) {
+                Some(HeaderLine::Format { number, typ, .. }) => {
+                    *number = original_number;
+                    *typ = original_type.parse()?;
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "encrypted field `{}` is not defined in the header",
+                        field
+                    ))
+                }
+            }
+        }
+
+        self.remove_entries_key = self.entries && !entries_remaining;
+        let remove_entries_key = self.remove_entries_key;
+        let mut line_index = 0;
+        header.header_lines.retain(|hl| {
+            let is_entries_key =
+                matches!(hl, HeaderLine::Format { id, .. } if id == ENCRYPTED_SAMPLE_KEY);
+            let remove = decrypted.contains(&line_index) || (remove_entries_key && is_entries_key);
+            line_index += 1;
+            !remove
+        });
+        Ok(())
+    }
+
+    /// Decrypts the sample columns of the given data line whose keys are known.
+    pub fn decrypt_data_line(&self, dl: &mut DataLine) -> anyhow::Result<()> {
+        let position = format!("{}:{}", dl.chromosome, dl.position);
+        if let (true, Some(enc_index)) = (self.entries, dl.format_index(ENCRYPTED_SAMPLE_KEY)) {
+            let mut entries = match &dl.format {
+                Some(FormatType::Entries(entries)) => entries.clone(),
+                _ => vec![],
+            };
+            entries.remove(enc_index);
+            let format = if entries.is_empty() {
+                FormatType::Missing
+            } else {
+                FormatType::Entries(entries)
+            };
+            let format_str = format.to_string();
+
+            for index in 0..dl.samples.len() {
+                let ciphertext = match &dl.samples[index] {
+                    SampleType::Entries(entries) => entries.get(enc_index).cloned(),
+                    SampleType::Missing => None,
+                };
+                let (key, ciphertext) = match (&self.keys[index], ciphertext) {
+                    (Some(key), Some(ciphertext)) if ciphertext != "." => (key, ciphertext),
+                    _ => {
+                        // the sample was decrypted before, only its `ENC` value is left
+                        if let SampleType::Entries(entries) = &mut dl.samples[index] {
+                            if self.remove_entries_key && entries.len() > enc_index {
+                                entries.remove(enc_index);
+                            }
+                        }
+                        continue;
+                    }
+                };
+                let aad = sample_associated_data(dl, &format_str, index);
+                let plaintext = decrypt_value(key, ciphertext.as_str()?, &aad).map_err(|e| {
+                    anyhow::anyhow!("could not decrypt sample {} at {}: {}", index, position, e)
+                })?;
+                let mut sample: SampleType = plaintext.parse()?;
+                if !self.remove_entries_key {
+                    // dropped trailing fields are filled in so that `ENC` stays in place
+                    let mut entries = match sample {
+                        SampleType::Entries(entries) => entries,
+                        SampleType::Missing => vec![],
+                    };
+                    entries.resize(enc_index, Text::from("."));
+                    entries.push(Text::from("."));
+                    sample = SampleType::Entries(entries);
+                }
+                dl.samples[index] = sample;
+            }
+            if self.remove_entries_key {
+                dl.format = Some(format);
+            }
+        }
+        for field in &self.fields {
+            map_field_values(dl, field, |value, aad, sample| {
+                match sample.and_then(|index| self.keys[index].as_ref()) {
+                    Some(key) => decrypt_value(key, checked_str(value)?, aad)
+                        .map(Text::from)
+                        .map_err(|e| {
+                            anyhow::anyhow!("could not decrypt `{}` at {}: {}", field, position, e)
+                        }),
+                    None => Ok(Text::from(value)),
+                }
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the fields described by the `##ENCRYPTED` header lines along with their ciphers,
+/// failing if a field is encrypted with another key or an unsupported cipher.
+fn encrypted_ciphers(header: &Header, key: &Key) -> anyhow::Result<Vec<(EncryptedField, Cipher)>> {
+    let mut result = vec![];
+    for encrypted in encrypted_fields(header) {
+        let field = &encrypted.field;
+        let cipher = match encrypted.cipher.as_str() {
+            CIPHER => Cipher::Authenticated,
+            DETERMINISTIC_CIPHER => Cipher::Deterministic,
+            FPE_CIPHER => {
+                let tweak = encrypted.other.get("Tweak").ok_or_else(|| {
+                    anyhow::anyhow!("tweak of encrypted field `{}` is missing", field)
+                })?;
+                Cipher::FormatPreserving {
+                    tweak: hex_decode(tweak)?,
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "field `{}` is encrypted with unsupported cipher `{}`",
+                    field,
+                    encrypted.cipher
+                ))
+            }
+        };
+        if encrypted.key_id != key.id() {
+            return Err(anyhow::anyhow!(
+                "field `{}` is encrypted with key `{}`, not with the given key `{}`",
+                field,
+                encrypted.key_id,
+                key.id()
+            ));
+        }
+        result.push((encrypted, cipher));
+    }
+    Ok(result)
+}
+
+/// Returns the definition of the given field in the header, if any.
+fn field_definition<'a>(header: &'a mut Header, field: &Field) -> Option<&'a mut HeaderLine> {
+    header.header_lines.iter_mut().find(|hl| match (hl, field) {
+        (HeaderLine::Info { id, .. }, Field::Info(field_id))
+        | (HeaderLine::Format { id, .. }, Field::Format(field_id)) => id == field_id,
+        _ => false,
+    })
+}
+
+/// Whether the given `##ENCRYPTED` header line describes values encrypted with the key of a
+/// single sample, which are handled by `SampleDecryptor`.
+fn is_per_sample(hl: &HeaderLine) -> bool {
+    match hl {
+        HeaderLine::Encrypted { scope, other, .. } => {
+            *scope == EncryptionScope::Sample || other.contains_key("Sample")
+        }
+        _ => false,
+    }
+}
+
+/// Returns the fields described by the `##ENCRYPTED` header lines, except those encrypted per
+/// sample.
+fn encrypted_fields(header: &Header) -> Vec<EncryptedField> {
+    let mut result = vec![];
+    for hl in &header.header_lines {
+        if is_per_sample(hl) {
+            continue;
+        }
+        if let HeaderLine::Encrypted {
+            id,
+            scope,
+            original_type,
+            original_number,
+            cipher,
+            key_id,
+            other,
+        } = hl
+        {
+            let field = match scope {
+                EncryptionScope::Id => Field::Id,
+                EncryptionScope::Info => Field::Info(id.to_string()),
+                EncryptionScope::Format => Field::Format(id.to_string()),
+                EncryptionScope::Sample => continue,
+            };
+            result.push(EncryptedField {
+                field,
+                original_type: original_type.clone(),
+                original_number: original_number.clone(),
+                cipher: cipher.to_string(),
+                key_id: key_id.to_string(),
+                other: other.clone(),
+            });
+        }
+    }
+    result
+}
+
+/// The data authenticated along with every encrypted value. It binds a ciphertext to its field,
+/// record and sample so that it cannot be moved elsewhere in the file without detection. Records
+/// are told apart by their position and alleles, so values can only be swapped between records
+/// that have the same ones, which are duplicates.
+pub fn associated_data(field: &Field, dl: &DataLine, sample: Option<usize>) -> Vec<u8> {
+    let mut aad = format!("{}:{}", field, record_associated_data(dl));
+    if let Some(index) = sample {
+        aad.push_str(&format!(":{}", index));
+    }
+    aad.into_bytes()
+}
+
+/// The data authenticated along with an entire encrypted sample entry. It includes the FORMAT
+/// column of the record, without the `ENC` key.
+fn sample_associated_data(dl: &DataLine, format: &str, sample: usize) -> Vec<u8> {
+    format!(
+        "SAMPLE:{}:{}:{}",
+        record_associated_data(dl),
+        format,
+        sample
+    )
+    .into_bytes()
+}
+
+/// The part of the associated data identifying a record: its position, REF and ALT.
+fn record_associated_data(dl: &DataLine) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        dl.chromosome, dl.position, dl.reference, dl.alternative
+    )
+}
+
+/// Replaces every value of the given field in a data line with the result of `f`, which is called
+/// with the bytes of the current value, its associated data and the index of its sample, if any.
+/// Missing samples and dropped trailing sample fields are left as they are.
+pub(crate) fn map_field_values<F>(dl: &mut DataLine, field: &Field, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(&[u8], &[u8], Option<usize>) -> anyhow::Result<Text>,
+{
+    match field {
+        Field::Id => {
+            let aad = associated_data(field, dl, None);
+            if let IdType::Entries(entries) = &mut dl.id {
+                for entry in entries.iter_mut() {
+                    *entry = String::from_utf8(f(entry.as_bytes(), &aad, None)?.into_bytes())?;
+                }
+            }
+        }
+        Field::Info(id) => {
+            let aad = associated_data(field, dl, None);
+            if let InfoType::Entries(entries) = &mut dl.info {
+                for entry in entries.iter_mut() {
+                    let bytes = entry.as_bytes();
+                    if let Some(index) = bytes.iter().position(|b| *b == b'=') {
+                        if &bytes[..index] == id.as_bytes() {
+                            let mut value = format!("{}=", id).into_bytes();
+                            value.extend_from_slice(f(&bytes[index + 1..], &aad, None)?.as_bytes());
+                            *entry = Text::from(value);
+                        }
+                    }
+                }
+            }
+        }
+        Field::Format(id) => {
+            let index = match dl.format_index(id) {
+                Some(index) => index,
+                None => return Ok(()),
+            };
+            for sample_index in 0..dl.samples.len() {
+                let aad = associated_data(field, dl, Some(sample_index));
+                if let SampleType::Entries(entries) = &mut dl.samples[sample_index] {
+                    if let Some(entry) = entries.get_mut(index) {
+                        *entry = f(entry.as_bytes(), &aad, Some(sample_index))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        crypto::{decrypt_value, Keyring},
+        encryption::*,
+        test_util,
+    };
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+##contig=<ID=20,length=62435964>
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=GP,Number=G,Type=Float,Description=\"Genotype Probabilities\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMP001\tSAMP002
+20\t1291018\trs11449\tG\tA\t.\tPASS\tDP=10\tGT\t0/0\t0/1
+20\t2300608\trs84825\tC\tT\t.\tPASS\t.\tGT:GP\t0/1:.\t0/1:0.03,0.97,0";
+
+    #[test]
+    fn test_field() {
+        assert_eq!(
+            Field::from_str("FORMAT/GP").unwrap(),
+            Field::Format("GP".to_string())
+        );
+        assert_eq!(Field::from_str("INFO/DP").unwrap().to_string(), "INFO/DP");
+        assert_eq!(Field::from_str("ID").unwrap(), Field::Id);
+        assert!(Field::from_str("GP").is_err());
+        assert!(Field::from_str("INFO/").is_err());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let key = Key::generate();
+        let encryptor = Encryptor::new(
+            key.clone(),
+            vec![
+                Field::Format("GP".to_string()),
+                Field::Info("DP".to_string()),
+            ],
+        );
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mut buf = vec![];
+        encryptor.encrypt(parser, &mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(
+            lines[2],
+            "##INFO=<ID=DP,Number=.,Type=String,Description=\"Total Depth\">"
+        );
+        assert_eq!(
+            lines[4],
+            "##FORMAT=<ID=GP,Number=.,Type=String,Description=\"Genotype Probabilities\">"
+        );
+
+        let first: Vec<&str> = lines[8].split('\t').collect();
+        let dp = first[7].strip_prefix("DP=").unwrap();
+        let aad = b"INFO/DP:20:1291018:G:A";
+        assert_eq!(decrypt_value(&key, dp, aad).unwrap(), "10");
+        assert_eq!(&first[8..], &["GT", "0/0", "0/1"]);
+
+        let second: Vec<&str> = lines[9].split('\t').collect();
+        let gp = second[10].strip_prefix("0/1:").unwrap();
+        let aad = b"FORMAT/GP:20:2300608:C:T:1";
+        assert_eq!(decrypt_value(&key, gp, aad).unwrap(), "0.03,0.97,0");
+        // ciphertexts cannot be moved between samples
+        let gp = second[9].strip_prefix("0/1:").unwrap();
+        assert!(decrypt_value(&key, gp, aad).is_err());
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let key = Key::generate();
+        let encryptor = Encryptor::new(
+            key.clone(),
+            vec![
+                Field::Format("GP".to_string()),
+                Field::Info("DP".to_string()),
+            ],
+        );
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        assert!(encrypted.contains(&format!(
+            "\n##ENCRYPTED=<ID=GP,Scope=FORMAT,OriginalType=Float,OriginalNumber=G,Cipher=AES-256-GCM,KeyId={}>\n",
+            key.id()
+        )));
+        assert!(encrypted.contains(&format!(
+            "\n##ENCRYPTED=<ID=DP,Scope=INFO,OriginalType=Integer,OriginalNumber=1,Cipher=AES-256-GCM,KeyId={}>\n",
+            key.id()
+        )));
+        let parser = VCFParser::new(encrypted.as_bytes()).unwrap();
+        assert!(parser.header.is_encrypted(&EncryptionScope::Format, "GP"));
+        assert_eq!(parser.header.key_ids(), vec![key.id()]);
+
+        let mut decrypted = vec![];
+        Decryptor::new(key)
+            .decrypt(
+                VCFParser::new(encrypted.as_bytes()).unwrap(),
+                &mut decrypted,
+            )
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+
+        // wrong key
+        let parser = VCFParser::new(encrypted.as_bytes()).unwrap();
+        let error = Decryptor::new(Key::generate())
+            .decrypt(parser, vec![])
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("field `FORMAT/GP` is encrypted with key"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // header lines with other quotes, attribute order and spacing than those written
+        let non_canonical = test_util::read(test_util::SMALL_VCF)
+            .replace(
+                "##contig=<ID=20,length=62435964,assembly=B36,",
+                "##contig=<ID=20,assembly=\"B36\",length=62435964,",
+            )
+            .replace(
+                "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\">",
+                "##INFO=<ID=DP,Type=Integer,Number=1,Source=\"dbsnp\",Description=\"Total Depth\">",
+            )
+            .replace(
+                "##INFO=<ID=AA,Number=1,Type=String,Description=\"Ancestral Allele\">",
+                "##INFO=<ID=AA,Number=1,Type=String,Source=\"dbsnp\",Description=\"Ancestral Allele\">\n\
+                 ##META=<ID=Assay,Type=String,Number=.,Values=[WholeGenome, Exome]>",
+            );
+        for original in [
+            non_canonical.into_bytes(),
+            std::fs::read(test_util::SMALL_VCF).unwrap(),
+            std::fs::read(test_util::LARGE_VCF).unwrap(),
+        ] {
+            let key = Key::generate();
+            let fields = vec![
+                Field::Info("DP".to_string()),
+                Field::Info("AF".to_string()),
+                Field::Id,
+            ];
+            let mut encrypted = vec![];
+            Encryptor::new(key.clone(), fields)
+                .encrypt(VCFParser::new(original.as_slice()).unwrap(), &mut encrypted)
+                .unwrap();
+            assert_ne!(encrypted, original);
+
+            let mut decrypted = vec![];
+            Decryptor::new(key)
+                .decrypt(
+                    VCFParser::new(encrypted.as_slice()).unwrap(),
+                    &mut decrypted,
+                )
+                .unwrap();
+            assert!(
+                decrypted == original,
+                "{}",
+                String::from_utf8_lossy(&original[..1000])
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_preserving() {
+        let key = Key::generate();
+        let encryptor = Encryptor::with_mode(
+            key.clone(),
+            vec![
+                Field::Format("GP".to_string()),
+                Field::Info("DP".to_string()),
+            ],
+            Mode::FormatPreserving,
+        );
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        let lines: Vec<&str> = encrypted.lines().collect();
+
+        // field definitions are unchanged
+        let original: Vec<&str> = SMALL_VCF.lines().collect();
+        assert_eq!(&lines[..5], &original[..5]);
+        assert!(lines[6].starts_with(
+            "##ENCRYPTED=<ID=DP,Scope=INFO,OriginalType=Integer,OriginalNumber=1,Cipher=FF1-AES-256,"
+        ));
+
+        let first: Vec<&str> = lines[8].split('\t').collect();
+        assert!(first[7].strip_prefix("DP=").unwrap().parse::<i32>().is_ok());
+        let second: Vec<&str> = lines[9].split('\t').collect();
+        assert_eq!(second[9], "0/1:.");
+        let gp: Vec<&str> = second[10]
+            .strip_prefix("0/1:")
+            .unwrap()
+            .split(',')
+            .collect();
+        assert_eq!(gp.len(), 3);
+        assert!(gp.iter().all(|v| v.parse::<f32>().is_ok()));
+
+        let mut decrypted = vec![];
+        Decryptor::new(key)
+            .decrypt(
+                VCFParser::new(encrypted.as_bytes()).unwrap(),
+                &mut decrypted,
+            )
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+
+        // only numeric fields can be encrypted preserving their format
+        let encryptor = Encryptor::with_mode(
+            Key::generate(),
+            vec![Field::Format("GT".to_string())],
+            Mode::FormatPreserving,
+        );
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        assert!(encryptor.encrypt(parser, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let key = Key::generate();
+        let encryptor = Encryptor::with_mode(
+            key.clone(),
+            vec![Field::Id, Field::Info("DP".to_string())],
+            Mode::Deterministic,
+        );
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        assert!(encrypted.contains(&format!(
+            "\n##ENCRYPTED=<ID=ID,Scope=ID,Cipher=AES-256-GCM-SIV,KeyId={}>\n",
+            key.id()
+        )));
+
+        // the encrypted IDs match an encrypted ID list
+        let mut ids = vec![];
+        encrypt_id_list(&key, "rs84825\n\nrs11449\n".as_bytes(), &mut ids).unwrap();
+        let ids = String::from_utf8(ids).unwrap();
+        let ids: Vec<&str> = ids.lines().collect();
+        let lines: Vec<&str> = encrypted.lines().collect();
+        assert_eq!(lines[8].split('\t').nth(2).unwrap(), ids[1]);
+        assert_eq!(lines[9].split('\t').nth(2).unwrap(), ids[0]);
+
+        // other files encrypted with the same key give the same ciphertexts
+        let mut again = vec![];
+        Encryptor::with_mode(key.clone(), vec![Field::Id], Mode::Deterministic)
+            .encrypt(VCFParser::new(SMALL_VCF.as_bytes()).unwrap(), &mut again)
+            .unwrap();
+        let again = String::from_utf8(again).unwrap();
+        assert_eq!(
+            again.lines().nth(7).unwrap().split('\t').nth(2),
+            Some(ids[1])
+        );
+
+        let mut decrypted = vec![];
+        Decryptor::new(key)
+            .decrypt(
+                VCFParser::new(encrypted.as_bytes()).unwrap(),
+                &mut decrypted,
+            )
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+    }
+
+    #[test]
+    fn test_rekey() {
+        let (old_key, new_key) = (Key::generate(), Key::generate());
+        let encryptor = Encryptor::new(
+            old_key.clone(),
+            vec![
+                Field::Format("GP".to_string()),
+                Field::Info("DP".to_string()),
+            ],
+        );
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+
+        let mut rekeyed = vec![];
+        Rekeyer::new(old_key.clone(), new_key.clone())
+            .rekey(VCFParser::new(&encrypted[..]).unwrap(), &mut rekeyed)
+            .unwrap();
+        let parser = VCFParser::new(&rekeyed[..]).unwrap();
+        assert_eq!(parser.header.key_ids(), vec![new_key.id()]);
+
+        let mut decrypted = vec![];
+        Decryptor::new(new_key.clone())
+            .decrypt(parser, &mut decrypted)
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+        let parser = VCFParser::new(&rekeyed[..]).unwrap();
+        assert!(Decryptor::new(old_key.clone())
+            .decrypt(parser, vec![])
+            .is_err());
+
+        // the old key must be the one the file is encrypted with
+        let parser = VCFParser::new(&rekeyed[..]).unwrap();
+        assert!(Rekeyer::new(old_key.clone(), new_key.clone())
+            .rekey(parser, vec![])
+            .is_err());
+
+        // tampered records are not re-encrypted
+        let tampered = String::from_utf8(encrypted)
+            .unwrap()
+            .replace("1291018", "1291019");
+        let parser = VCFParser::new(tampered.as_bytes()).unwrap();
+        let error = Rekeyer::new(old_key, new_key)
+            .rekey(parser, vec![])
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("could not decrypt `INFO/DP` at 20:1291019"));
+    }
+
+    fn sample_keyring(samples: &[(&str, &Key)]) -> Keyring {
+        let mut keyring = Keyring::new();
+        for (sample, key) in samples {
+            keyring.insert(sample.to_string(), (*key).clone());
+        }
+        keyring
+    }
+
+    #[test]
+    fn test_sample_entries() {
+        let (first, second) = (Key::generate(), Key::generate());
+        let keyring = sample_keyring(&[("SAMP001", &first), ("SAMP002", &second)]);
+        let mut encrypted = vec![];
+        SampleEncryptor::new(keyring, SampleFields::All)
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        assert!(encrypted.contains(&format!(
+            "\n##ENCRYPTED=<ID=SAMP002,Scope=SAMPLE,Cipher=AES-256-GCM,KeyId={}>",
+            second.id()
+        )));
+        let lines: Vec<&str> = encrypted.lines().collect();
+        let record: Vec<&str> = lines[10].split('\t').collect();
+        assert_eq!(record[8], "GT:GP:ENC");
+        assert!(record[9].starts_with(".:.:"));
+        assert!(record[10].starts_with(".:.:"));
+
+        // only the second sample can be decrypted
+        let mut partial = vec![];
+        SampleDecryptor::new(sample_keyring(&[("SAMP002", &second)]))
+            .decrypt(VCFParser::new(encrypted.as_bytes()).unwrap(), &mut partial)
+            .unwrap();
+        let partial = String::from_utf8(partial).unwrap();
+        let lines: Vec<&str> = partial.lines().collect();
+        let record: Vec<&str> = lines[9].split('\t').collect();
+        assert_eq!(record[8], "GT:GP:ENC");
+        assert!(record[9].starts_with(".:.:"));
+        assert_eq!(record[10], "0/1:0.03,0.97,0:.");
+        let parser = VCFParser::new(partial.as_bytes()).unwrap();
+        assert!(parser.reader.into_iter().all(|dl| dl.is_ok()));
+
+        // then the first one
+        let mut decrypted = vec![];
+        SampleDecryptor::new(sample_keyring(&[("SAMP001", &first)]))
+            .decrypt(VCFParser::new(partial.as_bytes()).unwrap(), &mut decrypted)
+            .unwrap();
+        let decrypted = String::from_utf8(decrypted).unwrap();
+        assert!(!decrypted.contains("ENC"));
+        assert!(decrypted.ends_with("GT:GP\t0/1:.\t0/1:0.03,0.97,0"));
+
+        // both at once restore the file exactly
+        let mut decrypted = vec![];
+        SampleDecryptor::new(sample_keyring(&[("SAMP001", &first), ("SAMP002", &second)]))
+            .decrypt(
+                VCFParser::new(encrypted.as_bytes()).unwrap(),
+                &mut decrypted,
+            )
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+
+        // samples cannot be swapped
+        let swapped = sample_keyring(&[("SAMP001", &second)]);
+        let parser = VCFParser::new(encrypted.as_bytes()).unwrap();
+        assert!(SampleDecryptor::new(swapped)
+            .decrypt(parser, vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_sample_keys() {
+        let (first, second) = (Key::generate(), Key::generate());
+        let keyring = sample_keyring(&[("SAMP001", &first), ("SAMP002", &second)]);
+        let mut encrypted = vec![];
+        SampleEncryptor::new(keyring, SampleFields::Keys(vec!["GP".to_string()]))
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        let lines: Vec<&str> = encrypted.lines().collect();
+        assert_eq!(
+            lines[4],
+            "##FORMAT=<ID=GP,Number=.,Type=String,Description=\"Genotype Probabilities\">"
+        );
+        assert!(lines[5].ends_with(",Sample=SAMP001>"));
+        let record: Vec<&str> = lines[9].split('\t').collect();
+        let gp = record[10].strip_prefix("0/1:").unwrap();
+        let aad = b"FORMAT/GP:20:2300608:C:T:1";
+        assert_eq!(decrypt_value(&second, gp, aad).unwrap(), "0.03,0.97,0");
+        assert!(decrypt_value(&first, gp, aad).is_err());
+
+        // the field stays a string while the first sample is encrypted
+        let mut partial = vec![];
+        SampleDecryptor::new(sample_keyring(&[("SAMP002", &second)]))
+            .decrypt(VCFParser::new(encrypted.as_bytes()).unwrap(), &mut partial)
+            .unwrap();
+        let partial = String::from_utf8(partial).unwrap();
+        assert!(partial.contains("Number=.,Type=String,Description=\"Genotype Probabilities\""));
+        assert!(partial.ends_with("\t0/1:0.03,0.97,0"));
+
+        let mut decrypted = vec![];
+        SampleDecryptor::new(sample_keyring(&[("SAMP001", &first)]))
+            .decrypt(VCFParser::new(partial.as_bytes()).unwrap(), &mut decrypted)
+            .unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), SMALL_VCF);
+
+        // every sample needs a key
+        let keyring = sample_keyring(&[("SAMP001", &first)]);
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        assert!(SampleEncryptor::new(keyring, SampleFields::All)
+            .encrypt(parser, vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let key = Key::generate();
+        let encryptor = Encryptor::new(key.clone(), vec![Field::Format("GP".to_string())]);
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        // move the record to another position
+        let encrypted = String::from_utf8(encrypted)
+            .unwrap()
+            .replace("2300608", "2300609");
+        let parser = VCFParser::new(encrypted.as_bytes()).unwrap();
+        let error = Decryptor::new(key).decrypt(parser, vec![]).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("could not decrypt `FORMAT/GP` at 20:2300609"));
+    }
+
+    #[test]
+    fn test_swapped_values() {
+        // two records at the same position, e.g., split from a multiallelic one
+        let vcf = SMALL_VCF.replace(
+            "20\t2300608\trs84825\tC\tT\t.\tPASS\t.\tGT:GP\t0/1:.\t0/1:0.03,0.97,0",
+            "20\t1291018\trs11449\tG\tC\t.\tPASS\tDP=20\tGT\t0/1\t1/1",
+        );
+        let swap = |encrypted: &str, column: usize| {
+            let mut lines: Vec<Vec<String>> = encrypted
+                .lines()
+                .map(|l| l.split('\t').map(|c| c.to_string()).collect())
+                .collect();
+            let n = lines.len();
+            let first = lines[n - 2][column].clone();
+            lines[n - 2][column] = std::mem::replace(&mut lines[n - 1][column], first);
+            let lines: Vec<String> = lines.iter().map(|l| l.join("\t")).collect();
+            lines.join("\n")
+        };
+
+        let key = Key::generate();
+        let encryptor = Encryptor::new(key.clone(), vec![Field::Info("DP".to_string())]);
+        let mut encrypted = vec![];
+        let parser = VCFParser::new(vcf.as_bytes()).unwrap();
+        encryptor.encrypt(parser, &mut encrypted).unwrap();
+        let swapped = swap(&String::from_utf8(encrypted).unwrap(), 7);
+        let parser = VCFParser::new(swapped.as_bytes()).unwrap();
+        let error = Decryptor::new(key).decrypt(parser, vec![]).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("could not decrypt `INFO/DP` at 20:1291018"));
+
+        let key = Key::generate();
+        let keyring = sample_keyring(&[("SAMP001", &key), ("SAMP002", &key)]);
+        let mut encryptor = SampleEncryptor::new(keyring.clone(), SampleFields::All);
+        let mut encrypted = vec![];
+        let parser = VCFParser::new(vcf.as_bytes()).unwrap();
+        encryptor.encrypt(parser, &mut encrypted).unwrap();
+        let swapped = swap(&String::from_utf8(encrypted).unwrap(), 10);
+        let parser = VCFParser::new(swapped.as_bytes()).unwrap();
+        assert!(SampleDecryptor::new(keyring)
+            .decrypt(parser, vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_field() {
+        let encryptor = Encryptor::new(Key::generate(), vec![Field::Format("GT".to_string())]);
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        assert!(encryptor.encrypt(parser, vec![]).is_err());
+
+        let encryptor = Encryptor::new(Key::generate(), vec![Field::Info("AF".to_string())]);
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        assert!(encryptor.encrypt(parser, vec![]).is_err());
+
+        // fields cannot be encrypted twice
+        let encryptor = Encryptor::new(Key::generate(), vec![Field::Format("GP".to_string())]);
+        let mut encrypted = vec![];
+        encryptor
+            .encrypt(
+                VCFParser::new(SMALL_VCF.as_bytes()).unwrap(),
+                &mut encrypted,
+            )
+            .unwrap();
+        let parser = VCFParser::new(&encrypted[..]).unwrap();
+        assert!(encryptor.encrypt(parser, vec![]).is_err());
+    }
+}
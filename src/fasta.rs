@@ -0,0 +1,575 @@
+use crate::{
+    body::DataLine,
+    crypto::hex_encode,
+    header::{Header, HeaderLine},
+    parser::VCFParser,
+    region::Region,
+};
+use md5::{Digest, Md5};
+use std::{
+    fmt::{Display, Error, Formatter},
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
+};
+
+/// A line of a `.fai` index, compatible with `samtools faidx`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaiRecord {
+    pub name: String,
+
+    /// The number of bases of the sequence.
+    pub length: u64,
+
+    /// The offset in the file of the first base.
+    pub offset: u64,
+
+    /// The number of bases of each line.
+    pub line_bases: u64,
+
+    /// The number of bytes of each line, including the newline.
+    pub line_width: u64,
+}
+
+impl FromStr for FaiRecord {
+    type Err = anyhow::Error;
+
+    fn from_str(line_str: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid FASTA index line `{}`", line_str);
+        let columns: Vec<&str> = line_str.split('\t').collect();
+        if columns.len() != 5 {
+            return Err(invalid());
+        }
+        let number = |column: &str| column.parse::<u64>().map_err(|_| invalid());
+        let record = FaiRecord {
+            name: columns[0].to_string(),
+            length: number(columns[1])?,
+            offset: number(columns[2])?,
+            line_bases: number(columns[3])?,
+            line_width: number(columns[4])?,
+        };
+        // the offsets of the bases are computed line by line
+        if record.length > 0 && (record.line_bases == 0 || record.line_width <= record.line_bases) {
+            return Err(invalid());
+        }
+        Ok(record)
+    }
+}
+
+impl Display for FaiRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.name, self.length, self.offset, self.line_bases, self.line_width
+        )
+    }
+}
+
+/// The index of a FASTA file, one record per sequence, in file order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FastaIndex {
+    pub records: Vec<FaiRecord>,
+}
+
+impl FastaIndex {
+    /// Indexes a FASTA file. All the lines of a sequence, but the last, must have the same length.
+    pub fn build<R: BufRead>(mut reader: R) -> anyhow::Result<Self> {
+        let mut index = FastaIndex::default();
+        let mut line: Vec<u8> = vec![];
+        let mut offset = 0u64;
+        // whether the last line of the current sequence was shorter than the others
+        let mut short_line = false;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            offset += read;
+            let bases = line
+                .strip_suffix(b"\n")
+                .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+                .unwrap_or(&line);
+            if let Some(name) = bases.strip_prefix(b">") {
+                let name = String::from_utf8_lossy(name);
+                let name = name.split_whitespace().next().unwrap_or_default();
+                if name.is_empty() || index.record(name).is_some() {
+                    return Err(anyhow::anyhow!(
+                        "invalid FASTA sequence name `{}`, (empty or duplicate)",
+                        name
+                    ));
+                }
+                index.records.push(FaiRecord {
+                    name: name.to_string(),
+                    length: 0,
+                    offset,
+                    line_bases: 0,
+                    line_width: 0,
+                });
+                short_line = false;
+                continue;
+            }
+            let record = match index.records.last_mut() {
+                Some(record) => record,
+                None if bases.is_empty() => continue,
+                None => return Err(anyhow::anyhow!("FASTA sequence without a `>` name line")),
+            };
+            if bases.is_empty() {
+                short_line = true;
+                continue;
+            }
+            if record.line_bases == 0 {
+                record.line_bases = bases.len() as u64;
+                record.line_width = read;
+            } else if short_line || bases.len() as u64 > record.line_bases {
+                return Err(anyhow::anyhow!(
+                    "FASTA sequence `{}` has lines of different lengths",
+                    record.name
+                ));
+            }
+            short_line = (bases.len() as u64) < record.line_bases;
+            record.length += bases.len() as u64;
+        }
+        Ok(index)
+    }
+
+    /// Reads a `.fai` file.
+    pub fn read<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let mut records = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if !line.is_empty() {
+                records.push(line.parse()?);
+            }
+        }
+        Ok(FastaIndex { records })
+    }
+
+    pub fn record(&self, name: &str) -> Option<&FaiRecord> {
+        self.records.iter().find(|record| record.name == name)
+    }
+}
+
+impl Display for FastaIndex {
+    /// Formats the index as a `.fai` file.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for record in &self.records {
+            writeln!(f, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+/// A FASTA file with random access to its sequences through its index.
+#[derive(Debug)]
+pub struct IndexedFasta<R> {
+    reader: R,
+    index: FastaIndex,
+}
+
+impl IndexedFasta<File> {
+    /// Opens a FASTA file with its index, `<path>.fai`, which is created if missing.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let open = |path: &str| {
+            File::open(path)
+                .map_err(|e| anyhow::anyhow!("could not open FASTA file `{}`: {}", path, e))
+        };
+        let index_path = format!("{}.fai", path);
+        let index = if Path::new(&index_path).exists() {
+            FastaIndex::read(BufReader::new(open(&index_path)?))?
+        } else {
+            let index = FastaIndex::build(BufReader::new(open(path)?))?;
+            fs::write(&index_path, index.to_string()).map_err(|e| {
+                anyhow::anyhow!("could not write FASTA index `{}`: {}", index_path, e)
+            })?;
+            index
+        };
+        Ok(IndexedFasta::new(open(path)?, index))
+    }
+}
+
+impl<R: Read + Seek> IndexedFasta<R> {
+    pub fn new(reader: R, index: FastaIndex) -> Self {
+        IndexedFasta { reader, index }
+    }
+
+    pub fn index(&self) -> &FastaIndex {
+        &self.index
+    }
+
+    /// The bases of a region, uppercase. Regions past the end of the sequence are truncated.
+    pub fn fetch(&mut self, region: &Region) -> anyhow::Result<String> {
+        let record = self.index.record(&region.chromosome).ok_or_else(|| {
+            anyhow::anyhow!("sequence `{}` not found in FASTA file", region.chromosome)
+        })?;
+        if region.start == 0 {
+            return Err(anyhow::anyhow!(
+                "invalid region start 0 of sequence `{}`, positions start at 1",
+                region.chromosome
+            ));
+        }
+        let end = region.end.unwrap_or(record.length).min(record.length);
+        if region.start > end {
+            return Ok(String::new());
+        }
+        // the byte offset of a 0-based base position
+        let offset = |position: u64| {
+            record.offset
+                + position / record.line_bases * record.line_width
+                + position % record.line_bases
+        };
+        let start_offset = offset(region.start - 1);
+        let mut bytes = vec![0; (offset(end - 1) + 1 - start_offset) as usize];
+        self.reader.seek(SeekFrom::Start(start_offset))?;
+        self.reader.read_exact(&mut bytes)?;
+        bytes.retain(|b| !b.is_ascii_whitespace());
+        bytes.make_ascii_uppercase();
+        String::from_utf8(bytes)
+            .map_err(|_| anyhow::anyhow!("invalid UTF-8 in FASTA sequence `{}`", region.chromosome))
+    }
+
+    /// The MD5 of a whole sequence, as in the `md5` attribute of `##contig` lines. The sequence is
+    /// read a line at a time.
+    pub fn md5(&mut self, name: &str) -> anyhow::Result<String> {
+        let record = self
+            .index
+            .record(name)
+            .ok_or_else(|| anyhow::anyhow!("sequence `{}` not found in FASTA file", name))?;
+        let mut hasher = Md5::new();
+        let mut line = vec![0; record.line_bases as usize];
+        let mut remaining = record.length;
+        self.reader.seek(SeekFrom::Start(record.offset))?;
+        while remaining > 0 {
+            let bases = &mut line[..remaining.min(record.line_bases) as usize];
+            self.reader.read_exact(bases)?;
+            bases.make_ascii_uppercase();
+            hasher.update(&*bases);
+            remaining -= bases.len() as u64;
+            if remaining > 0 {
+                let newline = (record.line_width - record.line_bases) as i64;
+                self.reader.seek(SeekFrom::Current(newline))?;
+            }
+        }
+        Ok(hex_encode(&hasher.finalize()))
+    }
+}
+
+/// A difference between a VCF file and its reference sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReferenceIssue {
+    /// A `##contig` line or a data line of a sequence missing from the FASTA file.
+    MissingSequence(String),
+
+    ContigLength {
+        id: String,
+        declared: u64,
+        actual: u64,
+    },
+
+    ContigMd5 {
+        id: String,
+        declared: String,
+        actual: String,
+    },
+
+    /// A `REF` that does not match the reference sequence.
+    Reference {
+        chromosome: String,
+        position: u64,
+        reference: String,
+        expected: String,
+    },
+
+    /// A `REF` at position 0, i.e., a telomere, which has no reference base.
+    ReferenceBeforeStart {
+        chromosome: String,
+        reference: String,
+    },
+
+    /// A `REF` that ends past the end of the reference sequence.
+    ReferencePastEnd {
+        chromosome: String,
+        position: u64,
+        reference: String,
+        length: u64,
+    },
+}
+
+impl Display for ReferenceIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            ReferenceIssue::MissingSequence(name) => {
+                write!(f, "sequence `{}` not found in FASTA file", name)
+            }
+            ReferenceIssue::ContigLength {
+                id,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "contig `{}` has length {}, expected {}",
+                id, declared, actual
+            ),
+            ReferenceIssue::ContigMd5 {
+                id,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "contig `{}` has md5 {}, expected {}",
+                id, declared, actual
+            ),
+            ReferenceIssue::Reference {
+                chromosome,
+                position,
+                reference,
+                expected,
+            } => write!(
+                f,
+                "`{}:{}` has REF `{}`, expected `{}`",
+                chromosome, position, reference, expected
+            ),
+            ReferenceIssue::ReferenceBeforeStart {
+                chromosome,
+                reference,
+            } => write!(
+                f,
+                "`{}:0` has REF `{}` before the start of the sequence",
+                chromosome, reference
+            ),
+            ReferenceIssue::ReferencePastEnd {
+                chromosome,
+                position,
+                reference,
+                length,
+            } => write!(
+                f,
+                "`{}:{}` has REF `{}` past the end of the sequence, of length {}",
+                chromosome, position, reference, length
+            ),
+        }
+    }
+}
+
+/// Checks the `length` and `md5` attributes of the `##contig` lines against the FASTA file.
+pub fn check_contigs<R: Read + Seek>(
+    header: &Header,
+    fasta: &mut IndexedFasta<R>,
+) -> anyhow::Result<Vec<ReferenceIssue>> {
+    let mut issues = vec![];
+    for hl in &header.header_lines {
+        let (id, other) = match hl {
+            HeaderLine::Contig { id, other, .. } => (id, other),
+            _ => continue,
+        };
+        let actual = match fasta.index.record(id) {
+            Some(record) => record.length,
+            None => {
+                issues.push(ReferenceIssue::MissingSequence(id.clone()));
+                continue;
+            }
+        };
+        if let Some(length) = other.get("length") {
+            let declared = length
+                .parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("invalid length `{}` of contig `{}`", length, id))?;
+            if declared != actual {
+                issues.push(ReferenceIssue::ContigLength {
+                    id: id.clone(),
+                    declared,
+                    actual,
+                });
+            }
+        }
+        if let Some(declared) = other.get("md5") {
+            let actual = fasta.md5(id)?;
+            if !declared.eq_ignore_ascii_case(&actual) {
+                issues.push(ReferenceIssue::ContigMd5 {
+                    id: id.clone(),
+                    declared: declared.clone(),
+                    actual,
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+/// Checks the `REF` of a data line against the FASTA file, ignoring case.
+pub fn check_reference<R: Read + Seek>(
+    dl: &DataLine,
+    fasta: &mut IndexedFasta<R>,
+) -> anyhow::Result<Option<ReferenceIssue>> {
+    let length = match fasta.index.record(&dl.chromosome) {
+        Some(record) => record.length,
+        None => return Ok(Some(ReferenceIssue::MissingSequence(dl.chromosome.clone()))),
+    };
+    if dl.position == 0 {
+        return Ok(Some(ReferenceIssue::ReferenceBeforeStart {
+            chromosome: dl.chromosome.clone(),
+            reference: dl.reference.clone(),
+        }));
+    }
+    let end = dl.position + (dl.reference.len() as u64).saturating_sub(1);
+    if end > length {
+        return Ok(Some(ReferenceIssue::ReferencePastEnd {
+            chromosome: dl.chromosome.clone(),
+            position: dl.position,
+            reference: dl.reference.clone(),
+            length,
+        }));
+    }
+    let expected = fasta.fetch(&Region {
+        chromosome: dl.chromosome.clone(),
+        start: dl.position,
+        end: Some(end),
+    })?;
+    if expected.eq_ignore_ascii_case(&dl.reference) {
+        Ok(None)
+    } else {
+        Ok(Some(ReferenceIssue::Reference {
+            chromosome: dl.chromosome.clone(),
+            position: dl.position,
+            reference: dl.reference.clone(),
+            expected,
+        }))
+    }
+}
+
+/// Checks the `##contig` lines and the `REF` of every data line of the parser against the FASTA
+/// file. Missing sequences are reported once.
+pub fn check_references<B: BufRead, R: Read + Seek>(
+    parser: VCFParser<B>,
+    fasta: &mut IndexedFasta<R>,
+) -> anyhow::Result<Vec<ReferenceIssue>> {
+    let VCFParser { header, reader } = parser;
+    let mut issues = check_contigs(&header, fasta)?;
+    for dl in reader {
+        match check_reference(&dl?, fasta)? {
+            Some(issue) if issues.contains(&issue) => {}
+            Some(issue) => issues.push(issue),
+            None => {}
+        }
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fasta::*;
+    use std::io::Cursor;
+
+    const FASTA: &str = ">1 first sequence\nACGTACGTAC\nGTACGTACGT\nNNacg\n>2\r\nTTTT\r\nGG\r\n";
+
+    fn fasta() -> IndexedFasta<Cursor<&'static [u8]>> {
+        let index = FastaIndex::build(FASTA.as_bytes()).unwrap();
+        IndexedFasta::new(Cursor::new(FASTA.as_bytes()), index)
+    }
+
+    #[test]
+    fn test_index() {
+        let index = FastaIndex::build(FASTA.as_bytes()).unwrap();
+        assert_eq!(index.to_string(), "1\t25\t18\t10\t11\n2\t6\t50\t4\t6\n");
+        assert_eq!(
+            FastaIndex::read(index.to_string().as_bytes()).unwrap(),
+            index
+        );
+
+        assert!(FastaIndex::build(">1\nACG\nACGT\n".as_bytes()).is_err());
+        assert!(FastaIndex::build(">1\nACGT\nAC\nAC\n".as_bytes()).is_err());
+        assert!(FastaIndex::build(">1\nA\n>1\nA\n".as_bytes()).is_err());
+        assert!(FastaIndex::build("ACGT\n".as_bytes()).is_err());
+        assert!(FastaIndex::read("1\t25\t18\n".as_bytes()).is_err());
+        assert!(FastaIndex::read("1\t25\t18\t0\t0\n".as_bytes()).is_err());
+        assert!(FastaIndex::read("1\t25\t18\t10\t10\n".as_bytes()).is_err());
+        assert!(FastaIndex::read("1\t0\t18\t0\t0\n".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_fetch() {
+        let mut fasta = fasta();
+        let fetch = |fasta: &mut IndexedFasta<_>, region: &str| {
+            fasta.fetch(&Region::from_str(region).unwrap()).unwrap()
+        };
+        assert_eq!(fetch(&mut fasta, "1:1-4"), "ACGT");
+        assert_eq!(fetch(&mut fasta, "1:9-12"), "ACGT");
+        assert_eq!(fetch(&mut fasta, "1:20-100"), "TNNACG");
+        assert_eq!(fetch(&mut fasta, "1:30-40"), "");
+        assert_eq!(fetch(&mut fasta, "2"), "TTTTGG");
+        assert_eq!(fetch(&mut fasta, "2:4-5"), "TG");
+        assert!(fasta.fetch(&Region::chromosome("3")).is_err());
+        let mut region = Region::chromosome("1");
+        region.start = 0;
+        assert!(fasta.fetch(&region).is_err());
+        assert_eq!(fasta.md5("2").unwrap(), hex_encode(&Md5::digest(b"TTTTGG")));
+        assert_eq!(
+            fasta.md5("1").unwrap(),
+            hex_encode(&Md5::digest(b"ACGTACGTACGTACGTACGTNNACG"))
+        );
+        assert!(fasta.md5("3").is_err());
+    }
+
+    #[test]
+    fn test_check_references() {
+        let vcf = format!(
+            "##fileformat=VCFv4.3
+##contig=<ID=1,length=25>
+##contig=<ID=2,length=7,md5={}>
+##contig=<ID=3,length=10>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+1\t0\t.\tN\tA\t.\t.\t.
+1\t1\t.\tacg\tA\t.\t.\t.
+1\t5\t.\tT\tC\t.\t.\t.
+1\t24\t.\tCGT\tC\t.\t.\t.
+2\t5\t.\tGG\tG\t.\t.\t.
+3\t1\t.\tA\tG\t.\t.\t.
+3\t2\t.\tA\tG\t.\t.\t.",
+            "0".repeat(32)
+        );
+        let parser = VCFParser::new(vcf.as_bytes()).unwrap();
+        let issues = check_references(parser, &mut fasta()).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                ReferenceIssue::ContigLength {
+                    id: "2".to_string(),
+                    declared: 7,
+                    actual: 6
+                },
+                ReferenceIssue::ContigMd5 {
+                    id: "2".to_string(),
+                    declared: "0".repeat(32),
+                    actual: fasta().md5("2").unwrap()
+                },
+                ReferenceIssue::MissingSequence("3".to_string()),
+                ReferenceIssue::ReferenceBeforeStart {
+                    chromosome: "1".to_string(),
+                    reference: "N".to_string()
+                },
+                ReferenceIssue::Reference {
+                    chromosome: "1".to_string(),
+                    position: 5,
+                    reference: "T".to_string(),
+                    expected: "A".to_string()
+                },
+                ReferenceIssue::ReferencePastEnd {
+                    chromosome: "1".to_string(),
+                    position: 24,
+                    reference: "CGT".to_string(),
+                    length: 25
+                },
+            ]
+        );
+        assert_eq!(
+            issues[3].to_string(),
+            "`1:0` has REF `N` before the start of the sequence"
+        );
+        assert_eq!(issues[4].to_string(), "`1:5` has REF `T`, expected `A`");
+        assert_eq!(
+            issues[5].to_string(),
+            "`1:24` has REF `CGT` past the end of the sequence, of length 25"
+        );
+    }
+}
@@ -0,0 +1,732 @@
+use crate::{
+    body::{AltType, DataLine, FilterType, IdType, InfoType, QualType, SampleType},
+    header::{self, Header, HeaderLine},
+    ids::IdList,
+    parser::{IntoIter, VCFReader},
+};
+use regex::Regex;
+use std::{borrow::Cow, collections::HashSet, io::BufRead, iter::Peekable, str::Chars};
+
+/// How the values of a field are compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueType {
+    Number,
+    String,
+    Flag,
+}
+
+/// A field of a data line that an expression can refer to.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Chrom,
+    Pos,
+    Id,
+    Ref,
+    Alt,
+    Qual,
+    Filter,
+    Info { key: String, typ: ValueType },
+    Format { key: String, typ: ValueType },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug)]
+enum Operand {
+    /// A number or string literal. Numbers keep their text for comparisons with string fields.
+    Literal {
+        text: String,
+        number: Option<f64>,
+    },
+
+    /// The missing value `.`.
+    Missing,
+
+    Regex(Regex),
+
+    /// The entries of an ID file, e.g., `@ids.txt`.
+    Set(HashSet<String>),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+
+    /// A field on its own: a set flag or a non missing value.
+    Present(Field),
+
+    Compare {
+        field: Field,
+        operator: Operator,
+        operand: Operand,
+    },
+
+    /// Whether all, or any, samples satisfy the expression.
+    Samples {
+        all: bool,
+        expr: Box<Expr>,
+    },
+}
+
+/// A filter expression over the fields of data lines, in the style of bcftools, e.g.,
+/// `QUAL>20 && INFO/DP>10 && FILTER="PASS"`.
+///
+/// Fields are `CHROM`, `POS`, `ID`, `REF`, `ALT`, `QUAL`, `FILTER`, `INFO/KEY` and `FORMAT/KEY`
+/// (or `FMT/KEY`), and plain keys are looked up in the INFO and then the FORMAT definitions of
+/// the header, whose types decide whether values are compared as numbers or strings. Operators
+/// are `=` (or `==`), `!=`, `<`, `<=`, `>`, `>=`, `~` and `!~` for regular expressions, combined
+/// with `&&` (or `&`), `||` (or `|`), `!` and parentheses. A field with several values, e.g.,
+/// `INFO/AF` or `ALT`, matches if any of its values does, and `!=` and `!~` are the negations of
+/// `=` and `~`. `.` is the missing value and `ID=@ids.txt` tests membership in the IDs of a file.
+///
+/// A condition on FORMAT fields holds if it holds for any sample, and `all(...)` or `any(...)`
+/// evaluate a whole expression per sample, e.g., `all(FMT/DP>10 && FMT/GQ>20)`. As in bcftools,
+/// neither holds for a data line without samples.
+#[derive(Debug)]
+pub struct Expression {
+    expr: Expr,
+}
+
+impl Expression {
+    /// Compiles the expression, failing on syntax errors, unknown keys and values that do not
+    /// match the type of their field.
+    pub fn new(expr_str: &str, header: &Header) -> anyhow::Result<Self> {
+        let mut parser = ExprParser {
+            tokens: tokenize(expr_str)?.into_iter().peekable(),
+            header,
+            in_samples: false,
+        };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.tokens.next() {
+            return Err(anyhow::anyhow!(
+                "invalid filter expression `{}`, (unexpected `{}`)",
+                expr_str,
+                token
+            ));
+        }
+        Ok(Expression { expr })
+    }
+
+    pub fn matches(&self, dl: &DataLine) -> bool {
+        self.expr.eval(dl, None)
+    }
+}
+
+impl Expr {
+    fn eval(&self, dl: &DataLine, sample: Option<usize>) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(dl, sample) && right.eval(dl, sample),
+            Expr::Or(left, right) => left.eval(dl, sample) || right.eval(dl, sample),
+            Expr::Not(expr) => !expr.eval(dl, sample),
+            Expr::Present(field) | Expr::Compare { field, .. }
+                if sample.is_none() && matches!(field, Field::Format { .. }) =>
+            {
+                (0..dl.samples.len()).any(|i| self.eval(dl, Some(i)))
+            }
+            Expr::Present(field) => field.values(dl, sample).is_some(),
+            Expr::Compare {
+                field,
+                operator,
+                operand,
+            } => compare(field.typ(), field.values(dl, sample), *operator, operand),
+            Expr::Samples { all: true, expr } => {
+                !dl.samples.is_empty() && (0..dl.samples.len()).all(|i| expr.eval(dl, Some(i)))
+            }
+            Expr::Samples { all: false, expr } => {
+                (0..dl.samples.len()).any(|i| expr.eval(dl, Some(i)))
+            }
+        }
+    }
+}
+
+impl Field {
+    fn typ(&self) -> ValueType {
+        match self {
+            Field::Pos | Field::Qual => ValueType::Number,
+            Field::Info { typ, .. } | Field::Format { typ, .. } => *typ,
+            _ => ValueType::String,
+        }
+    }
+
+    /// Returns the values of the field, or `None` if it is missing. Set flags have the value `1`.
+    /// Bytes of INFO and FORMAT values that are not valid UTF-8 are replaced with `U+FFFD`, so
+    /// such values can still be compared.
+    fn values<'a>(&self, dl: &'a DataLine, sample: Option<usize>) -> Option<Vec<Cow<'a, str>>> {
+        let values: Vec<Cow<str>> = match self {
+            Field::Chrom => vec![Cow::from(&dl.chromosome)],
+            Field::Pos => vec![Cow::from(dl.position.to_string())],
+            Field::Id => match &dl.id {
+                IdType::Entries(entries) => entries.iter().map(Cow::from).collect(),
+                IdType::Missing => vec![],
+            },
+            Field::Ref => vec![Cow::from(&dl.reference)],
+            Field::Alt => match &dl.alternative {
+                AltType::Entries(entries) => entries.iter().map(Cow::from).collect(),
+                AltType::Missing => vec![],
+            },
+            Field::Qual => match &dl.quality {
+                QualType::Integer(q) => vec![Cow::from(q.to_string())],
+                QualType::Missing => vec![],
+            },
+            Field::Filter => match &dl.filter {
+                FilterType::Pass => vec![Cow::from("PASS")],
+                FilterType::Entries(entries) => entries.iter().map(Cow::from).collect(),
+                FilterType::Missing => vec![],
+            },
+            Field::Info { key, typ } => match &dl.info {
+                InfoType::Entries(entries) => entries
+                    .iter()
+                    .filter_map(|e| {
+                        let e = e.as_bytes();
+                        match e.iter().position(|b| *b == b'=') {
+                            Some(index) if &e[..index] == key.as_bytes() => Some(&e[index + 1..]),
+                            None if e == key.as_bytes() && *typ == ValueType::Flag => Some(b"1"),
+                            _ => None,
+                        }
+                    })
+                    .flat_map(|v| v.split(|b| *b == b','))
+                    .map(String::from_utf8_lossy)
+                    .collect(),
+                InfoType::Missing => vec![],
+            },
+            Field::Format { key, .. } => {
+                match (dl.format_index(key), sample.and_then(|i| dl.samples.get(i))) {
+                    (Some(index), Some(SampleType::Entries(entries))) => match entries.get(index) {
+                        Some(entry) => entry
+                            .as_bytes()
+                            .split(|b| *b == b',')
+                            .map(String::from_utf8_lossy)
+                            .collect(),
+                        None => vec![],
+                    },
+                    _ => vec![],
+                }
+            }
+        };
+        let values: Vec<Cow<str>> = values.into_iter().filter(|v| v != ".").collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values)
+        }
+    }
+}
+
+fn compare(
+    typ: ValueType,
+    values: Option<Vec<Cow<str>>>,
+    operator: Operator,
+    operand: &Operand,
+) -> bool {
+    let values = match (operator, operand) {
+        (Operator::Ne, _) => return !compare(typ, values, Operator::Eq, operand),
+        (Operator::NotMatch, _) => return !compare(typ, values, Operator::Match, operand),
+        (_, Operand::Missing) => return values.is_none(),
+        (_, _) => match values {
+            Some(values) => values,
+            None => return false,
+        },
+    };
+    values.iter().any(|value| match operand {
+        Operand::Set(set) => set.contains(value.as_ref()),
+        Operand::Regex(re) => re.is_match(value),
+        Operand::Literal { text, number } => {
+            let ordering = match (typ, number) {
+                (ValueType::Number, Some(number)) => value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|value| value.partial_cmp(number)),
+                _ => Some(value.as_ref().cmp(text.as_str())),
+            };
+            ordering.is_some_and(|ordering| match operator {
+                Operator::Eq => ordering.is_eq(),
+                Operator::Lt => ordering.is_lt(),
+                Operator::Le => ordering.is_le(),
+                Operator::Gt => ordering.is_gt(),
+                Operator::Ge => ordering.is_ge(),
+                _ => false,
+            })
+        }
+        Operand::Missing => false,
+    })
+}
+
+/// A token of a filter expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A field, a number or the missing value `.`.
+    Word(String),
+    String(String),
+    File(String),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(s) => write!(f, "{}", s),
+            Token::String(s) => write!(f, "\"{}\"", s),
+            Token::File(s) => write!(f, "@{}", s),
+            Token::Operator(operator) => write!(f, "{:?}", operator),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '/' | '.' | '-' | '+')
+}
+
+fn take_while(chars: &mut Peekable<Chars>, predicate: fn(char) -> bool) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.peek() {
+        if !predicate(*c) {
+            break;
+        }
+        word.push(*c);
+        chars.next();
+    }
+    word
+}
+
+fn tokenize(expr_str: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expr_str.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let mut next_is = |c: char| chars.next_if_eq(&c).is_some();
+        let token = match ch {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' => {
+                next_is('&');
+                Token::And
+            }
+            '|' => {
+                next_is('|');
+                Token::Or
+            }
+            '=' => {
+                next_is('=');
+                Token::Operator(Operator::Eq)
+            }
+            '!' if next_is('=') => Token::Operator(Operator::Ne),
+            '!' if next_is('~') => Token::Operator(Operator::NotMatch),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Operator(Operator::Le),
+            '<' => Token::Operator(Operator::Lt),
+            '>' if next_is('=') => Token::Operator(Operator::Ge),
+            '>' => Token::Operator(Operator::Gt),
+            '~' => Token::Operator(Operator::Match),
+            '"' | '\'' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(c) => string.push(c),
+                            None => break,
+                        },
+                        Some(c) if c == ch => break,
+                        Some(c) => string.push(c),
+                        None => {
+                            return Err(anyhow::anyhow!(
+                                "invalid filter expression `{}`, (unbalanced quote)",
+                                expr_str
+                            ))
+                        }
+                    }
+                }
+                Token::String(string)
+            }
+            '@' => {
+                let path = take_while(&mut chars, |c| {
+                    !c.is_whitespace() && !matches!(c, '(' | ')' | '&' | '|')
+                });
+                if path.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "invalid filter expression `{}`, (`@` without a file)",
+                        expr_str
+                    ));
+                }
+                Token::File(path)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                word.push_str(&take_while(&mut chars, is_word_char));
+                Token::Word(word)
+            }
+            c => {
+                return Err(anyhow::anyhow!(
+                    "invalid filter expression `{}`, (unexpected `{}`)",
+                    expr_str,
+                    c
+                ))
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of an expression. `||` binds looser than `&&`.
+struct ExprParser<'a> {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+    header: &'a Header,
+    in_samples: bool,
+}
+
+impl<'a> ExprParser<'a> {
+    fn parse_or(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> anyhow::Result<Expr> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => self.parse_group(),
+            Some(Token::Word(word)) if word == "all" || word == "any" => {
+                if self.tokens.next_if_eq(&Token::Open).is_none() {
+                    return Err(anyhow::anyhow!(
+                        "invalid filter expression, (`{}` must be followed by `(`)",
+                        word
+                    ));
+                }
+                if self.in_samples {
+                    return Err(anyhow::anyhow!(
+                        "invalid filter expression, (nested `{}`)",
+                        word
+                    ));
+                }
+                self.in_samples = true;
+                let expr = self.parse_group()?;
+                self.in_samples = false;
+                Ok(Expr::Samples {
+                    all: word == "all",
+                    expr: Box::new(expr),
+                })
+            }
+            Some(Token::Word(word)) => self.parse_comparison(&word),
+            Some(token) => Err(anyhow::anyhow!(
+                "invalid filter expression, (unexpected `{}`)",
+                token
+            )),
+            None => Err(anyhow::anyhow!(
+                "invalid filter expression, (unexpected end)"
+            )),
+        }
+    }
+
+    fn parse_group(&mut self) -> anyhow::Result<Expr> {
+        let expr = self.parse_or()?;
+        match self.tokens.next() {
+            Some(Token::Close) => Ok(expr),
+            _ => Err(anyhow::anyhow!(
+                "invalid filter expression, (unbalanced `(`)"
+            )),
+        }
+    }
+
+    fn parse_comparison(&mut self, word: &str) -> anyhow::Result<Expr> {
+        let field = self.parse_field(word)?;
+        let operator = match self.tokens.peek() {
+            Some(Token::Operator(operator)) => *operator,
+            _ => return Ok(Expr::Present(field)),
+        };
+        self.tokens.next();
+        if field.typ() == ValueType::Flag {
+            return Err(anyhow::anyhow!(
+                "invalid filter expression, (flag `{}` cannot be compared)",
+                word
+            ));
+        }
+        let operand = match (self.tokens.next(), operator) {
+            (Some(Token::Word(w)), Operator::Eq | Operator::Ne) if w == "." => Operand::Missing,
+            (Some(Token::String(s)), Operator::Match | Operator::NotMatch) => Operand::Regex(
+                Regex::new(&s).map_err(|e| anyhow::anyhow!("invalid regular expression: {}", e))?,
+            ),
+            (Some(Token::File(path)), Operator::Eq | Operator::Ne) => Operand::Set(
+                IdList::load(&path)?
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect(),
+            ),
+            (Some(Token::Word(text)), _) | (Some(Token::String(text)), _)
+                if !matches!(operator, Operator::Match | Operator::NotMatch) =>
+            {
+                let number = text.parse::<f64>().ok();
+                if field.typ() == ValueType::Number && number.is_none() {
+                    return Err(anyhow::anyhow!(
+                        "invalid filter expression, (`{}` is not a number)",
+                        text
+                    ));
+                }
+                Operand::Literal { text, number }
+            }
+            (token, _) => {
+                return Err(anyhow::anyhow!(
+                    "invalid filter expression, (invalid value {} for `{}`)",
+                    token.map_or("end".to_string(), |t| format!("`{}`", t)),
+                    word
+                ))
+            }
+        };
+        Ok(Expr::Compare {
+            field,
+            operator,
+            operand,
+        })
+    }
+
+    fn parse_field(&self, word: &str) -> anyhow::Result<Field> {
+        let field = match word {
+            "CHROM" => Field::Chrom,
+            "POS" => Field::Pos,
+            "ID" => Field::Id,
+            "REF" => Field::Ref,
+            "ALT" => Field::Alt,
+            "QUAL" => Field::Qual,
+            "FILTER" => Field::Filter,
+            _ => {
+                if let Some(key) = word.strip_prefix("INFO/") {
+                    info_field(self.header, key)
+                        .ok_or_else(|| anyhow::anyhow!("unknown INFO key `{}`", key))?
+                } else if let Some(key) = word
+                    .strip_prefix("FORMAT/")
+                    .or_else(|| word.strip_prefix("FMT/"))
+                {
+                    format_field(self.header, key)
+                        .ok_or_else(|| anyhow::anyhow!("unknown FORMAT key `{}`", key))?
+                } else {
+                    info_field(self.header, word)
+                        .or_else(|| format_field(self.header, word))
+                        .ok_or_else(|| anyhow::anyhow!("unknown field `{}`", word))?
+                }
+            }
+        };
+        Ok(field)
+    }
+}
+
+fn info_field(header: &Header, key: &str) -> Option<Field> {
+    header.header_lines.iter().find_map(|hl| match hl {
+        HeaderLine::Info { id, typ, .. } if id == key => Some(Field::Info {
+            key: key.to_string(),
+            typ: match typ {
+                header::InfoType::Integer | header::InfoType::Float => ValueType::Number,
+                header::InfoType::Flag => ValueType::Flag,
+                header::InfoType::Character | header::InfoType::String => ValueType::String,
+            },
+        }),
+        _ => None,
+    })
+}
+
+fn format_field(header: &Header, key: &str) -> Option<Field> {
+    header.header_lines.iter().find_map(|hl| match hl {
+        HeaderLine::Format { id, typ, .. } if id == key => Some(Field::Format {
+            key: key.to_string(),
+            typ: match typ {
+                header::FormatType::Integer | header::FormatType::Float => ValueType::Number,
+                header::FormatType::Character | header::FormatType::String => ValueType::String,
+            },
+        }),
+        _ => None,
+    })
+}
+
+/// An iterator over the data lines that an expression includes, or excludes. Errors are passed
+/// through.
+pub struct FilteredLines<I> {
+    lines: I,
+    expression: Expression,
+    include: bool,
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> FilteredLines<I> {
+    pub fn new(lines: I, expression: Expression, include: bool) -> Self {
+        FilteredLines {
+            lines,
+            expression,
+            include,
+        }
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> Iterator for FilteredLines<I> {
+    type Item = anyhow::Result<DataLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(dl) if self.expression.matches(&dl) != self.include => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> VCFReader<R> {
+    /// Returns the data lines that match the expression, like `bcftools view -i`.
+    pub fn include(self, expression: Expression) -> FilteredLines<IntoIter<R>> {
+        FilteredLines::new(self.into_iter(), expression, true)
+    }
+
+    /// Returns the data lines that do not match the expression, like `bcftools view -e`.
+    pub fn exclude(self, expression: Expression) -> FilteredLines<IntoIter<R>> {
+        FilteredLines::new(self.into_iter(), expression, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        filter::*,
+        parser::VCFParser,
+        test_util::{parser, read, LARGE_VCF, SMALL_VCF},
+    };
+
+    fn positions(expr_str: &str, include: bool) -> anyhow::Result<Vec<u64>> {
+        let parser = parser(SMALL_VCF);
+        let expression = Expression::new(expr_str, &parser.header)?;
+        let lines = if include {
+            parser.reader.include(expression)
+        } else {
+            parser.reader.exclude(expression)
+        };
+        Ok(lines.map(|dl| dl.unwrap().position).collect())
+    }
+
+    #[test]
+    fn test_filter() {
+        let include = |expr_str| positions(expr_str, true).unwrap();
+        assert_eq!(include("QUAL>20 && INFO/DP>10"), vec![14370, 1230237]);
+        assert_eq!(include("QUAL<=3 || AA=\"G\""), vec![17330, 1234567]);
+        assert_eq!(
+            include("FILTER=\"PASS\""),
+            vec![14370, 1110696, 1230237, 1234567]
+        );
+        assert_eq!(include("FILTER!=\"PASS\""), vec![17330]);
+        assert_eq!(include("DB"), vec![14370, 1110696]);
+        assert_eq!(include("!DB && DP>10"), vec![17330, 1230237]);
+        assert_eq!(include("AF>0.2 && AF<0.4"), vec![1110696]);
+        assert_eq!(include("ALT=\"T\""), vec![1110696]);
+        assert_eq!(include("ID=."), vec![17330, 1230237]);
+        assert_eq!(include("ID~\"^rs60\""), vec![14370, 1110696]);
+        assert_eq!(include("ID!~\"^rs60\""), vec![17330, 1230237, 1234567]);
+        assert_eq!(
+            include("CHROM=20 && (POS<15000 || POS>=1230000)"),
+            vec![14370, 1230237, 1234567]
+        );
+        assert_eq!(include("INFO/AA=."), vec![14370, 17330]);
+        assert_eq!(
+            positions("DP>12", false).unwrap(),
+            vec![17330, 1110696, 1234567]
+        );
+    }
+
+    #[test]
+    fn test_samples() {
+        let include = |expr_str| positions(expr_str, true).unwrap();
+        assert_eq!(include("FMT/DP>6"), vec![14370, 1230237]);
+        assert_eq!(include("all(FMT/DP>2)"), vec![17330]);
+        assert_eq!(include("any(FMT/GT=\"1|0\" && FMT/DP<10)"), vec![14370]);
+        assert_eq!(include("FORMAT/GT=\"2/2\""), vec![1110696]);
+        assert_eq!(include("!any(FMT/DP>6)"), vec![17330, 1110696, 1234567]);
+    }
+
+    #[test]
+    fn test_no_samples() {
+        let sites_only: Vec<String> = read(SMALL_VCF)
+            .lines()
+            .map(|line| match line.starts_with("##") {
+                true => line.to_string(),
+                false => line.split('\t').take(8).collect::<Vec<_>>().join("\t"),
+            })
+            .collect();
+        let sites_only = sites_only.join("\n");
+        let matches = |expr_str: &str| {
+            let parser = VCFParser::new(sites_only.as_bytes()).unwrap();
+            let expression = Expression::new(expr_str, &parser.header).unwrap();
+            let dl = parser.reader.into_iter().next().unwrap().unwrap();
+            assert!(dl.samples.is_empty());
+            expression.matches(&dl)
+        };
+        assert!(!matches("all(FMT/DP>10)"));
+        assert!(!matches("any(FMT/DP>10)"));
+        assert!(!matches("FMT/DP>10"));
+        assert!(matches("!all(FMT/DP>10)"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for expr_str in &[
+            "",
+            "QUAL>",
+            "QUAL>abc",
+            "(QUAL>1",
+            "QUAL>1)",
+            "XX=1",
+            "INFO/GT=1",
+            "DB=1",
+            "ID~\"(\"",
+            "ID=\"rs1",
+            "QUAL>1 &&",
+            "all(any(FMT/DP>1))",
+            "ID=@missing.txt",
+            "QUAL # 1",
+        ] {
+            assert!(positions(expr_str, true).is_err(), "{}", expr_str);
+        }
+    }
+
+    #[test]
+    fn test_id_file() {
+        let count = |expr_str: &str, include: bool| {
+            let parser = parser(LARGE_VCF);
+            let expression = Expression::new(expr_str, &parser.header).unwrap();
+            if include {
+                parser.reader.include(expression).count()
+            } else {
+                parser.reader.exclude(expression).count()
+            }
+        };
+        let total = count("POS>0", true);
+        assert!(total > 0);
+        assert_eq!(count("ID=@test/resources/valid/ids.txt", true), 0);
+        assert_eq!(count("ID=@test/resources/valid/ids.txt", false), total);
+        assert_eq!(count("ID=\"rs565014200\"", true), 1);
+    }
+}
@@ -1,12 +1,53 @@
+use crate::text::{checked_str, Text};
 use linked_hash_map::LinkedHashMap;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Display, Error, Formatter},
+    io::{self, Write},
     str::FromStr,
 };
 
 pub const OTHER_KEY: &str = "Value";
 
+/// The attributes whose values are written quoted, as in the examples of the specification.
+const QUOTED_KEYS: &[&str] = &["Description", "Source", "Version", "species"];
+
+/// The keys of `##FILTER`, `##FORMAT` and `##INFO` header lines that are not stored in their
+/// `other` map.
+const FILTER_KEYS: &[&str] = &["ID", "Description"];
+const FORMAT_KEYS: &[&str] = &["ID", "Number", "Type", "Description"];
+const INFO_KEYS: &[&str] = &["ID", "Number", "Type", "Description", "Source", "Version"];
+
+/// The header lines parsed into their own variant, whose values are checked to be valid UTF-8.
+const HEADER_TYPES: &[&str] = &[
+    "ALT",
+    "assembly",
+    "contig",
+    "ENCRYPTED",
+    "fileDate",
+    "FILTER",
+    "FORMAT",
+    "INFO",
+    "META",
+    "MERKLE",
+    "PEDIGREE",
+    "pedigreeDB",
+    "SAMPLE",
+];
+
+/// The header lines with a free text `Description`, kept as bytes.
+const DESCRIBED_TYPES: &[&str] = &["ALT", "FILTER", "FORMAT", "INFO", "SAMPLE"];
+
+/// The keys of `##ENCRYPTED` header lines that are not stored in their `other` map.
+const ENCRYPTED_KEYS: &[&str] = &[
+    "ID",
+    "Scope",
+    "OriginalType",
+    "OriginalNumber",
+    "Cipher",
+    "KeyId",
+];
+
 /// The header of the VCF file
 #[derive(Debug)]
 pub struct Header {
@@ -18,6 +59,20 @@ pub struct Header {
 
     /// The additional column names not containing the expected fixed columns.
     pub column_names: Vec<String>,
+
+    /// The header lines read in another form than the one `HeaderLine::write_to` writes, e.g.,
+    /// with other quotes or attribute order, see `write_lines`.
+    original_lines: Vec<OriginalLine>,
+}
+
+/// The header lines read, by key and ID, e.g., `INFO` and `DP`.
+type LinesByKeyId<'a> = HashMap<(Vec<u8>, Vec<u8>), VecDeque<&'a [u8]>>;
+
+/// A header line as read, along with the form written by `HeaderLine::write_to`.
+#[derive(Debug)]
+struct OriginalLine {
+    read: Vec<u8>,
+    written: Vec<u8>,
 }
 
 impl Header {
@@ -26,26 +81,132 @@ impl Header {
             version: Version { value: version },
             header_lines,
             column_names,
+            original_lines: vec![],
+        }
+    }
+
+    /// Keeps the bytes a header line was parsed from, so that `write_lines` writes them as they
+    /// were read while the line is unchanged.
+    pub fn keep_original(&mut self, read: &[u8], hl: &HeaderLine) {
+        let mut written = vec![];
+        let _ = hl.write_to(&mut written);
+        if written != read {
+            self.original_lines.push(OriginalLine {
+                read: read.to_vec(),
+                written,
+            });
+        }
+    }
+
+    /// Writes the `##fileformat` line and the header lines, each followed by a newline. A header
+    /// line unchanged since it was read is written as read. A changed one, e.g., an INFO line
+    /// whose type was restored by decryption, keeps the layout of the line read with the same key
+    /// and ID, see `write_in_layout`.
+    pub fn write_lines<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut unchanged: HashMap<&[u8], VecDeque<&[u8]>> = HashMap::new();
+        let mut by_id: LinesByKeyId<'_> = HashMap::new();
+        for original in &self.original_lines {
+            unchanged
+                .entry(&original.written)
+                .or_default()
+                .push_back(&original.read);
+            if let Some((key, id)) = line_key_id(&original.read) {
+                by_id
+                    .entry((key.to_vec(), id.to_vec()))
+                    .or_default()
+                    .push_back(&original.read);
+            }
+        }
+
+        writeln!(writer, "{}", self.version)?;
+        for hl in &self.header_lines {
+            let mut written = vec![];
+            hl.write_to(&mut written)?;
+            if let Some(read) = unchanged
+                .get_mut(written.as_slice())
+                .and_then(VecDeque::pop_front)
+            {
+                writer.write_all(read)?;
+            } else if let Some(read) = line_key_id(&written)
+                .and_then(|(key, id)| by_id.get_mut(&(key.to_vec(), id.to_vec())))
+                .and_then(VecDeque::pop_front)
+            {
+                write_in_layout(writer, read, &written)?;
+            } else {
+                writer.write_all(&written)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the values of the given field are encrypted, according to the `##ENCRYPTED`
+    /// header lines.
+    pub fn is_encrypted(&self, scope: &EncryptionScope, field_id: &str) -> bool {
+        self.header_lines.iter().any(|hl| match hl {
+            HeaderLine::Encrypted { id, scope: s, .. } => s == scope && id == field_id,
+            _ => false,
+        })
+    }
+
+    /// The `##ALT` line declaring the IDs of a symbolic allele or, failing that, the one declaring
+    /// the longest prefix of them, e.g., `DEL:ME` for `<DEL:ME:ALU>`.
+    pub fn alt_line(&self, ids: &[AltId]) -> Option<&HeaderLine> {
+        (1..=ids.len()).rev().find_map(|len| {
+            self.header_lines.iter().find(|hl| match hl {
+                HeaderLine::Alt { id, .. } => id.as_slice() == &ids[..len],
+                _ => false,
+            })
+        })
+    }
+
+    /// The distinct ids of the keys used to encrypt fields of the file.
+    pub fn key_ids(&self) -> Vec<&str> {
+        let mut key_ids: Vec<&str> = vec![];
+        for hl in &self.header_lines {
+            if let HeaderLine::Encrypted { key_id, .. } = hl {
+                if !key_ids.contains(&key_id.as_str()) {
+                    key_ids.push(key_id);
+                }
+            }
         }
+        key_ids
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Version {
     pub value: String,
 }
 
+impl Version {
+    /// The major and minor version numbers, e.g., `(4, 3)` for `VCFv4.3`.
+    pub fn number(&self) -> Option<(u32, u32)> {
+        let (major, minor) = self.value.strip_prefix("VCFv")?.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+}
+
+impl Default for Version {
+    /// `VCFv4.3`, for data lines read without a header.
+    fn default() -> Self {
+        Version {
+            value: "VCFv4.3".to_string(),
+        }
+    }
+}
+
 impl Display for Version {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "##fileformat={}", self.value)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum HeaderLine {
     /// Example:
     /// ##ALT=<ID=type,Description=description>
-    Alt { id: Vec<AltId>, description: String },
+    Alt { id: Vec<AltId>, description: Text },
 
     /// Example:
     /// ##assembly=ftp://ftp-trace.ncbi.nih.gov/1000genomes
@@ -55,7 +216,22 @@ pub enum HeaderLine {
     /// ##contig=<ID=ctg1,length=81195210,species="Homo sapiens",URL=ftp://somewhere.org/assembly.fa,>
     Contig {
         id: String,
-        species: Option<String>,
+
+        /// The attributes other than `ID`, e.g., `length` or `species`, in file order, see
+        /// `HeaderLine::species`.
+        other: LinkedHashMap<String, String>,
+    },
+
+    /// Describes a field whose values are encrypted, along with its original definition.
+    /// Example:
+    /// ##ENCRYPTED=<ID=GP,Scope=FORMAT,OriginalType=Float,OriginalNumber=G,Cipher=AES-256-GCM,KeyId=9f86d081884c7d65>
+    Encrypted {
+        id: String,
+        scope: EncryptionScope,
+        original_type: Option<String>,
+        original_number: Option<Number>,
+        cipher: String,
+        key_id: String,
         other: LinkedHashMap<String, String>,
     },
 
@@ -65,7 +241,13 @@ pub enum HeaderLine {
 
     /// Example:
     /// ##FILTER=<ID=ID,Description="description">
-    Filter { id: String, description: String },
+    Filter {
+        id: String,
+        description: Text,
+
+        /// Any other attribute, e.g., the `IDX` of a header read from BCF.
+        other: LinkedHashMap<String, String>,
+    },
 
     /// Example:
     /// ##FORMAT=<ID=ID,Number=number,Type=type,Description="description">
@@ -73,7 +255,8 @@ pub enum HeaderLine {
         id: String,
         number: Number,
         typ: FormatType,
-        description: String,
+        description: Text,
+        other: LinkedHashMap<String, String>,
     },
 
     /// Example:
@@ -82,9 +265,10 @@ pub enum HeaderLine {
         id: String,
         number: Number,
         typ: InfoType,
-        description: String,
+        description: Text,
         source: Option<String>,
         version: Option<String>,
+        other: LinkedHashMap<String, String>,
     },
 
     /// Example:
@@ -98,6 +282,15 @@ pub enum HeaderLine {
         values: Vec<String>,
     },
 
+    /// The root of the Merkle tree over the data lines of a contig, written after the data lines.
+    /// Example:
+    /// ##MERKLE=<ID=20,Records=3,Root=1b4f0e9851971998e732078544c96b36c3d01cedf7caa332359d6f1d83567014>
+    Merkle {
+        id: String,
+        records: usize,
+        root: String,
+    },
+
     /// Example:
     /// ##PEDIGREE=<ID=TumourSample,Original=GermlineID>
     /// ##PEDIGREE=<ID=ChildID,Father=FatherID,Mother=MotherID>
@@ -110,7 +303,8 @@ pub enum HeaderLine {
 
     /// Example:
     /// ##reference=1000GenomesPilot-NCBI36
-    Other { key: String, value: String },
+    /// ##GATKCommandLine=<ID=HaplotypeCaller,Version=4.1>
+    Other { key: String, value: Text },
 
     /// Example:
     /// ##SAMPLE=<ID=Sample1,Assay=WholeGenome,Ethnicity=AFR,Disease=None,Description="Patient germline genome",DOI=url>
@@ -118,20 +312,49 @@ pub enum HeaderLine {
     Sample {
         id: String,
         meta: LinkedHashMap<String, Vec<String>>,
-        description: String,
+        description: Text,
         doi: Option<String>,
     },
 }
 
-impl FromStr for HeaderLine {
-    type Err = anyhow::Error;
+impl HeaderLine {
+    /// The key of the header line, e.g., `INFO` for `##INFO=<...>` or `reference` for
+    /// `##reference=...`.
+    pub fn key(&self) -> &str {
+        match self {
+            HeaderLine::Alt { .. } => "ALT",
+            HeaderLine::Assembly(_) => "assembly",
+            HeaderLine::Contig { .. } => "contig",
+            HeaderLine::Encrypted { .. } => "ENCRYPTED",
+            HeaderLine::FileDate(_) => "fileDate",
+            HeaderLine::Filter { .. } => "FILTER",
+            HeaderLine::Format { .. } => "FORMAT",
+            HeaderLine::Info { .. } => "INFO",
+            HeaderLine::Meta { .. } => "META",
+            HeaderLine::Merkle { .. } => "MERKLE",
+            HeaderLine::Pedigree { .. } => "PEDIGREE",
+            HeaderLine::PedigreeDB(_) => "pedigreeDB",
+            HeaderLine::Other { key, .. } => key,
+            HeaderLine::Sample { .. } => "SAMPLE",
+        }
+    }
 
-    fn from_str(header_line_str: &str) -> anyhow::Result<Self> {
-        let eq_index = header_line_str.find('=');
+    /// The `species` attribute of a `##contig` line, if any.
+    pub fn species(&self) -> Option<&str> {
+        match self {
+            HeaderLine::Contig { other, .. } => other.get("species").map(|s| s.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Parses a header line from bytes. Descriptions and the payloads of unknown header lines are
+    /// free text, kept as bytes, while any other value fails if it is not valid UTF-8.
+    pub fn from_bytes(header_line: &[u8]) -> anyhow::Result<Self> {
+        let eq_index = header_line.iter().position(|b| *b == b'=');
         if eq_index.is_none() {
             return Err(anyhow::anyhow!(
                 "invalid header line `{}`, (header lines must contain an `=` sign)",
-                header_line_str
+                String::from_utf8_lossy(header_line)
             ));
         }
 
@@ -139,20 +362,46 @@ impl FromStr for HeaderLine {
         // ##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">
         //   ^^^^^^ ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
         //   ^type  ^payload
-        let (mut header_type, mut header_payload) = header_line_str.split_at(eq_index.unwrap());
+        let (header_type, header_payload) = header_line.split_at(eq_index.unwrap());
 
         // remove "##" from meta type
-        if !header_type.starts_with("##") {
+        if !header_type.starts_with(b"##") {
             return Err(anyhow::anyhow!(
                 "invalid header type `{}`, (header lines must start with `##`)",
-                header_type
+                String::from_utf8_lossy(header_type)
             ));
         }
-        header_type = &header_type[2..];
+        let header_type = checked_str(&header_type[2..])?;
 
         // remove `=` sign and parse to parts
-        header_payload = &header_payload[1..];
-        let payload_parts = parse_header_payload(header_payload)?;
+        let header_payload = &header_payload[1..];
+        let payload_bytes = split_header_payload(header_payload)?;
+
+        // structured payloads of unknown header lines are kept as they are
+        let other_value = payload_bytes
+            .get(OTHER_KEY.as_bytes())
+            .copied()
+            .unwrap_or(header_payload);
+        let is_known = HEADER_TYPES.contains(&header_type);
+
+        let mut payload_parts = LinkedHashMap::new();
+        let mut description = None;
+        for (key, value) in payload_bytes.iter().filter(|_| is_known) {
+            let key = checked_str(key)?;
+            if key == "Description" && DESCRIBED_TYPES.contains(&header_type) {
+                description = Some(Text::from(*value));
+            } else {
+                let value = checked_str(value)
+                    .map_err(|e| anyhow::anyhow!("invalid `{}` value: {}", key, e))?;
+                payload_parts.insert(key, value);
+            }
+        }
+        let description = description.ok_or_else(|| {
+            anyhow::anyhow!(
+                "value not found in map: value=`Description`, map=`{:?}`",
+                payload_parts
+            )
+        });
 
         let header_line = match header_type {
             "ALT" => HeaderLine::Alt {
@@ -161,51 +410,79 @@ impl FromStr for HeaderLine {
                         .get("ID")
                         .ok_or_else(|| anyhow::anyhow!("value not found"))?,
                 )?,
-                description: get_map_value(&payload_parts, "Description")?,
+                description: description?,
             },
             "assembly" => HeaderLine::Assembly(get_map_value(&payload_parts, OTHER_KEY)?),
             "contig" => {
                 let id = get_map_value(&payload_parts, "ID")?;
-                let species = get_map_value(&payload_parts, "species").ok();
                 let mut other: LinkedHashMap<String, String> = LinkedHashMap::new();
                 for (key, value) in payload_parts {
-                    if key != "ID" && key != "species" {
+                    if key != "ID" {
                         other.insert(key.to_string(), value.to_string());
                     }
                 }
-                HeaderLine::Contig { id, species, other }
+                HeaderLine::Contig { id, other }
+            }
+            "ENCRYPTED" => {
+                let id = get_map_value(&payload_parts, "ID")?;
+                let scope = get_map_value(&payload_parts, "Scope")?.parse()?;
+                let original_type = payload_parts.get("OriginalType").map(|s| (*s).to_string());
+                let original_number = match payload_parts.get("OriginalNumber") {
+                    Some(s) => Some(s.parse()?),
+                    None => None,
+                };
+                let cipher = get_map_value(&payload_parts, "Cipher")?;
+                let key_id = get_map_value(&payload_parts, "KeyId")?;
+                let other = other_attributes(&payload_parts, ENCRYPTED_KEYS);
+                HeaderLine::Encrypted {
+                    id,
+                    scope,
+                    original_type,
+                    original_number,
+                    cipher,
+                    key_id,
+                    other,
+                }
             }
             "fileDate" => HeaderLine::FileDate(get_map_value(&payload_parts, OTHER_KEY)?),
             "FILTER" => HeaderLine::Filter {
                 id: get_map_value(&payload_parts, "ID")?,
-                description: get_map_value(&payload_parts, "Description")?,
+                description: description?,
+                other: other_attributes(&payload_parts, FILTER_KEYS),
             },
             "FORMAT" => HeaderLine::Format {
                 id: get_map_value(&payload_parts, "ID")?,
                 number: Number::new(payload_parts.get("Number").copied())?,
                 typ: FormatType::new(payload_parts.get("Type").copied())?,
-                description: get_map_value(&payload_parts, "Description")?,
+                description: description?,
+                other: other_attributes(&payload_parts, FORMAT_KEYS),
             },
             "INFO" => HeaderLine::Info {
                 id: get_map_value(&payload_parts, "ID")?,
                 number: Number::new(payload_parts.get("Number").copied())?,
                 typ: InfoType::new(payload_parts.get("Type").copied())?,
-                description: get_map_value(&payload_parts, "Description")?,
+                description: description?,
                 source: payload_parts.get("Source").map(|s| (*s).to_string()),
                 version: payload_parts.get("Version").map(|s| (*s).to_string()),
+                other: other_attributes(&payload_parts, INFO_KEYS),
             },
             "META" => HeaderLine::Meta {
                 id: get_map_value(&payload_parts, "ID")?,
                 typ: get_map_value(&payload_parts, "Type")?,
                 number: Number::new(payload_parts.get("Number").copied())?,
-                values: {
-                    let value_string = payload_parts.get("Values").unwrap();
-                    value_string
+                values: match payload_parts.get("Values") {
+                    Some(value_string) => value_string
                         .split(',')
                         .map(|s| s.trim().to_string())
-                        .collect()
+                        .collect(),
+                    None => vec![],
                 },
             },
+            "MERKLE" => HeaderLine::Merkle {
+                id: get_map_value(&payload_parts, "ID")?,
+                records: get_map_value(&payload_parts, "Records")?.parse()?,
+                root: get_map_value(&payload_parts, "Root")?,
+            },
             "PEDIGREE" => HeaderLine::Pedigree {
                 id: get_map_value(&payload_parts, "ID")?,
                 relation: PedigreeType::new(payload_parts)?,
@@ -213,7 +490,7 @@ impl FromStr for HeaderLine {
             "pedigreeDB" => HeaderLine::PedigreeDB(get_map_value(&payload_parts, OTHER_KEY)?),
             "SAMPLE" => {
                 let id = get_map_value(&payload_parts, "ID")?;
-                let description = get_map_value(&payload_parts, "Description")?;
+                let description = description?;
                 let doi = payload_parts.get("DOI").map(|s| (*s).to_string());
                 let mut meta: LinkedHashMap<String, Vec<String>> = LinkedHashMap::new();
                 for (key, value) in payload_parts {
@@ -233,7 +510,7 @@ impl FromStr for HeaderLine {
             }
             _ => HeaderLine::Other {
                 key: header_type.to_string(),
-                value: get_map_value(&payload_parts, OTHER_KEY)?,
+                value: Text::from(other_value),
             },
         };
 
@@ -241,8 +518,17 @@ impl FromStr for HeaderLine {
     }
 }
 
-impl Display for HeaderLine {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+impl FromStr for HeaderLine {
+    type Err = anyhow::Error;
+
+    fn from_str(header_line_str: &str) -> anyhow::Result<Self> {
+        HeaderLine::from_bytes(header_line_str.as_bytes())
+    }
+}
+
+impl HeaderLine {
+    /// Writes the header line, with its free text as the bytes read.
+    pub fn write_to<W: Write + ?Sized>(&self, f: &mut W) -> io::Result<()> {
         match self {
             HeaderLine::Alt { id, description } => {
                 let mut id_str = String::new();
@@ -253,35 +539,69 @@ impl Display for HeaderLine {
                         id_str.push_str(format!("{}", v).as_str());
                     }
                 }
-                write!(f, "##ALT=<ID={},Description=\"{}\">", id_str, description)
+                write!(f, "##ALT=<ID={},Description=\"", id_str)?;
+                f.write_all(description.as_bytes())?;
+                write!(f, "\">")
             }
             HeaderLine::Assembly(s) => write!(f, "##assembly={}", s),
-            HeaderLine::Contig { id, species, other } => {
-                let mut species_str = String::new();
-                if let Some(s) = species {
-                    species_str.push_str(format!(",species=\"{}\"", s).as_str())
+            HeaderLine::Contig { id, other } => {
+                write!(f, "##contig=<ID={}", id)?;
+                write_attributes(f, other)?;
+                write!(f, ">")
+            }
+            HeaderLine::Encrypted {
+                id,
+                scope,
+                original_type,
+                original_number,
+                cipher,
+                key_id,
+                other,
+            } => {
+                let mut original_str = String::new();
+                if let Some(s) = original_type {
+                    original_str.push_str(format!(",OriginalType={}", s).as_str());
                 }
-                let mut other_str = String::new();
-                for (k, v) in other {
-                    other_str.push_str(format!(",{}={}", k, v).as_str())
+                if let Some(n) = original_number {
+                    original_str.push_str(format!(",OriginalNumber={}", n).as_str());
                 }
-                write!(f, "##contig=<ID={}{}{}>", id, species_str, other_str)?;
-                Ok(())
+                write!(
+                    f,
+                    "##ENCRYPTED=<ID={},Scope={}{},Cipher={},KeyId={}",
+                    id, scope, original_str, cipher, key_id
+                )?;
+                write_attributes(f, other)?;
+                write!(f, ">")
             }
             HeaderLine::FileDate(s) => write!(f, "##fileDate={}", s),
-            HeaderLine::Filter { id, description } => {
-                write!(f, "##FILTER=<ID={},Description=\"{}\">", id, description)
+            HeaderLine::Filter {
+                id,
+                description,
+                other,
+            } => {
+                write!(f, "##FILTER=<ID={},Description=\"", id)?;
+                f.write_all(description.as_bytes())?;
+                write!(f, "\"")?;
+                write_attributes(f, other)?;
+                write!(f, ">")
             }
             HeaderLine::Format {
                 id,
                 number,
                 typ,
                 description,
-            } => write!(
-                f,
-                "##FORMAT=<ID={},Number={},Type={},Description=\"{}\">",
-                id, number, typ, description
-            ),
+                other,
+            } => {
+                write!(
+                    f,
+                    "##FORMAT=<ID={},Number={},Type={},Description=\"",
+                    id, number, typ
+                )?;
+                f.write_all(description.as_bytes())?;
+                write!(f, "\"")?;
+                write_attributes(f, other)?;
+                write!(f, ">")
+            }
             HeaderLine::Info {
                 id,
                 number,
@@ -289,6 +609,7 @@ impl Display for HeaderLine {
                 description,
                 source,
                 version,
+                other,
             } => {
                 let mut optional_str = String::new();
                 if let Some(s) = source {
@@ -299,10 +620,13 @@ impl Display for HeaderLine {
                 }
                 write!(
                     f,
-                    "##INFO=<ID={},Number={},Type={},Description=\"{}\"{}>",
-                    id, number, typ, description, optional_str
+                    "##INFO=<ID={},Number={},Type={},Description=\"",
+                    id, number, typ
                 )?;
-                Ok(())
+                f.write_all(description.as_bytes())?;
+                write!(f, "\"{}", optional_str)?;
+                write_attributes(f, other)?;
+                write!(f, ">")
             }
             HeaderLine::Meta {
                 id,
@@ -311,7 +635,7 @@ impl Display for HeaderLine {
                 values,
             } => {
                 let mut values_str = String::new();
-                if values.is_empty() {
+                if !values.is_empty() {
                     values_str.push_str(format!(",Values=[{}]", values.join(",")).as_str());
                 }
                 write!(
@@ -320,11 +644,17 @@ impl Display for HeaderLine {
                     id, typ, number, values_str
                 )
             }
+            HeaderLine::Merkle { id, records, root } => {
+                write!(f, "##MERKLE=<ID={},Records={},Root={}>", id, records, root)
+            }
             HeaderLine::Pedigree { id, relation } => {
                 write!(f, "##PEDIGREE=<ID={},{}>", id, relation)
             }
             HeaderLine::PedigreeDB(s) => write!(f, "##pedigreeDB={}", s),
-            HeaderLine::Other { key, value } => write!(f, "##{}={}", key, value),
+            HeaderLine::Other { key, value } => {
+                write!(f, "##{}=", key)?;
+                f.write_all(value.as_bytes())
+            }
             HeaderLine::Sample {
                 id,
                 meta,
@@ -341,16 +671,152 @@ impl Display for HeaderLine {
                 if let Some(s) = doi {
                     doi_str.push_str(format!(",DOI={}", s).as_str());
                 }
-                write!(
-                    f,
-                    "##SAMPLE=<ID={}{},Description=\"{}\"{}>",
-                    id, meta_str, description, doi_str
-                )
+                write!(f, "##SAMPLE=<ID={}{},Description=\"", id, meta_str)?;
+                f.write_all(description.as_bytes())?;
+                write!(f, "\"{}>", doi_str)
             }
         }
     }
 }
 
+impl Display for HeaderLine {
+    /// Writes the header line, replacing bytes that are not valid UTF-8 with `U+FFFD`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let mut bytes = vec![];
+        self.write_to(&mut bytes).map_err(|_| Error)?;
+        f.write_str(&String::from_utf8_lossy(&bytes))
+    }
+}
+
+/// The attributes of a header line payload other than the given keys, in file order.
+fn other_attributes(
+    payload_parts: &LinkedHashMap<&str, &str>,
+    keys: &[&str],
+) -> LinkedHashMap<String, String> {
+    payload_parts
+        .iter()
+        .filter(|(key, _)| !keys.contains(key))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Writes the attributes of a header line as `,key=value`, quoting the values of `QUOTED_KEYS`
+/// and those that could not be parsed back otherwise.
+fn write_attributes<W: Write + ?Sized>(
+    f: &mut W,
+    attributes: &LinkedHashMap<String, String>,
+) -> io::Result<()> {
+    for (key, value) in attributes {
+        if QUOTED_KEYS.contains(&key.as_str())
+            || value.contains(|c: char| c == ',' || c == '"' || c.is_whitespace())
+        {
+            write!(f, ",{}=\"{}\"", key, value)?;
+        } else {
+            write!(f, ",{}={}", key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// The key and ID of a structured header line, e.g., `INFO` and `DP`.
+fn line_key_id(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (key, attributes) = line_attributes(line)?;
+    let (_, id, _) = attributes.into_iter().find(|(key, ..)| *key == b"ID")?;
+    Some((key, id))
+}
+
+/// The key and the attributes of a structured header line, e.g., `##INFO=<ID=DP,...>`.
+fn line_attributes(line: &[u8]) -> Option<(&[u8], Vec<Attribute<'_>>)> {
+    let eq_index = line.iter().position(|b| *b == b'=')?;
+    let (key, payload) = (line[..eq_index].strip_prefix(b"##")?, &line[eq_index + 1..]);
+    if !payload.starts_with(b"<") || !payload.contains(&b'=') {
+        return None;
+    }
+    Some((key, payload_attributes(payload).ok()?))
+}
+
+/// Writes a changed structured header line in the layout of the line read: the attributes read
+/// keep their order and those with an unchanged value are written as read. A changed value keeps
+/// its quotes or brackets, removed attributes are dropped and new ones are appended as written.
+fn write_in_layout<W: Write + ?Sized>(f: &mut W, read: &[u8], written: &[u8]) -> io::Result<()> {
+    let (key, read_attributes, written_attributes) =
+        match (line_attributes(read), line_attributes(written)) {
+            (Some((key, read_attributes)), Some((_, written_attributes))) => {
+                (key, read_attributes, written_attributes)
+            }
+            _ => return f.write_all(written),
+        };
+    write!(f, "##")?;
+    f.write_all(key)?;
+    write!(f, "=<")?;
+    let mut attributes = vec![];
+    for (key, value, as_read) in &read_attributes {
+        if let Some((_, new_value, as_written)) = find_attribute(&written_attributes, key) {
+            let enclosed = as_read.len() > value.len();
+            let value = if new_value == *value {
+                as_read.to_vec()
+            } else if enclosed {
+                [&as_read[..1], new_value, &as_read[as_read.len() - 1..]].concat()
+            } else {
+                as_written.to_vec()
+            };
+            attributes.push([*key, b"=", &value].concat());
+        }
+    }
+    for (key, _, as_written) in &written_attributes {
+        if find_attribute(&read_attributes, key).is_none() {
+            attributes.push([*key, b"=", as_written].concat());
+        }
+    }
+    f.write_all(&attributes.join(&b','))?;
+    write!(f, ">")
+}
+
+fn find_attribute<'a>(attributes: &[Attribute<'a>], key: &[u8]) -> Option<Attribute<'a>> {
+    attributes.iter().find(|(k, ..)| *k == key).copied()
+}
+
+/// The kind of field described by an `##ENCRYPTED` header line.
+#[derive(Debug, PartialEq, Clone)]
+pub enum EncryptionScope {
+    /// The values of an INFO key.
+    Info,
+
+    /// The values of a FORMAT key.
+    Format,
+
+    /// The entire entries of a sample column. The ID is the sample name.
+    Sample,
+
+    /// The entries of the ID column. The ID is `ID`.
+    Id,
+}
+
+impl FromStr for EncryptionScope {
+    type Err = anyhow::Error;
+
+    fn from_str(scope_str: &str) -> anyhow::Result<Self> {
+        match scope_str {
+            "INFO" => Ok(EncryptionScope::Info),
+            "FORMAT" => Ok(EncryptionScope::Format),
+            "SAMPLE" => Ok(EncryptionScope::Sample),
+            "ID" => Ok(EncryptionScope::Id),
+            s => Err(anyhow::anyhow!("invalid encryption Scope value `{}`", s)),
+        }
+    }
+}
+
+impl Display for EncryptionScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            EncryptionScope::Info => write!(f, "INFO"),
+            EncryptionScope::Format => write!(f, "FORMAT"),
+            EncryptionScope::Sample => write!(f, "SAMPLE"),
+            EncryptionScope::Id => write!(f, "ID"),
+        }
+    }
+}
+
 /// A number of values that can be included within the FORMAT or INFO field.
 #[derive(Debug, PartialEq, Clone)]
 pub enum Number {
@@ -393,6 +859,14 @@ impl Number {
     }
 }
 
+impl FromStr for Number {
+    type Err = anyhow::Error;
+
+    fn from_str(number_str: &str) -> anyhow::Result<Self> {
+        Number::new(Some(number_str))
+    }
+}
+
 impl Display for Number {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
@@ -406,7 +880,7 @@ impl Display for Number {
 }
 
 /// The possible types for the key "Type" of "INFO" fields.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum InfoType {
     Character,
     Flag,
@@ -434,6 +908,14 @@ impl InfoType {
     }
 }
 
+impl FromStr for InfoType {
+    type Err = anyhow::Error;
+
+    fn from_str(type_str: &str) -> anyhow::Result<Self> {
+        InfoType::new(Some(type_str))
+    }
+}
+
 impl Display for InfoType {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
@@ -447,7 +929,7 @@ impl Display for InfoType {
 }
 
 /// The possible types for the key "ID" of "ALT" fields.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AltId {
     DEL,
     INS,
@@ -477,8 +959,9 @@ impl AltId {
         Ok(alt_id)
     }
 
-    fn new_alt_ids(ids_str: &str) -> anyhow::Result<Vec<AltId>> {
-        Ok(ids_str.split(':').map(|s| AltId::new(s).unwrap()).collect())
+    /// Parses colon separated IDs, e.g., `DEL:ME:ALU`.
+    pub fn new_alt_ids(ids_str: &str) -> anyhow::Result<Vec<AltId>> {
+        ids_str.split(':').map(AltId::new).collect()
     }
 }
 
@@ -523,6 +1006,14 @@ impl FormatType {
     }
 }
 
+impl FromStr for FormatType {
+    type Err = anyhow::Error;
+
+    fn from_str(type_str: &str) -> anyhow::Result<Self> {
+        FormatType::new(Some(type_str))
+    }
+}
+
 impl Display for FormatType {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
@@ -535,7 +1026,7 @@ impl Display for FormatType {
 }
 
 /// The possible types for the key "Type" of "FORMAT" fields.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PedigreeType {
     Original(String),
     Parents {
@@ -612,13 +1103,36 @@ fn get_map_value(map: &LinkedHashMap<&str, &str>, key: &str) -> anyhow::Result<S
 ///     ftp://ftp-trace.ncbi.nih.gov/1000genomes/ftp/release/sv/breakpoint_assemblies.fasta
 ///     <ID=Assay,Type=String,Number=.,Values=[WholeGenome, Exome]>
 pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str, &str>> {
+    // keys and values are split at ASCII characters, so they are valid UTF-8 as well
+    split_header_payload(payload.as_bytes())?
+        .into_iter()
+        .map(|(key, value)| Ok((checked_str(key)?, checked_str(value)?)))
+        .collect()
+}
+
+/// Parses the payload of the header from bytes, see `parse_header_payload`.
+fn split_header_payload(payload: &[u8]) -> anyhow::Result<LinkedHashMap<&[u8], &[u8]>> {
+    Ok(payload_attributes(payload)?
+        .into_iter()
+        .map(|(key, value, _)| (key, value))
+        .collect())
+}
+
+/// An attribute of a header line payload: its key, its value and its value as read, i.e., with
+/// the quotes or brackets enclosing it, if any.
+type Attribute<'a> = (&'a [u8], &'a [u8], &'a [u8]);
+
+/// Splits a header line payload into its attributes, in file order.
+fn payload_attributes(payload: &[u8]) -> anyhow::Result<Vec<Attribute<'_>>> {
+    let lossy = || String::from_utf8_lossy(payload);
+
     // remove triangle brackets, if any.
-    let payload = if payload.starts_with('<') || payload.ends_with('>') {
+    let payload = if payload.starts_with(b"<") || payload.ends_with(b">") {
         // either both exist or neither
-        if !payload.starts_with('<') || !payload.ends_with('>') {
+        if !payload.starts_with(b"<") || !payload.ends_with(b">") {
             return Err(anyhow::anyhow!(
                 "invalid header payload `{}`, (unbalanced triangle brackets)",
-                payload
+                lossy()
             ));
         }
         // remove brackets
@@ -626,21 +1140,22 @@ pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str,
     } else {
         payload
     };
+    let lossy = || String::from_utf8_lossy(payload);
 
     // a header payload cannot be empty
     if payload.is_empty() {
         return Err(anyhow::anyhow!("invalid header payload, (empty)"));
     }
 
-    let mut result = LinkedHashMap::new();
+    let mut result = vec![];
 
     // handle payloads not following the key=value pattern as a single string
-    if payload.find('=').is_none() {
-        result.insert(OTHER_KEY, payload);
+    if !payload.contains(&b'=') {
+        result.push((OTHER_KEY.as_bytes(), payload, payload));
         return Ok(result);
     }
 
-    // parse key=value pairs of payload
+    // parse key=value pairs of payload, byte by byte as all delimiters are ASCII
     // reference implementation: https://github.com/informationsea/vcf-rs
     enum PayloadParseState {
         // expecting to parse a key
@@ -650,7 +1165,7 @@ pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str,
         Value,
 
         // expecting to parse a value enclosed within specific characters, e.g., "value" or [v1, v2]
-        EnclosedValue(char),
+        EnclosedValue(u8),
 
         // expecting to parse end of quoted value, e.g., a `,` or end of line
         QuoteEnded,
@@ -660,23 +1175,23 @@ pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str,
     let mut key_start: usize = 0;
     let mut key_end: usize = 0;
     let mut value_start: usize = 0;
-    let mut previous_ch: char = '_';
+    let mut previous_ch: u8 = b'_';
 
-    for (ch_idx, ch) in payload.chars().enumerate() {
+    for (ch_idx, &ch) in payload.iter().enumerate() {
         match state {
             PayloadParseState::Key => {
                 // '=' indicates end of a key.
-                if ch == '=' {
+                if ch == b'=' {
                     key_end = ch_idx;
-                    value_start = ch_idx + ch.len_utf8();
+                    value_start = ch_idx + 1;
                     state = PayloadParseState::Value;
                 }
             }
             PayloadParseState::Value => {
                 // `,` or eol indicates end of value
-                if ch == ',' || ch_idx == payload.len() - 1 {
+                if ch == b',' || ch_idx + 1 == payload.len() {
                     let key = &payload[key_start..key_end];
-                    let value = if ch == ',' {
+                    let value = if ch == b',' {
                         &payload[value_start..ch_idx]
                     } else {
                         &payload[value_start..]
@@ -684,71 +1199,71 @@ pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str,
                     if key.is_empty() {
                         return Err(anyhow::anyhow!(
                             "invalid header payload `{}`, (empty key)",
-                            payload
+                            lossy()
                         ));
                     }
                     if value.is_empty() {
                         return Err(anyhow::anyhow!(
                             "invalid header payload `{}`, (empty value)",
-                            payload
+                            lossy()
                         ));
                     }
-                    result.insert(key, value);
-                    key_start = ch_idx + ch.len_utf8();
+                    result.push((key, value, value));
+                    key_start = ch_idx + 1;
                     state = PayloadParseState::Key;
-                } else if ch == '"' || ch == '[' {
+                } else if ch == b'"' || ch == b'[' {
                     // double quote or opening square bracket indicates an enclosed value. These
                     // characters can occur only at the start of the value
                     if ch_idx != value_start {
                         return Err(anyhow::anyhow!(
                             "invalid header payload `{}`, (invalid character `{}` found)",
-                            payload,
-                            ch
+                            lossy(),
+                            ch as char
                         ));
                     }
-                    value_start = ch_idx + ch.len_utf8();
-                    previous_ch = '_';
+                    value_start = ch_idx + 1;
+                    previous_ch = b'_';
                     state = PayloadParseState::EnclosedValue(ch);
                 }
             }
             PayloadParseState::EnclosedValue(enclosing_char) => {
                 // handle unescaped quote
-                if (enclosing_char == '"' && ch == '"' && previous_ch != '\\')
-                    || (enclosing_char == '[' && ch == ']')
+                if (enclosing_char == b'"' && ch == b'"' && previous_ch != b'\\')
+                    || (enclosing_char == b'[' && ch == b']')
                 {
                     let key = &payload[key_start..key_end];
                     let value = &payload[value_start..ch_idx];
                     if key.is_empty() {
                         return Err(anyhow::anyhow!(
                             "invalid header payload `{}`, (empty key)",
-                            payload
+                            lossy()
                         ));
                     }
                     if value.is_empty() {
                         return Err(anyhow::anyhow!(
                             "invalid header payload `{}`, (empty value)",
-                            payload
+                            lossy()
                         ));
                     }
-                    result.insert(key, value);
+                    result.push((key, value, &payload[value_start - 1..=ch_idx]));
                     state = PayloadParseState::QuoteEnded;
                     continue;
                 }
                 // remember previous character.
-                if ch == '\\' && previous_ch == '\\' {
-                    previous_ch = '_';
+                if ch == b'\\' && previous_ch == b'\\' {
+                    previous_ch = b'_';
                 } else {
                     previous_ch = ch;
                 }
             }
             PayloadParseState::QuoteEnded => {
-                if ch == ',' {
+                if ch == b',' {
                     state = PayloadParseState::Key;
-                    key_start = ch_idx + ch.len_utf8();
+                    key_start = ch_idx + 1;
                 } else {
                     return Err(anyhow::anyhow!(
                         "invalid header payload `{}`, non `,` character found after closing quote",
-                        payload
+                        lossy()
                     ));
                 }
             }
@@ -758,13 +1273,13 @@ pub fn parse_header_payload(payload: &str) -> anyhow::Result<LinkedHashMap<&str,
     if let PayloadParseState::Value = state {
         return Err(anyhow::anyhow!(
             "invalid header payload `{}`, (empty value)",
-            payload
+            lossy()
         ));
     }
     if let PayloadParseState::EnclosedValue(_) = state {
         return Err(anyhow::anyhow!(
             "invalid header payload `{}`, (unbalanced quote)",
-            payload
+            lossy()
         ));
     }
     Ok(result)
@@ -778,12 +1293,12 @@ pub fn parse_version(version_line: &str) -> anyhow::Result<String> {
     if !version_line.starts_with(prefix) {
         return Err(anyhow::anyhow!("invalid version line `{}`", version_line));
     }
-    Ok((&version_line[prefix.len()..]).to_string())
+    Ok(version_line[prefix.len()..].to_string())
 }
 
 /// Parses the column names of the header.
 /// Example:
-///     #CHROM	POS	ID	REF	ALT	QUAL	FILTER	INFO	FORMAT	NA00001	NA00002	NA00003
+///     #CHROM  POS  ID  REF  ALT  QUAL  FILTER  INFO  FORMAT  NA00001  NA00002  NA00003
 pub fn parse_column_names(column_line: &str) -> anyhow::Result<Vec<String>> {
     let prefix = "#CHROM	POS	ID	REF	ALT	QUAL	FILTER	INFO";
     if !column_line.starts_with(prefix) {
@@ -910,6 +1425,73 @@ mod test {
         let actual_version = parse_version(line_str).unwrap();
         let expected_version = "VCFv4.3";
         assert_eq!(actual_version, expected_version);
+
+        let version = Version {
+            value: actual_version,
+        };
+        assert_eq!(version.number(), Some((4, 3)));
+        assert_eq!(Version::default(), version);
+        let version = Version {
+            value: "VCF4".to_string(),
+        };
+        assert_eq!(version.number(), None);
+    }
+
+    #[test]
+    fn test_alt_line() {
+        let header_lines = vec![
+            HeaderLine::from_str("##ALT=<ID=DEL,Description=\"Deletion\">").unwrap(),
+            HeaderLine::from_str("##ALT=<ID=DEL:ME,Description=\"Mobile element deletion\">")
+                .unwrap(),
+        ];
+        let header = Header::new("VCFv4.3".to_string(), header_lines, vec![]);
+        let alt_line = |ids: &str| {
+            header
+                .alt_line(&AltId::new_alt_ids(ids).unwrap())
+                .map(|hl| hl.to_string())
+        };
+        assert!(alt_line("DEL:ME:ALU").unwrap().contains("ID=DEL:ME,"));
+        assert!(alt_line("DEL").unwrap().contains("ID=DEL,"));
+        assert_eq!(alt_line("INV"), None);
+    }
+
+    #[test]
+    fn test_write_lines() {
+        let read = [
+            "##contig=<ID=20,assembly=\"B36\",length=62435964>",
+            "##INFO=<ID=DP,Type=Integer,Number=1,Source=\"dbsnp\",Description=\"Total Depth\",Note=x>",
+            "##META=<ID=Assay,Type=String,Number=.,Values=[WholeGenome, Exome]>",
+        ];
+        let mut header = Header::new("VCFv4.3".to_string(), vec![], vec![]);
+        for line in &read {
+            let hl = HeaderLine::from_str(line).unwrap();
+            header.keep_original(line.as_bytes(), &hl);
+            header.header_lines.push(hl);
+        }
+        let written = |header: &Header| {
+            let mut written = vec![];
+            header.write_lines(&mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(
+            written(&header),
+            format!("##fileformat=VCFv4.3\n{}\n", read.join("\n"))
+        );
+
+        if let HeaderLine::Info { typ, .. } = &mut header.header_lines[1] {
+            *typ = InfoType::String;
+        }
+        header
+            .header_lines
+            .push(HeaderLine::from_str("##contig=<ID=21,length=1>").unwrap());
+        let lines = written(&header);
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(
+            lines[2],
+            "##INFO=<ID=DP,Type=String,Number=1,Source=\"dbsnp\",Description=\"Total Depth\",Note=x>"
+        );
+        assert_eq!(lines[3], read[2]);
+        assert_eq!(lines[4], "##contig=<ID=21,length=1>");
     }
 
     #[test]
@@ -920,9 +1502,10 @@ mod test {
             id: "BKPTID".to_string(),
             number: Number::Unknown,
             typ: InfoType::String,
-            description: "ID of the assembled alternate allele in the assembly file".to_string(),
+            description: "ID of the assembled alternate allele in the assembly file".into(),
             source: None,
             version: None,
+            other: LinkedHashMap::new(),
         };
         assert_eq!(actual_header_line, expected_header_line);
 
@@ -932,7 +1515,8 @@ mod test {
             id: "CNQ".to_string(),
             number: Number::Integer(1),
             typ: FormatType::Float,
-            description: "Copy number genotype quality for imprecise events".to_string(),
+            description: "Copy number genotype quality for imprecise events".into(),
+            other: LinkedHashMap::new(),
         };
         assert_eq!(actual_header_line, expected_header_line);
 
@@ -940,15 +1524,21 @@ mod test {
         let actual_header_line = HeaderLine::from_str(line_str).unwrap();
         let expected_header_line = HeaderLine::Filter {
             id: "s50".to_string(),
-            description: "Less than 50% of samples have data".to_string(),
+            description: "Less than 50% of samples have data".into(),
+            other: LinkedHashMap::new(),
         };
         assert_eq!(actual_header_line, expected_header_line);
+        let line_str = "##FILTER=<ID=q10,Description=\"Quality below 10\",IDX=4>";
+        assert_eq!(
+            HeaderLine::from_str(line_str).unwrap().to_string(),
+            line_str
+        );
 
         let line_str = "##ALT=<ID=INS,Description=\"Insertion of novel sequence\">";
         let actual_header_line = HeaderLine::from_str(line_str).unwrap();
         let expected_header_line = HeaderLine::Alt {
             id: vec![AltId::INS],
-            description: "Insertion of novel sequence".to_string(),
+            description: "Insertion of novel sequence".into(),
         };
         assert_eq!(actual_header_line, expected_header_line);
 
@@ -960,7 +1550,7 @@ mod test {
                 AltId::Other("ME".to_string()),
                 AltId::Other("ALU".to_string()),
             ],
-            description: "Insertion of ALU element".to_string(),
+            description: "Insertion of ALU element".into(),
         };
         assert_eq!(actual_header_line, expected_header_line);
 
@@ -974,15 +1564,27 @@ mod test {
         let actual_header_line = HeaderLine::from_str(line_str).unwrap();
         let expected_header_line = HeaderLine::Contig {
             id: "20".to_string(),
-            species: Some("Homo sapiens".to_string()),
             other: linked_map!(
                 "length".to_string() => "62435964".to_string(),
                 "assembly".to_string() => "B36".to_string(),
                 "md5".to_string() => "f126cdf8a6e0c7f379d618ff66beb2da".to_string(),
+                "species".to_string() => "Homo sapiens".to_string(),
                 "taxonomy".to_string() => "x".to_string(),
             ),
         };
         assert_eq!(actual_header_line, expected_header_line);
+        assert_eq!(actual_header_line.to_string(), line_str);
+        let line_str = "##contig=<ID=1,species=\"human\",URL=ftp://somewhere.org/assembly.fa>";
+        assert_eq!(
+            HeaderLine::from_str(line_str).unwrap().to_string(),
+            line_str
+        );
+        assert_eq!(actual_header_line.species(), Some("Homo sapiens"));
+        let contig = HeaderLine::from_str("##contig=<ID=1,length=10>").unwrap();
+        assert_eq!(contig.species(), None);
+        assert_eq!(HeaderLine::from_str(line_str).unwrap().key(), "contig");
+        let line_str = "##reference=1000GenomesPilot-NCBI36";
+        assert_eq!(HeaderLine::from_str(line_str).unwrap().key(), "reference");
 
         let line_str = "##META=<ID=Assay,Type=String,Number=.,Values=[WholeGenome, Exome]>";
         let actual_header_line = HeaderLine::from_str(line_str).unwrap();
@@ -999,7 +1601,7 @@ mod test {
         let expected_header_line = HeaderLine::Sample {
             id: "Sample1".to_string(),
             meta: LinkedHashMap::default(),
-            description: "Patient germline".to_string(),
+            description: "Patient germline".into(),
             doi: None,
         };
         assert_eq!(actual_header_line, expected_header_line);
@@ -1012,7 +1614,7 @@ mod test {
                 "Genomes".to_string() => vec!("Germline".to_string(),"Tumor".to_string()),
                 "Mixture".to_string() => vec!(".3".to_string(),".7".to_string()),
             ),
-            description: "Patient germline genome;Patient tumor genome".to_string(),
+            description: "Patient germline genome;Patient tumor genome".into(),
             doi: Some("url".to_string()),
         };
         assert_eq!(actual_header_line, expected_header_line);
@@ -1058,6 +1660,54 @@ mod test {
         let actual_header_line = HeaderLine::from_str(line_str).unwrap();
         let expected_header_line = HeaderLine::FileDate("20100501".to_string());
         assert_eq!(actual_header_line, expected_header_line);
+
+        let line_str = "##ENCRYPTED=<ID=GP,Scope=FORMAT,OriginalType=Float,OriginalNumber=G,Cipher=AES-256-GCM,KeyId=9f86d081884c7d65>";
+        let actual_header_line = HeaderLine::from_str(line_str).unwrap();
+        let expected_header_line = HeaderLine::Encrypted {
+            id: "GP".to_string(),
+            scope: EncryptionScope::Format,
+            original_type: Some("Float".to_string()),
+            original_number: Some(Number::Genotype),
+            cipher: "AES-256-GCM".to_string(),
+            key_id: "9f86d081884c7d65".to_string(),
+            other: LinkedHashMap::default(),
+        };
+        assert_eq!(actual_header_line, expected_header_line);
+        assert_eq!(actual_header_line.to_string(), line_str);
+
+        let line_str = "##GATKCommandLine=<ID=HaplotypeCaller,Version=4.1>";
+        let actual_header_line = HeaderLine::from_str(line_str).unwrap();
+        let expected_header_line = HeaderLine::Other {
+            key: "GATKCommandLine".to_string(),
+            value: "<ID=HaplotypeCaller,Version=4.1>".into(),
+        };
+        assert_eq!(actual_header_line, expected_header_line);
+        assert_eq!(actual_header_line.to_string(), line_str);
+    }
+
+    #[test]
+    fn test_encrypted_fields() {
+        let header_lines = vec![
+            "##FORMAT=<ID=GP,Number=.,Type=String,Description=\"Genotype Probabilities\">",
+            "##ENCRYPTED=<ID=GP,Scope=FORMAT,OriginalType=Float,OriginalNumber=G,Cipher=AES-256-GCM,KeyId=k1>",
+            "##ENCRYPTED=<ID=DP,Scope=INFO,OriginalType=Integer,OriginalNumber=1,Cipher=AES-256-GCM,KeyId=k1>",
+        ]
+        .into_iter()
+        .map(|l| HeaderLine::from_str(l).unwrap())
+        .collect();
+        let header = Header::new("VCFv4.3".to_string(), header_lines, vec![]);
+        assert!(header.is_encrypted(&EncryptionScope::Format, "GP"));
+        assert!(!header.is_encrypted(&EncryptionScope::Info, "GP"));
+        assert!(header.is_encrypted(&EncryptionScope::Info, "DP"));
+        assert_eq!(header.key_ids(), vec!["k1"]);
+
+        let line_str = "##ENCRYPTED=<ID=SAMP001,Scope=SAMPLE,Cipher=AES-256-GCM,KeyId=k2>";
+        let header_line = HeaderLine::from_str(line_str).unwrap();
+        assert_eq!(header_line.to_string(), line_str);
+        let line_str = "##ENCRYPTED=<ID=GP,Scope=QUAL,Cipher=AES-256-GCM,KeyId=k1>";
+        assert!(HeaderLine::from_str(line_str).is_err());
+        let line_str = "##ENCRYPTED=<ID=GP,Scope=FORMAT,Cipher=AES-256-GCM>";
+        assert!(HeaderLine::from_str(line_str).is_err());
     }
 
     #[test]
@@ -1081,4 +1731,26 @@ mod test {
         let actual_version = parse_column_names(line_str);
         assert!(actual_version.is_err());
     }
+
+    #[test]
+    fn test_malformed() {
+        // multibyte characters before and inside values
+        let line = "<ID=Σ1,Description=\"naïve ψ\",Source=é>";
+        let expected = linked_map!(
+            "ID" => "Σ1",
+            "Description" => "naïve ψ",
+            "Source" => "é",
+        );
+        assert_eq!(parse_header_payload(line).unwrap(), expected);
+        assert!(parse_header_payload("<ID=é,Description=\"ü").is_err());
+        assert!(parse_header_payload("<é=>").is_err());
+
+        let line_str = "##META=<ID=Assay,Type=String,Number=.>";
+        let header_line = HeaderLine::from_str(line_str).unwrap();
+        assert_eq!(header_line.to_string(), line_str);
+        assert!(HeaderLine::from_str("##ALT=<ID=DEL::ME,Description=\"Deletion\">").is_err());
+        assert!(HeaderLine::from_str("##ALT=<ID=DEL,Description=\"Deletion\">").is_ok());
+        assert!(HeaderLine::from_str("##é").is_err());
+        assert!(HeaderLine::from_str("é=").is_err());
+    }
 }
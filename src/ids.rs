@@ -0,0 +1,182 @@
+use crate::{
+    body::{DataLine, IdType},
+    parser::{IntoIter, VCFReader},
+};
+use linked_hash_map::LinkedHashMap;
+use std::{fs, io::BufRead, str::FromStr};
+
+/// A list of variant IDs, e.g., `rs6054257`, counting how many data lines matched each of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdList {
+    ids: LinkedHashMap<String, usize>,
+}
+
+impl IdList {
+    /// Reads an ID list file, see `from_str`.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("could not read ID file `{}`: {}", path, e))?
+            .parse()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains_key(id)
+    }
+
+    /// The IDs of the list, in file order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.ids.keys().map(|id| id.as_str())
+    }
+
+    /// Returns whether any ID of the data line is in the list, counting every ID that is.
+    pub fn matches(&mut self, dl: &DataLine) -> bool {
+        let mut matched = false;
+        if let IdType::Entries(entries) = &dl.id {
+            for entry in entries {
+                if let Some(count) = self.ids.get_mut(entry) {
+                    *count += 1;
+                    matched = true;
+                }
+            }
+        }
+        matched
+    }
+
+    /// The number of data lines that matched the ID, or `None` if it is not in the list.
+    pub fn count(&self, id: &str) -> Option<usize> {
+        self.ids.get(id).copied()
+    }
+
+    /// The IDs of the list that no data line matched so far, in file order.
+    pub fn unseen(&self) -> Vec<&str> {
+        self.ids
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| id.as_str())
+            .collect()
+    }
+}
+
+impl FromStr for IdList {
+    type Err = anyhow::Error;
+
+    /// Parses one ID per line. Surrounding whitespace, empty lines and lines starting with `#` are
+    /// ignored.
+    fn from_str(ids_str: &str) -> anyhow::Result<Self> {
+        let mut ids = LinkedHashMap::new();
+        for line in ids_str.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.contains(char::is_whitespace) || line.contains(';') {
+                return Err(anyhow::anyhow!("invalid ID `{}`", line));
+            }
+            ids.insert(line.to_string(), 0);
+        }
+        Ok(IdList { ids })
+    }
+}
+
+/// An iterator keeping, or dropping, the data lines with an ID in a list. Errors are passed
+/// through.
+pub struct IdFilteredLines<I> {
+    lines: I,
+    ids: IdList,
+    keep: bool,
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> IdFilteredLines<I> {
+    pub fn new(lines: I, ids: IdList, keep: bool) -> Self {
+        IdFilteredLines { lines, ids, keep }
+    }
+
+    /// The list with the counts of the data lines read so far, e.g., to report its unseen IDs.
+    pub fn ids(&self) -> &IdList {
+        &self.ids
+    }
+
+    pub fn into_ids(self) -> IdList {
+        self.ids
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> Iterator for IdFilteredLines<I> {
+    type Item = anyhow::Result<DataLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(dl) if self.ids.matches(&dl) != self.keep => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> VCFReader<R> {
+    /// Returns the data lines with an ID in the list.
+    pub fn keep_ids(self, ids: IdList) -> IdFilteredLines<IntoIter<R>> {
+        IdFilteredLines::new(self.into_iter(), ids, true)
+    }
+
+    /// Returns the data lines without an ID in the list.
+    pub fn drop_ids(self, ids: IdList) -> IdFilteredLines<IntoIter<R>> {
+        IdFilteredLines::new(self.into_iter(), ids, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ids::*, parser::VCFParser};
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t14370\trs6054257\tG\tA\t29\tPASS\t.
+20\t17330\t.\tT\tA\t3\tq10\t.
+20\t1110696\trs6040355;rs1\tA\tG\t67\tPASS\t.";
+
+    const IDS: &str = "# dbSNP IDs
+rs6040355
+
+rs6054257
+rs0000001
+";
+
+    #[test]
+    fn test_keep_drop() {
+        let ids: IdList = IDS.parse().unwrap();
+        assert_eq!(ids.len(), 3);
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mut lines = parser.reader.keep_ids(ids);
+        let positions: Vec<u64> = lines.by_ref().map(|dl| dl.unwrap().position).collect();
+        assert_eq!(positions, vec![14370, 1110696]);
+        let ids = lines.into_ids();
+        assert_eq!(ids.unseen(), vec!["rs0000001"]);
+        assert_eq!(ids.count("rs6040355"), Some(1));
+        assert_eq!(ids.count("rs1"), None);
+
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let positions: Vec<u64> = parser
+            .reader
+            .drop_ids(IDS.parse().unwrap())
+            .map(|dl| dl.unwrap().position)
+            .collect();
+        assert_eq!(positions, vec![17330]);
+    }
+
+    #[test]
+    fn test_load() {
+        let ids = IdList::load("test/resources/valid/ids.txt").unwrap();
+        assert_eq!(ids.iter().collect::<Vec<_>>(), vec!["rs84825"]);
+        assert!(IdList::load("test/resources/valid/missing.txt").is_err());
+        assert!(IdList::from_str("rs1 rs2").is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{
+    body::DataLine,
+    compression::{bgzf_decode_block, BgzfWriter},
+    header::{parse_column_names, parse_version, Version},
+    region::Region,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+/// The magic bytes of a tabix index.
+pub const TBI_MAGIC: &[u8; 4] = b"TBI\x01";
+
+/// The size of the windows of the linear index, as a power of two.
+const LINEAR_SHIFT: u32 = 14;
+
+/// The largest end of an interval the bins of a tabix index can hold, 512 Mbp. Larger positions
+/// need a CSI index.
+const MAX_POSITION: u64 = 1 << 29;
+
+/// A chunk of a BGZF file, from a virtual offset to another. A virtual offset is the offset of a
+/// block in the compressed file, shifted by 16 bits, and an offset in the uncompressed block.
+pub type Chunk = (u64, u64);
+
+/// The index of the data lines of a chromosome.
+#[derive(Debug, Default, PartialEq)]
+struct ReferenceIndex {
+    /// The chunks of each bin, see `region_to_bin`.
+    bins: BTreeMap<u32, Vec<Chunk>>,
+
+    /// The virtual offset of the first data line overlapping each 16kb window.
+    intervals: Vec<u64>,
+}
+
+/// A tabix index of a BGZF compressed VCF file, compatible with `tabix -p vcf`. Data lines must be
+/// sorted by position and the data lines of a chromosome must be contiguous.
+#[derive(Debug, Default, PartialEq)]
+pub struct TabixIndex {
+    names: Vec<String>,
+    references: Vec<ReferenceIndex>,
+}
+
+impl TabixIndex {
+    /// Indexes a BGZF compressed VCF file. Data lines span from `POS` to their end, see
+    /// `DataLine::end`.
+    pub fn build<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut index = TabixIndex::default();
+        let mut column_names: Vec<String> = vec![];
+        let mut version = Version::default();
+        let mut line: Vec<u8> = vec![];
+        let mut line_start = None;
+        let mut last: Option<(String, u64)> = None;
+        let mut block_offset = 0u64;
+        while let Some((bytes, block_size)) = bgzf_decode_block(&mut reader)? {
+            let next_block_offset = block_offset + block_size as u64;
+            let mut rest = bytes.as_slice();
+            while !rest.is_empty() {
+                if line_start.is_none() {
+                    line_start = Some(block_offset << 16 | (bytes.len() - rest.len()) as u64);
+                }
+                let end = match rest.iter().position(|b| *b == b'\n') {
+                    Some(end) => end,
+                    None => {
+                        line.extend_from_slice(rest);
+                        break;
+                    }
+                };
+                line.extend_from_slice(&rest[..end]);
+                rest = &rest[end + 1..];
+                let line_end = if rest.is_empty() {
+                    next_block_offset << 16
+                } else {
+                    block_offset << 16 | (bytes.len() - rest.len()) as u64
+                };
+                let chunk = (line_start.take().unwrap(), line_end);
+                index.push_line(&line, chunk, &mut column_names, &mut version, &mut last)?;
+                line.clear();
+            }
+            block_offset = next_block_offset;
+        }
+        if let Some(start) = line_start {
+            index.push_line(
+                &line,
+                (start, block_offset << 16),
+                &mut column_names,
+                &mut version,
+                &mut last,
+            )?;
+        }
+        for reference in &mut index.references {
+            for i in 1..reference.intervals.len() {
+                if reference.intervals[i] == 0 {
+                    reference.intervals[i] = reference.intervals[i - 1];
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    fn push_line(
+        &mut self,
+        line: &[u8],
+        chunk: Chunk,
+        column_names: &mut Vec<String>,
+        version: &mut Version,
+        last: &mut Option<(String, u64)>,
+    ) -> anyhow::Result<()> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(b"##fileformat=") {
+            version.value = parse_version(&String::from_utf8_lossy(line))?;
+            return Ok(());
+        }
+        if line.starts_with(b"#CHROM") {
+            *column_names = parse_column_names(&String::from_utf8_lossy(line))?;
+            return Ok(());
+        }
+        if line.is_empty() || line.starts_with(b"#") {
+            return Ok(());
+        }
+        let dl = DataLine::from_bytes(line, column_names)?;
+        // tabix uses 0-based, half-open coordinates
+        let (start, end) = (dl.position.saturating_sub(1), dl.end(version));
+        if end > MAX_POSITION {
+            return Err(anyhow::anyhow!(
+                "data line `{}:{}` ends past the tabix limit of {}",
+                dl.chromosome,
+                dl.position,
+                MAX_POSITION
+            ));
+        }
+        match last {
+            Some((chromosome, position)) if *chromosome == dl.chromosome => {
+                if dl.position < *position {
+                    return Err(anyhow::anyhow!(
+                        "unsorted data lines, (`{}:{}` after `{}:{}`)",
+                        dl.chromosome,
+                        dl.position,
+                        chromosome,
+                        position
+                    ));
+                }
+            }
+            _ => {
+                if self.names.contains(&dl.chromosome) {
+                    return Err(anyhow::anyhow!(
+                        "unsorted data lines, (chromosome `{}` is not contiguous)",
+                        dl.chromosome
+                    ));
+                }
+                self.names.push(dl.chromosome.clone());
+                self.references.push(ReferenceIndex::default());
+            }
+        }
+        *last = Some((dl.chromosome.clone(), dl.position));
+
+        let reference = self.references.last_mut().unwrap();
+        let chunks = reference.bins.entry(region_to_bin(start, end)).or_default();
+        match chunks.last_mut() {
+            Some(last_chunk) if last_chunk.1 == chunk.0 => last_chunk.1 = chunk.1,
+            _ => chunks.push(chunk),
+        }
+        let last_window = ((end.max(start + 1) - 1) >> LINEAR_SHIFT) as usize;
+        if reference.intervals.len() <= last_window {
+            reference.intervals.resize(last_window + 1, 0);
+        }
+        for window in (start >> LINEAR_SHIFT) as usize..=last_window {
+            if reference.intervals[window] == 0 {
+                reference.intervals[window] = chunk.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// The chromosomes of the index, in file order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The chunks of the file that may contain data lines overlapping the region, sorted by
+    /// offset.
+    pub fn query(&self, region: &Region) -> Vec<Chunk> {
+        let reference = match self.names.iter().position(|n| *n == region.chromosome) {
+            Some(index) => &self.references[index],
+            None => return vec![],
+        };
+        let start = region.start - 1;
+        if start >= MAX_POSITION {
+            return vec![];
+        }
+        let end = region.end.unwrap_or(MAX_POSITION).min(MAX_POSITION);
+        let min_offset = reference
+            .intervals
+            .get((start >> LINEAR_SHIFT) as usize)
+            .copied()
+            .unwrap_or(0);
+        let mut chunks: Vec<Chunk> = region_to_bins(start, end)
+            .iter()
+            .filter_map(|bin| reference.bins.get(bin))
+            .flatten()
+            .filter(|chunk| chunk.1 > min_offset)
+            .copied()
+            .collect();
+        chunks.sort_unstable();
+        chunks
+    }
+
+    /// Encodes the index, uncompressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = TBI_MAGIC.to_vec();
+        let names: Vec<u8> = self
+            .names
+            .iter()
+            .flat_map(|name| name.bytes().chain(std::iter::once(0)))
+            .collect();
+        // the number of references, the VCF preset (format 2, CHROM in column 1, POS in column 2,
+        // no end column, `#` comments, no skipped lines) and the names
+        for value in &[self.names.len() as i32, 2, 1, 2, 0, b'#' as i32, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(names.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&names);
+        for reference in &self.references {
+            bytes.extend_from_slice(&(reference.bins.len() as i32).to_le_bytes());
+            for (bin, chunks) in &reference.bins {
+                bytes.extend_from_slice(&bin.to_le_bytes());
+                bytes.extend_from_slice(&(chunks.len() as i32).to_le_bytes());
+                for (start, end) in chunks {
+                    bytes.extend_from_slice(&start.to_le_bytes());
+                    bytes.extend_from_slice(&end.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&(reference.intervals.len() as i32).to_le_bytes());
+            for offset in &reference.intervals {
+                bytes.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Writes the index, BGZF compressed like a `.tbi` file.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut writer = BgzfWriter::new(writer);
+        writer.write_all(&self.to_bytes())?;
+        writer.finish()
+    }
+}
+
+/// The smallest bin of the UCSC binning scheme containing the 0-based, half-open interval, which
+/// ends at `MAX_POSITION` at most.
+fn region_to_bin(start: u64, end: u64) -> u32 {
+    debug_assert!(end <= MAX_POSITION);
+    let end = end.max(start + 1) - 1;
+    for (shift, offset) in &[(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if start >> shift == end >> shift {
+            return (offset + (start >> shift)) as u32;
+        }
+    }
+    0
+}
+
+/// All the bins that may overlap the 0-based, half-open interval, which ends at `MAX_POSITION` at
+/// most.
+fn region_to_bins(start: u64, end: u64) -> Vec<u32> {
+    debug_assert!(end <= MAX_POSITION);
+    let end = end.max(start + 1) - 1;
+    let mut bins = vec![0];
+    for (shift, offset) in &[(26, 1), (23, 9), (20, 73), (17, 585), (14, 4681)] {
+        bins.extend((offset + (start >> shift)) as u32..=(offset + (end >> shift)) as u32);
+    }
+    bins
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compression::{gz_decode, BgzfWriter},
+        index::*,
+    };
+    use std::{fs::File, str::FromStr};
+
+    #[test]
+    fn test_bins() {
+        assert_eq!(region_to_bin(0, 1), 4681);
+        assert_eq!(region_to_bin(0, 1 << 14), 4681);
+        assert_eq!(region_to_bin(0, (1 << 14) + 1), 585);
+        assert_eq!(region_to_bin(0, 1 << 29), 0);
+        assert!(region_to_bins(20000, 20001).contains(&(4681 + 1)));
+    }
+
+    #[test]
+    fn test_index() {
+        let file = File::open("test/resources/valid/file.vcf.gz").unwrap();
+        let index = TabixIndex::build(file).unwrap();
+        assert_eq!(index.names(), &["20".to_string()]);
+
+        let bytes = gz_decode(&index.write(vec![]).unwrap()).unwrap();
+        assert_eq!(bytes, index.to_bytes());
+        assert_eq!(&bytes[..4], TBI_MAGIC);
+        assert_eq!(&bytes[36..39], b"20\0");
+
+        assert!(index.query(&Region::chromosome("21")).is_empty());
+        let chunks = index.query(&Region::from_str("20:34001111").unwrap());
+        assert!(!chunks.is_empty());
+    }
+
+    #[test]
+    fn test_offsets() {
+        // a small block size puts data lines across blocks
+        let vcf = "##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+                   1\t100\t.\tA\tG\t.\t.\t.\n1\t20000\t.\tA\tG\t.\t.\t.\n\
+                   2\t5\t.\tN\t<DEL>\t.\t.\tEND=40000\n";
+        let mut writer = BgzfWriter::new(vec![]);
+        for part in vcf.as_bytes().chunks(30) {
+            writer.write_all(part).unwrap();
+            writer.flush().unwrap();
+        }
+        let bgzf = writer.finish().unwrap();
+        let index = TabixIndex::build(bgzf.as_slice()).unwrap();
+        assert_eq!(index.names(), &["1".to_string(), "2".to_string()]);
+
+        // virtual offsets point to the start of each data line
+        let lines: Vec<u64> = index
+            .query(&Region::from_str("1").unwrap())
+            .iter()
+            .map(|chunk| chunk.0)
+            .collect();
+        let mut blocks = vec![];
+        let mut reader = bgzf.as_slice();
+        let mut offset = 0;
+        while let Some((bytes, size)) = bgzf_decode_block(&mut reader).unwrap() {
+            blocks.push((offset, bytes));
+            offset += size as u64;
+        }
+        for voffset in lines {
+            let (_, bytes) = blocks.iter().find(|b| b.0 == voffset >> 16).unwrap();
+            assert!(bytes[(voffset & 0xffff) as usize..].starts_with(b"1\t"));
+        }
+        assert_eq!(
+            index.query(&Region::from_str("1:101-19999").unwrap()).len(),
+            2
+        );
+        assert_eq!(index.query(&Region::from_str("2:30000").unwrap()).len(), 1);
+
+        // positions past the tabix limit are errors, not a huge linear index
+        let far = vcf.replace("1\t20000", "1\t536870912\t.\tA\tG\t.\t.\t.\n1\t536870913");
+        let mut writer = BgzfWriter::new(vec![]);
+        writer.write_all(far.as_bytes()).unwrap();
+        let error = TabixIndex::build(writer.finish().unwrap().as_slice())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "data line `1:536870913` ends past the tabix limit of 536870912"
+        );
+        assert!(index
+            .query(&Region::from_str("1:536870913").unwrap())
+            .is_empty());
+
+        let unsorted = vcf.replace("1\t20000", "2\t20000");
+        let mut writer = BgzfWriter::new(vec![]);
+        writer.write_all(unsorted.as_bytes()).unwrap();
+        assert!(TabixIndex::build(writer.finish().unwrap().as_slice()).is_err());
+    }
+}
@@ -1,4 +1,26 @@
+pub mod bcf;
 pub mod body;
+pub mod breakend;
+pub mod channel;
 pub mod compression;
+pub mod container;
+pub mod crypto;
+pub mod encryption;
+pub mod fasta;
+pub mod filter;
 pub mod header;
+pub mod ids;
+pub mod index;
+pub mod merkle;
+pub mod norm;
 pub mod parser;
+pub mod pseudonym;
+pub mod qc;
+pub mod query;
+pub mod redaction;
+pub mod region;
+pub mod stats;
+pub mod text;
+
+#[cfg(test)]
+mod test_util;
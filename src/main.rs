@@ -0,0 +1,808 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use flate2::read::MultiGzDecoder;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+use vcflib::{
+    body::{DataLine, InfoType, SampleType},
+    compression::BgzfWriter,
+    crypto::Key,
+    encryption::{Decryptor, Encryptor, Field, Mode},
+    fasta::{check_contigs, check_reference, IndexedFasta, ReferenceIssue},
+    filter::Expression,
+    header::{Header, HeaderLine},
+    ids::IdList,
+    index::TabixIndex,
+    norm::normalize,
+    parser::{Limits, Utf8Mode, VCFParser, VCFWriter},
+    qc::{sample_qc, QcThresholds},
+    query::QueryFormat,
+    region::{Overlap, Region, RegionFilter},
+};
+
+/// Reads, filters, transforms and protects VCF files.
+#[derive(Debug, Parser)]
+#[command(name = "vcflib", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Selects data lines and samples, like `bcftools view`.
+    View {
+        #[command(flatten)]
+        input: Input,
+
+        #[command(flatten)]
+        selection: Selection,
+
+        /// Keep only these samples, comma separated, or all but these if prefixed with `^`.
+        #[arg(short, long)]
+        samples: Option<String>,
+
+        /// Write only the data lines.
+        #[arg(short = 'H', long)]
+        no_header: bool,
+
+        #[command(flatten)]
+        output: Output,
+    },
+
+    /// Prints the data lines in a format, like `bcftools query`.
+    Query {
+        #[command(flatten)]
+        input: Input,
+
+        /// The format, e.g., `%CHROM\t%POS\t[ %GT]\n`.
+        #[arg(short, long)]
+        format: String,
+
+        #[command(flatten)]
+        selection: Selection,
+
+        /// The output file, stdout if not given.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Prints summary statistics of the data lines.
+    Stats {
+        #[command(flatten)]
+        input: Input,
+
+        /// Print the statistics as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints per-sample metrics, with the thresholds that each sample does not meet.
+    Qc {
+        #[command(flatten)]
+        input: Input,
+
+        #[command(flatten)]
+        thresholds: Thresholds,
+
+        /// Print only the samples that do not meet the thresholds.
+        #[arg(long)]
+        outliers: bool,
+
+        /// Print the metrics as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Checks that a file parses and that its data lines use keys defined in its header.
+    Validate {
+        #[command(flatten)]
+        input: Input,
+
+        /// Also check REF and the `##contig` lengths and MD5s against this FASTA file, indexed in
+        /// `<file>.fai`, which is created if missing.
+        #[arg(short, long)]
+        fasta_ref: Option<String>,
+    },
+
+    /// Writes a tabix index, `<file>.tbi`, of a BGZF compressed file.
+    Index {
+        file: String,
+
+        /// Overwrite an existing index.
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Compresses a file with BGZF, to `<file>.gz` or stdout when reading stdin.
+    Compress {
+        #[command(flatten)]
+        input: Input,
+
+        /// The output file.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Encrypts the values of INFO and FORMAT keys, or IDs.
+    Encrypt {
+        #[command(flatten)]
+        input: Input,
+
+        /// A file holding the key as 64 hex characters, e.g., from `openssl rand -hex 32`.
+        #[arg(short, long)]
+        key: String,
+
+        /// The fields to encrypt, comma separated, e.g., `ID,INFO/DP,FORMAT/GQ`.
+        #[arg(short, long, required = true, value_delimiter = ',')]
+        fields: Vec<String>,
+
+        /// How values are encrypted.
+        #[arg(short, long, value_enum, default_value_t = CipherMode::Authenticated)]
+        mode: CipherMode,
+
+        #[command(flatten)]
+        output: Output,
+    },
+
+    /// Decrypts a file written by `encrypt`.
+    Decrypt {
+        #[command(flatten)]
+        input: Input,
+
+        /// A file holding the key as 64 hex characters.
+        #[arg(short, long)]
+        key: String,
+
+        #[command(flatten)]
+        output: Output,
+    },
+
+    /// Trims the alleles of data lines and splits multiallelic data lines.
+    Norm {
+        #[command(flatten)]
+        input: Input,
+
+        /// Split multiallelic data lines, like `bcftools norm -m-`.
+        #[arg(short = 'm', long)]
+        split: bool,
+
+        #[command(flatten)]
+        output: Output,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct Input {
+    /// The input file, plain or gzip compressed, or `-` for stdin.
+    #[arg(default_value = "-")]
+    file: String,
+
+    /// Replace bytes that are not valid UTF-8 instead of failing, e.g., for Latin-1 descriptions.
+    #[arg(long)]
+    lossy: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct Output {
+    /// The output file, stdout if not given.
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// The output type, `v` for plain VCF or `z` for BGZF compressed VCF.
+    #[arg(short = 'O', long, value_enum, default_value_t = OutputType::V)]
+    output_type: OutputType,
+}
+
+#[derive(Debug, clap::Args)]
+struct Thresholds {
+    /// Flag samples with a lower fraction of called genotypes.
+    #[arg(long, default_value_t = 0.95)]
+    min_call_rate: f64,
+
+    /// Flag samples with a higher ratio of heterozygous to homozygous alternate genotypes.
+    #[arg(long)]
+    max_het_hom: Option<f64>,
+
+    /// Flag samples with a lower Ts/Tv.
+    #[arg(long)]
+    min_ts_tv: Option<f64>,
+
+    /// Flag samples with a higher Ts/Tv.
+    #[arg(long)]
+    max_ts_tv: Option<f64>,
+
+    /// Flag samples with a lower mean FORMAT/DP.
+    #[arg(long)]
+    min_depth: Option<f64>,
+
+    /// Flag samples with more singletons.
+    #[arg(long)]
+    max_singletons: Option<u64>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Selection {
+    /// Keep data lines overlapping the region, e.g., `20` or `20:1,000-2,000`. May be repeated.
+    #[arg(short, long = "regions")]
+    regions: Vec<String>,
+
+    /// Keep data lines overlapping the intervals of a BED file.
+    #[arg(short = 'R', long)]
+    regions_file: Option<String>,
+
+    /// Keep data lines overlapping none of the regions instead.
+    #[arg(long)]
+    complement: bool,
+
+    /// Whether only `POS` or the whole variant must overlap a region.
+    #[arg(long, value_enum, default_value_t = OverlapMode::Variant)]
+    overlap: OverlapMode,
+
+    /// Keep data lines matching the expression, e.g., `QUAL>20 && INFO/DP>10`.
+    #[arg(short, long, conflicts_with = "exclude")]
+    include: Option<String>,
+
+    /// Drop data lines matching the expression.
+    #[arg(short, long)]
+    exclude: Option<String>,
+
+    /// Keep data lines with an ID in the file, one ID per line.
+    #[arg(long)]
+    ids: Option<String>,
+
+    /// Drop the data lines with an ID in the `--ids` file instead.
+    #[arg(long, requires = "ids")]
+    drop_ids: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputType {
+    V,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OverlapMode {
+    Pos,
+    Variant,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CipherMode {
+    /// AES-256-GCM, changing the encrypted fields to strings.
+    Authenticated,
+    /// AES-256-GCM-SIV, which reveals which values of a field are equal.
+    Deterministic,
+    /// FF1 on the digits of numbers, keeping their types. Values are not authenticated, keep
+    /// their sign, number of digits and exponent, and are easily guessed under 6 digits.
+    FormatPreserving,
+}
+
+/// The data line filters of a `Selection`, compiled against a header.
+struct Selector {
+    regions: Option<RegionFilter>,
+    expression: Option<(Expression, bool)>,
+    ids: Option<(IdList, bool)>,
+}
+
+impl Selector {
+    fn new(selection: &Selection, header: &Header) -> anyhow::Result<Self> {
+        let overlap = match selection.overlap {
+            OverlapMode::Pos => Overlap::Position,
+            OverlapMode::Variant => Overlap::Variant,
+        };
+        let contigs: Vec<&str> = header
+            .header_lines
+            .iter()
+            .filter_map(|hl| match hl {
+                HeaderLine::Contig { id, .. } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut regions = selection
+            .regions
+            .iter()
+            .map(|region_str| Region::with_contigs(region_str, &contigs))
+            .collect::<anyhow::Result<Vec<Region>>>()?;
+        if let Some(path) = &selection.regions_file {
+            let file = File::open(path)
+                .map_err(|e| anyhow::anyhow!("could not open BED file `{}`: {}", path, e))?;
+            regions.extend(vcflib::region::read_bed(BufReader::new(file))?);
+        }
+        let regions = if regions.is_empty() && selection.regions_file.is_none() {
+            None
+        } else {
+            Some(
+                RegionFilter::new(&regions, overlap, selection.complement)
+                    .with_version(&header.version),
+            )
+        };
+        let expression = match (&selection.include, &selection.exclude) {
+            (Some(expr_str), _) => Some((Expression::new(expr_str, header)?, true)),
+            (None, Some(expr_str)) => Some((Expression::new(expr_str, header)?, false)),
+            (None, None) => None,
+        };
+        let ids = match &selection.ids {
+            Some(path) => Some((IdList::load(path)?, !selection.drop_ids)),
+            None => None,
+        };
+        Ok(Selector {
+            regions,
+            expression,
+            ids,
+        })
+    }
+
+    fn matches(&mut self, dl: &DataLine) -> bool {
+        self.regions
+            .as_ref()
+            .is_none_or(|regions| regions.matches(dl))
+            && self
+                .expression
+                .as_ref()
+                .is_none_or(|(expression, include)| expression.matches(dl) == *include)
+            && self
+                .ids
+                .as_mut()
+                .is_none_or(|(ids, keep)| ids.matches(dl) == *keep)
+    }
+
+    /// Warns about the IDs of the `--ids` file that no data line had.
+    fn report_unseen(&self) {
+        if let Some((ids, _)) = &self.ids {
+            let unseen = ids.unseen();
+            if !unseen.is_empty() {
+                eprintln!(
+                    "warning: {} of {} IDs not found: {}",
+                    unseen.len(),
+                    ids.len(),
+                    unseen.join(",")
+                );
+            }
+        }
+    }
+}
+
+fn open_input(path: &str) -> anyhow::Result<Box<dyn BufRead>> {
+    let read: Box<dyn Read> = if path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|e| anyhow::anyhow!("could not open `{}`: {}", path, e))?)
+    };
+    let mut reader = BufReader::new(read);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn open_parser(input: &Input) -> anyhow::Result<VCFParser<Box<dyn BufRead>>> {
+    VCFParser::from_source(
+        open_input(&input.file)?,
+        Limits::default(),
+        if input.lossy {
+            Utf8Mode::Lossy
+        } else {
+            Utf8Mode::Strict
+        },
+    )
+}
+
+/// A file or stdout, compressed or not, which must be finished to report the errors of the last
+/// writes.
+enum OutputWriter {
+    Plain(BufWriter<Box<dyn Write>>),
+    Bgzf(BgzfWriter<BufWriter<Box<dyn Write>>>),
+}
+
+impl OutputWriter {
+    /// Flushes the output, writing the BGZF end of file marker if compressed.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(mut writer) => writer.flush(),
+            OutputWriter::Bgzf(writer) => writer.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(writer) => writer.write(buf),
+            OutputWriter::Bgzf(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(writer) => writer.flush(),
+            OutputWriter::Bgzf(writer) => writer.flush(),
+        }
+    }
+}
+
+fn create_output(path: Option<&str>, output_type: OutputType) -> anyhow::Result<OutputWriter> {
+    let writer: Box<dyn Write> = match path {
+        Some(path) => Box::new(
+            File::create(path)
+                .map_err(|e| anyhow::anyhow!("could not create `{}`: {}", path, e))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    let writer = BufWriter::new(writer);
+    Ok(match output_type {
+        OutputType::V => OutputWriter::Plain(writer),
+        OutputType::Z => OutputWriter::Bgzf(BgzfWriter::new(writer)),
+    })
+}
+
+fn read_key(path: &str) -> anyhow::Result<Key> {
+    fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("could not read key file `{}`: {}", path, e))?
+        .parse()
+}
+
+/// Keeps the named samples, or all but the named samples if the list starts with `^`, returning
+/// the indices of the kept samples.
+fn select_samples(header: &mut Header, samples_str: &str) -> anyhow::Result<Vec<usize>> {
+    let (exclude, names) = match samples_str.strip_prefix('^') {
+        Some(names) => (true, names),
+        None => (false, samples_str),
+    };
+    let names: Vec<&str> = names.split(',').filter(|name| !name.is_empty()).collect();
+    for name in &names {
+        if !header.column_names.iter().any(|c| c == name) {
+            return Err(anyhow::anyhow!("unknown sample `{}`", name));
+        }
+    }
+    let indices: Vec<usize> = if exclude {
+        (0..header.column_names.len())
+            .filter(|i| !names.contains(&header.column_names[*i].as_str()))
+            .collect()
+    } else {
+        names
+            .iter()
+            .map(|name| header.column_names.iter().position(|c| c == name).unwrap())
+            .collect()
+    };
+    header.column_names = indices
+        .iter()
+        .map(|i| header.column_names[*i].clone())
+        .collect();
+    Ok(indices)
+}
+
+fn view(
+    input: &Input,
+    selection: &Selection,
+    samples: Option<&str>,
+    no_header: bool,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let VCFParser {
+        mut header,
+        mut reader,
+    } = open_parser(input)?;
+    let mut selector = Selector::new(selection, &header)?;
+    let sample_indices = match samples {
+        Some(samples_str) => Some(select_samples(&mut header, samples_str)?),
+        None => None,
+    };
+    let mut out = create_output(output.output.as_deref(), output.output_type)?;
+    let lines = reader.iter().filter_map(|dl| match dl {
+        Ok(dl) if !selector.matches(&dl) => None,
+        Ok(mut dl) => {
+            if let Some(indices) = &sample_indices {
+                let samples = std::mem::take(&mut dl.samples);
+                dl.samples = indices.iter().map(|i| samples[*i].clone()).collect();
+                if dl.samples.is_empty() {
+                    dl.format = None;
+                }
+            }
+            Some(Ok(dl))
+        }
+        Err(e) => Some(Err(e)),
+    });
+    if no_header {
+        let mut written = false;
+        for dl in lines {
+            if written {
+                writeln!(out)?;
+            }
+            write!(out, "{}", dl?)?;
+            written = true;
+        }
+        if written && reader.final_newline {
+            writeln!(out)?;
+        }
+    } else {
+        let mut writer = VCFWriter::new(&mut out, &header)?;
+        for dl in lines {
+            writer.write_data_line(&dl?)?;
+        }
+        writer.set_final_newline(reader.final_newline);
+        writer.finish()?;
+    }
+    out.finish()?;
+    selector.report_unseen();
+    Ok(())
+}
+
+fn query(
+    input: &Input,
+    format_str: &str,
+    selection: &Selection,
+    output: Option<&str>,
+) -> anyhow::Result<()> {
+    let VCFParser { header, reader } = open_parser(input)?;
+    let format = QueryFormat::new(format_str, &header)?;
+    let mut selector = Selector::new(selection, &header)?;
+    let mut out = create_output(output, OutputType::V)?;
+    for dl in reader {
+        let dl = dl?;
+        if selector.matches(&dl) {
+            format.render(&dl, &mut out)?;
+        }
+    }
+    out.finish()?;
+    selector.report_unseen();
+    Ok(())
+}
+
+fn stats(input: &Input, json: bool) -> anyhow::Result<()> {
+    let stats = vcflib::stats::stats(open_parser(input)?)?;
+    let mut out = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &stats)?;
+        writeln!(out)?;
+    } else {
+        write!(out, "{}", stats)?;
+    }
+    Ok(())
+}
+
+fn qc(input: &Input, thresholds: &Thresholds, outliers: bool, json: bool) -> anyhow::Result<()> {
+    let thresholds = QcThresholds {
+        min_call_rate: Some(thresholds.min_call_rate),
+        max_het_hom_ratio: thresholds.max_het_hom,
+        min_ts_tv: thresholds.min_ts_tv,
+        max_ts_tv: thresholds.max_ts_tv,
+        min_mean_depth: thresholds.min_depth,
+        max_singletons: thresholds.max_singletons,
+    };
+    let mut report = sample_qc(open_parser(input)?)?;
+    if outliers {
+        let outliers: HashSet<String> = report
+            .outliers(&thresholds)
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        report.samples = report
+            .samples
+            .into_iter()
+            .filter(|(name, _)| outliers.contains(name))
+            .collect();
+    }
+    let mut out = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &report.rows(&thresholds))?;
+        writeln!(out)?;
+    } else {
+        write!(out, "{}", report.to_table(&thresholds))?;
+    }
+    Ok(())
+}
+
+fn validate(input: &Input, fasta_ref: Option<&str>) -> anyhow::Result<()> {
+    let VCFParser { header, reader } = open_parser(input)?;
+    let mut fasta = fasta_ref.map(IndexedFasta::open).transpose()?;
+    let mut errors = 0;
+    let mut reported_issues: HashSet<ReferenceIssue> = HashSet::new();
+    if let Some(fasta) = &mut fasta {
+        for issue in check_contigs(&header, fasta)? {
+            eprintln!("error: {}", issue);
+            reported_issues.insert(issue);
+            errors += 1;
+        }
+    }
+    let info_keys: HashSet<&str> = header
+        .header_lines
+        .iter()
+        .filter_map(|hl| match hl {
+            HeaderLine::Info { id, .. } => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+    let format_keys: HashSet<&str> = header
+        .header_lines
+        .iter()
+        .filter_map(|hl| match hl {
+            HeaderLine::Format { id, .. } => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut records = 0;
+    let mut reported: HashSet<String> = HashSet::new();
+    for (index, dl) in reader.into_iter().enumerate() {
+        let dl = match dl {
+            Ok(dl) => dl,
+            Err(e) => {
+                eprintln!("error: data line {}: {}", index + 1, e);
+                errors += 1;
+                continue;
+            }
+        };
+        records += 1;
+        if let Some(fasta) = &mut fasta {
+            if let Some(issue) = check_reference(&dl, fasta)? {
+                if !reported_issues.contains(&issue) {
+                    eprintln!("error: data line {}: {}", index + 1, issue);
+                    errors += 1;
+                    if let ReferenceIssue::MissingSequence(_) = issue {
+                        reported_issues.insert(issue);
+                    }
+                }
+            }
+        }
+        let mut undefined = vec![];
+        if let InfoType::Entries(entries) = &dl.info {
+            for entry in entries {
+                let key = entry
+                    .as_bytes()
+                    .split(|b| *b == b'=')
+                    .next()
+                    .unwrap_or_default();
+                let key = String::from_utf8_lossy(key);
+                if !info_keys.contains(key.as_ref()) {
+                    undefined.push(format!("INFO/{}", key));
+                }
+            }
+        }
+        if let Some(vcflib::body::FormatType::Entries(keys)) = &dl.format {
+            for key in keys {
+                if !format_keys.contains(key.as_str()) {
+                    undefined.push(format!("FORMAT/{}", key));
+                }
+            }
+            for (sample, values) in dl.samples.iter().enumerate() {
+                if matches!(values, SampleType::Entries(values) if values.len() > keys.len()) {
+                    eprintln!(
+                        "error: data line {}: sample `{}` has more values than FORMAT keys",
+                        index + 1,
+                        header.column_names[sample]
+                    );
+                    errors += 1;
+                }
+            }
+        }
+        for ids in dl.undeclared_alt_ids(&header) {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+            undefined.push(format!("ALT/<{}>", ids.join(":")));
+        }
+        for key in undefined {
+            if reported.insert(key.clone()) {
+                eprintln!(
+                    "error: data line {}: `{}` is not defined in the header",
+                    index + 1,
+                    key
+                );
+                errors += 1;
+            }
+        }
+    }
+    if errors > 0 {
+        return Err(anyhow::anyhow!(
+            "{} errors in {} data lines",
+            errors,
+            records
+        ));
+    }
+    println!("valid: {} data lines", records);
+    Ok(())
+}
+
+fn index(file: &str, force: bool) -> anyhow::Result<()> {
+    let index_path = format!("{}.tbi", file);
+    if !force && Path::new(&index_path).exists() {
+        return Err(anyhow::anyhow!(
+            "index `{}` already exists, use --force to overwrite it",
+            index_path
+        ));
+    }
+    let reader = BufReader::new(
+        File::open(file).map_err(|e| anyhow::anyhow!("could not open `{}`: {}", file, e))?,
+    );
+    let index = TabixIndex::build(reader)?;
+    index.write(File::create(&index_path)?)?;
+    Ok(())
+}
+
+fn compress(input: &Input, output: Option<&str>) -> anyhow::Result<()> {
+    let path = match (output, input.file.as_str()) {
+        (Some(output), _) => Some(output.to_string()),
+        (None, "-") => None,
+        (None, file) => Some(format!("{}.gz", file)),
+    };
+    let mut out = create_output(path.as_deref(), OutputType::Z)?;
+    io::copy(&mut open_input(&input.file)?, &mut out)?;
+    out.finish()?;
+    Ok(())
+}
+
+/// Runs a transform writing a whole file, which ends with a newline only if the input does.
+fn transform<F>(input: &Input, output: &Output, run: F) -> anyhow::Result<()>
+where
+    F: FnOnce(VCFParser<Box<dyn BufRead>>, &mut OutputWriter) -> anyhow::Result<()>,
+{
+    let parser = open_parser(input)?;
+    let mut out = create_output(output.output.as_deref(), output.output_type)?;
+    run(parser, &mut out)?;
+    out.finish()?;
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::View {
+            input,
+            selection,
+            samples,
+            no_header,
+            output,
+        } => view(&input, &selection, samples.as_deref(), no_header, &output),
+        Command::Query {
+            input,
+            format,
+            selection,
+            output,
+        } => query(&input, &format, &selection, output.as_deref()),
+        Command::Stats { input, json } => stats(&input, json),
+        Command::Qc {
+            input,
+            thresholds,
+            outliers,
+            json,
+        } => qc(&input, &thresholds, outliers, json),
+        Command::Validate { input, fasta_ref } => validate(&input, fasta_ref.as_deref()),
+        Command::Index { file, force } => index(&file, force),
+        Command::Compress { input, output } => compress(&input, output.as_deref()),
+        Command::Encrypt {
+            input,
+            key,
+            fields,
+            mode,
+            output,
+        } => {
+            let fields = fields
+                .iter()
+                .map(|field| Field::from_str(field))
+                .collect::<anyhow::Result<Vec<Field>>>()?;
+            let mode = match mode {
+                CipherMode::Authenticated => Mode::Authenticated,
+                CipherMode::Deterministic => Mode::Deterministic,
+                CipherMode::FormatPreserving => Mode::FormatPreserving,
+            };
+            let encryptor = Encryptor::with_mode(read_key(&key)?, fields, mode);
+            transform(&input, &output, |parser, out| {
+                encryptor.encrypt(parser, out)
+            })
+        }
+        Command::Decrypt { input, key, output } => {
+            let mut decryptor = Decryptor::new(read_key(&key)?);
+            transform(&input, &output, |parser, out| {
+                decryptor.decrypt(parser, out)
+            })
+        }
+        Command::Norm {
+            input,
+            split,
+            output,
+        } => transform(&input, &output, |parser, out| normalize(parser, out, split)),
+    }
+}
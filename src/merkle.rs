@@ -0,0 +1,462 @@
+use crate::{
+    body::DataLine,
+    crypto::{hex_decode, hex_encode},
+    header::HeaderLine,
+    parser::VCFParser,
+};
+use linked_hash_map::LinkedHashMap;
+use sha2::{Digest, Sha256};
+use std::{
+    convert::TryInto,
+    fmt::{Display, Error, Formatter},
+    io::BufRead,
+    str::FromStr,
+};
+
+/// A SHA-256 digest.
+pub type Hash = [u8; 32];
+
+/// The hash of a data line, computed over the bytes it is written as. Leaves and inner nodes are hashed
+/// with different prefixes, as in RFC 6962, so that one cannot be passed off as the other.
+pub fn leaf_hash(dl: &DataLine) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    let mut bytes = vec![];
+    // writing to a vector cannot fail
+    let _ = dl.write_to(&mut bytes);
+    hasher.update(&bytes);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The largest power of two smaller than `n`, where the tree of `n` leaves is split.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+fn subtree_root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&subtree_root(&leaves[..k]), &subtree_root(&leaves[k..]))
+        }
+    }
+}
+
+fn subtree_path(index: usize, leaves: &[Hash], path: &mut Vec<Hash>) {
+    let n = leaves.len();
+    if n <= 1 {
+        return;
+    }
+    let k = split_point(n);
+    if index < k {
+        subtree_path(index, &leaves[..k], path);
+        path.push(subtree_root(&leaves[k..]));
+    } else {
+        subtree_path(index - k, &leaves[k..], path);
+        path.push(subtree_root(&leaves[..k]));
+    }
+}
+
+/// A Merkle tree over a sequence of data lines, following the construction of RFC 6962.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTree {
+    leaves: Vec<Hash>,
+}
+
+impl MerkleTree {
+    pub fn new() -> Self {
+        MerkleTree::default()
+    }
+
+    /// Appends a data line to the tree and returns its index.
+    pub fn push(&mut self, dl: &DataLine) -> usize {
+        self.leaves.push(leaf_hash(dl));
+        self.leaves.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn root(&self) -> Hash {
+        subtree_root(&self.leaves)
+    }
+
+    /// Returns the proof that the data line with the given index is included in the tree.
+    pub fn proof(&self, index: usize) -> Option<InclusionProof> {
+        if index >= self.leaves.len() {
+            return None;
+        }
+        let mut path = vec![];
+        subtree_path(index, &self.leaves, &mut path);
+        Some(InclusionProof {
+            index,
+            size: self.leaves.len(),
+            path,
+        })
+    }
+}
+
+/// Proves that a data line is included in a Merkle tree with a given root, without the other
+/// data lines of the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InclusionProof {
+    /// The index of the data line in the tree.
+    pub index: usize,
+
+    /// The number of data lines in the tree.
+    pub size: usize,
+
+    /// The hashes of the sibling subtrees, from the leaf up to the root.
+    pub path: Vec<Hash>,
+}
+
+impl InclusionProof {
+    /// Whether the given data line is included in the tree with the given root.
+    pub fn verify(&self, dl: &DataLine, root: &Hash) -> bool {
+        if self.index >= self.size {
+            return false;
+        }
+        // verification algorithm of RFC 9162, section 2.1.3.2
+        let (mut f, mut s) = (self.index, self.size - 1);
+        let mut hash = leaf_hash(dl);
+        for sibling in &self.path {
+            if s == 0 {
+                return false;
+            }
+            if f & 1 == 1 || f == s {
+                hash = node_hash(sibling, &hash);
+                while f & 1 == 0 && f != 0 {
+                    f >>= 1;
+                    s >>= 1;
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+            f >>= 1;
+            s >>= 1;
+        }
+        s == 0 && hash == *root
+    }
+}
+
+impl Display for InclusionProof {
+    /// Formats the proof as `index:size:hash,hash,...`, with the hashes in hex.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let path: Vec<String> = self.path.iter().map(|h| hex_encode(h)).collect();
+        write!(f, "{}:{}:{}", self.index, self.size, path.join(","))
+    }
+}
+
+impl FromStr for InclusionProof {
+    type Err = anyhow::Error;
+
+    fn from_str(proof_str: &str) -> anyhow::Result<Self> {
+        let parts: Vec<&str> = proof_str.trim().splitn(3, ':').collect();
+        if parts.len() != 3 {
+            return Err(anyhow::anyhow!("invalid inclusion proof `{}`", proof_str));
+        }
+        let path = if parts[2].is_empty() {
+            vec![]
+        } else {
+            parts[2]
+                .split(',')
+                .map(parse_hash)
+                .collect::<anyhow::Result<Vec<Hash>>>()?
+        };
+        Ok(InclusionProof {
+            index: parts[0].parse()?,
+            size: parts[1].parse()?,
+            path,
+        })
+    }
+}
+
+/// Parses a hash from its hex representation.
+pub fn parse_hash(hash_str: &str) -> anyhow::Result<Hash> {
+    hex_decode(hash_str)?
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid hash `{}`, (expected 32 bytes)", hash_str))
+}
+
+/// One Merkle tree per contig, over the data lines of the contig in file order.
+#[derive(Debug, Clone, Default)]
+pub struct ContigTrees {
+    trees: LinkedHashMap<String, MerkleTree>,
+}
+
+impl ContigTrees {
+    pub fn new() -> Self {
+        ContigTrees::default()
+    }
+
+    /// Appends a data line to the tree of its contig and returns its index in that tree.
+    pub fn push(&mut self, dl: &DataLine) -> usize {
+        if !self.trees.contains_key(&dl.chromosome) {
+            self.trees.insert(dl.chromosome.clone(), MerkleTree::new());
+        }
+        self.trees.get_mut(&dl.chromosome).unwrap().push(dl)
+    }
+
+    pub fn get(&self, contig: &str) -> Option<&MerkleTree> {
+        self.trees.get(contig)
+    }
+
+    /// The `##MERKLE` lines recording the root of every tree, in the order the contigs appeared.
+    pub fn header_lines(&self) -> Vec<HeaderLine> {
+        self.trees
+            .iter()
+            .map(|(contig, tree)| HeaderLine::Merkle {
+                id: contig.to_string(),
+                records: tree.len(),
+                root: hex_encode(&tree.root()),
+            })
+            .collect()
+    }
+}
+
+/// Recomputes the Merkle roots of a file written with `VCFWriter::with_merkle` and checks them
+/// against the `##MERKLE` lines after its data lines.
+pub fn verify<R: BufRead>(parser: VCFParser<R>) -> anyhow::Result<()> {
+    let mut reader = parser.reader;
+    let mut trees = ContigTrees::new();
+    for dl in reader.iter() {
+        trees.push(&dl?);
+    }
+    let expected: Vec<&HeaderLine> = reader.trailer_lines.iter().collect();
+    if expected.is_empty() {
+        return Err(anyhow::anyhow!("no Merkle roots found"));
+    }
+    let actual = trees.header_lines();
+    for hl in actual.iter().chain(expected.iter().copied()) {
+        if let HeaderLine::Merkle { id, .. } = hl {
+            if !actual.contains(hl) || !expected.contains(&hl) {
+                return Err(contig_mismatch(id));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn contig_mismatch(contig: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "records of contig `{}` do not match their Merkle root",
+        contig
+    )
+}
+
+/// Produces inclusion proofs for the data lines at the given position, along with the data lines.
+/// The proofs are verified against the root of the contig in the `##MERKLE` lines after the data
+/// lines, so an error is returned if the records of the contig were modified.
+pub fn prove<R: BufRead>(
+    parser: VCFParser<R>,
+    chromosome: &str,
+    position: u64,
+) -> anyhow::Result<Vec<(DataLine, InclusionProof)>> {
+    let mut reader = parser.reader;
+    let mut tree = MerkleTree::new();
+    let mut found = vec![];
+    for dl in reader.iter() {
+        let dl = dl?;
+        if dl.chromosome == chromosome {
+            let index = tree.push(&dl);
+            if dl.position == position {
+                found.push((index, dl));
+            }
+        }
+    }
+    let expected = root(&reader.trailer_lines, chromosome)?
+        .ok_or_else(|| anyhow::anyhow!("no Merkle root found for contig `{}`", chromosome))?;
+    found
+        .into_iter()
+        .map(|(index, dl)| {
+            let proof = tree.proof(index).unwrap();
+            if !proof.verify(&dl, &expected) {
+                return Err(contig_mismatch(chromosome));
+            }
+            Ok((dl, proof))
+        })
+        .collect()
+}
+
+/// Returns the Merkle root of the given contig recorded in the `##MERKLE` lines, if any.
+pub fn root(header_lines: &[HeaderLine], contig: &str) -> anyhow::Result<Option<Hash>> {
+    for hl in header_lines {
+        if let HeaderLine::Merkle { id, root, .. } = hl {
+            if id == contig {
+                return Ok(Some(parse_hash(root)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{merkle::*, parser::VCFWriter};
+    use std::{
+        cell::RefCell,
+        io::{self, Write},
+        rc::Rc,
+    };
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+##contig=<ID=20,length=62435964>
+##contig=<ID=21,length=48129895>
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMP001
+20\t14370\trs6054257\tG\tA\t29\tPASS\t.\tGT\t0|0
+20\t17330\t.\tT\tA\t3\tq10\t.\tGT\t0|1
+20\t1110696\trs6040355\tA\tG,T\t67\tPASS\t.\tGT\t1|2
+20\t1230237\t.\tT\t.\t47\tPASS\t.\tGT\t0|0
+20\t1234567\tmicrosat1\tGTC\tG,GTCT\t50\tPASS\t.\tGT\t0/1
+21\t9411239\t.\tC\tT\t50\tPASS\t.\tGT\t0/1";
+
+    /// Writes the file with Merkle trees.
+    fn write_with_merkle(vcf: &str) -> String {
+        let parser = VCFParser::new(vcf.as_bytes()).unwrap();
+        let mut writer = VCFWriter::with_merkle(vec![], &parser.header).unwrap();
+        for dl in parser.reader {
+            writer.write_data_line(&dl.unwrap()).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    /// A writer whose output can be read while the `VCFWriter` still owns it.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tree() {
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let lines: Vec<DataLine> = parser.reader.into_iter().map(|dl| dl.unwrap()).collect();
+        // every size up to 6 leaves, including unbalanced trees
+        for size in 1..=lines.len() {
+            let mut tree = MerkleTree::new();
+            for dl in &lines[..size] {
+                tree.push(dl);
+            }
+            let root = tree.root();
+            for (index, dl) in lines[..size].iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(dl, &root));
+                assert_eq!(InclusionProof::from_str(&proof.to_string()).unwrap(), proof);
+                // the proof is bound to the data line and its index
+                let other = &lines[(index + 1) % lines.len()];
+                assert!(!proof.verify(other, &root));
+                let moved = InclusionProof {
+                    index: (index + 1) % size,
+                    ..proof.clone()
+                };
+                assert!(size == 1 || !moved.verify(dl, &root));
+            }
+            assert!(tree.proof(size).is_none());
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let output = write_with_merkle(SMALL_VCF);
+        let trailer = output.strip_prefix(SMALL_VCF).unwrap();
+        let lines: Vec<&str> = trailer.lines().skip(1).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("##MERKLE=<ID=20,Records=5,Root="));
+        assert!(lines[1].starts_with("##MERKLE=<ID=21,Records=1,Root="));
+
+        assert!(verify(VCFParser::new(output.as_bytes()).unwrap()).is_ok());
+        let tampered = output.replace("rs6040355", "rs6040356");
+        let error = verify(VCFParser::new(tampered.as_bytes()).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "records of contig `20` do not match their Merkle root"
+        );
+        assert!(verify(VCFParser::new(SMALL_VCF.as_bytes()).unwrap()).is_err());
+        // dropped contigs are detected too
+        let dropped = output.replace("\n21\t9411239\t.\tC\tT\t50\tPASS\t.\tGT\t0/1", "");
+        assert!(verify(VCFParser::new(dropped.as_bytes()).unwrap()).is_err());
+        // only `##MERKLE` lines may follow the data lines, and no data line may follow them
+        let appended = format!("{}\n21\t9411240\t.\tC\tT\t50\tPASS\t.\tGT\t0/1", output);
+        assert!(verify(VCFParser::new(appended.as_bytes()).unwrap()).is_err());
+        let other = format!("{}\n##contig=<ID=22>", output);
+        assert!(verify(VCFParser::new(other.as_bytes()).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_writer_streams() {
+        // every data line is written as it comes, not held until the roots are known
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let output = SharedOutput::default();
+        let mut writer = VCFWriter::with_merkle(output.clone(), &parser.header).unwrap();
+        for dl in parser.reader {
+            let dl = dl.unwrap();
+            writer.write_data_line(&dl).unwrap();
+            assert!(output.0.borrow().ends_with(dl.to_string().as_bytes()));
+        }
+        assert_eq!(output.0.borrow().as_slice(), SMALL_VCF.as_bytes());
+        assert_eq!(writer.merkle().unwrap().get("20").unwrap().len(), 5);
+        writer.finish().unwrap();
+        assert_eq!(
+            VCFParser::new(output.0.borrow().as_slice())
+                .unwrap()
+                .reader
+                .into_iter()
+                .map(Result::unwrap)
+                .count(),
+            6
+        );
+    }
+
+    #[test]
+    fn test_prove() {
+        let output = write_with_merkle(SMALL_VCF);
+        let parser = VCFParser::new(output.as_bytes()).unwrap();
+        let proofs = prove(parser, "20", 1110696).unwrap();
+        assert_eq!(proofs.len(), 1);
+        let (dl, proof) = &proofs[0];
+        assert_eq!(proof.index, 2);
+        let mut reader = VCFParser::new(output.as_bytes()).unwrap().reader;
+        reader.iter().for_each(drop);
+        let root = root(&reader.trailer_lines, "20").unwrap().unwrap();
+        assert!(proof.verify(dl, &root));
+
+        let parser = VCFParser::new(output.as_bytes()).unwrap();
+        assert!(prove(parser, "20", 1).unwrap().is_empty());
+        // proofs of modified records or of contigs without a root are errors
+        let tampered = output.replace("rs6040355", "rs6040356");
+        let parser = VCFParser::new(tampered.as_bytes()).unwrap();
+        assert!(prove(parser, "20", 1110696).is_err());
+        let parser = VCFParser::new(output.as_bytes()).unwrap();
+        assert!(prove(parser, "22", 1).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    body::{AltType, DataLine, InfoType, SampleType},
+    header::{Header, HeaderLine, Number},
+    parser::{transform, VCFParser},
+    text::Text,
+};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+/// Splits multiallelic data lines into biallelic ones, like `bcftools norm -m-`, using the
+/// `Number` of INFO and FORMAT keys to pick the values of each allele.
+#[derive(Debug)]
+pub struct Normalizer {
+    info_numbers: HashMap<String, Number>,
+    format_numbers: HashMap<String, Number>,
+}
+
+impl Normalizer {
+    pub fn new(header: &Header) -> Self {
+        let mut info_numbers = HashMap::new();
+        let mut format_numbers = HashMap::new();
+        for hl in &header.header_lines {
+            match hl {
+                HeaderLine::Info { id, number, .. } => {
+                    info_numbers.insert(id.to_string(), number.clone());
+                }
+                HeaderLine::Format { id, number, .. } => {
+                    format_numbers.insert(id.to_string(), number.clone());
+                }
+                _ => {}
+            }
+        }
+        Normalizer {
+            info_numbers,
+            format_numbers,
+        }
+    }
+
+    /// Returns one data line per alternate allele. Values with `Number=A`, `R` or `G` are
+    /// restricted to the allele, and genotypes are recoded so that the allele is `1` and any other
+    /// alternate allele is `0`. Other values are copied to every data line.
+    pub fn split(&self, dl: &DataLine) -> Vec<DataLine> {
+        let alts = match &dl.alternative {
+            AltType::Entries(alts) if alts.len() > 1 => alts,
+            _ => return vec![dl.clone()],
+        };
+        (1..=alts.len())
+            .map(|allele| {
+                let mut split = dl.clone();
+                split.alternative = AltType::Entries(vec![alts[allele - 1].clone()]);
+                if let InfoType::Entries(entries) = &mut split.info {
+                    for entry in entries.iter_mut() {
+                        let bytes = entry.as_bytes();
+                        let index = match bytes.iter().position(|b| *b == b'=') {
+                            Some(index) => index,
+                            None => continue,
+                        };
+                        let number = std::str::from_utf8(&bytes[..index])
+                            .ok()
+                            .and_then(|key| self.info_numbers.get(key));
+                        if let Some(number) = number {
+                            let value = &bytes[index + 1..];
+                            let mut split_entry = bytes[..index + 1].to_vec();
+                            split_entry.extend(split_values(value, number, allele, alts.len() + 1));
+                            *entry = Text::from(split_entry);
+                        }
+                    }
+                }
+                let gt_index = dl.format_index("GT");
+                let format_numbers: Vec<Option<&Number>> = match &dl.format {
+                    Some(crate::body::FormatType::Entries(keys)) => keys
+                        .iter()
+                        .map(|key| self.format_numbers.get(key))
+                        .collect(),
+                    _ => vec![],
+                };
+                for sample in split.samples.iter_mut() {
+                    if let SampleType::Entries(values) = sample {
+                        for (index, value) in values.iter_mut().enumerate() {
+                            if Some(index) == gt_index {
+                                *value = Text::from(split_genotype(value.as_bytes(), allele));
+                            } else if let Some(Some(number)) = format_numbers.get(index) {
+                                let values =
+                                    split_values(value.as_bytes(), number, allele, alts.len() + 1);
+                                *value = Text::from(values);
+                            }
+                        }
+                    }
+                }
+                split
+            })
+            .collect()
+    }
+}
+
+/// Picks the values of an allele, given the number of values of the key and the number of alleles
+/// of the data line, including the reference. Values of unexpected length are kept.
+fn split_values(value: &[u8], number: &Number, allele: usize, alleles: usize) -> Vec<u8> {
+    if value == b"." {
+        return value.to_vec();
+    }
+    let values: Vec<&[u8]> = value.split(|b| *b == b',').collect();
+    let indices = match number {
+        Number::Allele if values.len() == alleles - 1 => vec![allele - 1],
+        Number::Reference if values.len() == alleles => vec![0, allele],
+        // haploid genotypes have one value per allele
+        Number::Genotype if values.len() == alleles => vec![0, allele],
+        Number::Genotype if values.len() == alleles * (alleles + 1) / 2 => {
+            let het = allele * (allele + 1) / 2;
+            vec![0, het, het + allele]
+        }
+        _ => return value.to_vec(),
+    };
+    indices
+        .iter()
+        .map(|i| values[*i])
+        .collect::<Vec<&[u8]>>()
+        .join(&b',')
+}
+
+/// Recodes a genotype, e.g., `1/2`, for a biallelic data line of the allele.
+fn split_genotype(genotype: &[u8], allele: usize) -> Vec<u8> {
+    let mut split = Vec::with_capacity(genotype.len());
+    let mut rest = genotype;
+    loop {
+        let end = rest
+            .iter()
+            .position(|b| *b == b'/' || *b == b'|')
+            .unwrap_or(rest.len());
+        let index = std::str::from_utf8(&rest[..end])
+            .ok()
+            .and_then(|a| a.parse::<usize>().ok());
+        split.extend_from_slice(match index {
+            Some(a) if a == allele => b"1",
+            Some(_) => b"0",
+            None => &rest[..end],
+        });
+        if end == rest.len() {
+            break;
+        }
+        split.push(rest[end]);
+        rest = &rest[end + 1..];
+    }
+    split
+}
+
+/// Removes the bases shared by the end, and then by the start, of all the alleles, keeping at
+/// least one base in each, e.g., `POS=1 REF=CTCC ALT=CCC,CCCC` becomes `POS=1 REF=CT ALT=C,CC`.
+/// Data lines with symbolic, breakend or missing alleles are not changed.
+pub fn trim_alleles(dl: &mut DataLine) {
+    let alts = match &mut dl.alternative {
+        AltType::Entries(alts) => alts,
+        AltType::Missing => return,
+    };
+    let is_sequence = |allele: &str| {
+        !allele.is_empty()
+            && allele
+                .bytes()
+                .all(|b| matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'))
+    };
+    if !is_sequence(&dl.reference) || !alts.iter().all(|alt| is_sequence(alt)) {
+        return;
+    }
+    let shared = |alleles: &[&String], index: &dyn Fn(&str) -> Option<u8>| {
+        let first = index(alleles[0]).map(|b| b.to_ascii_uppercase());
+        first.is_some()
+            && alleles
+                .iter()
+                .all(|a| a.len() > 1 && index(a).map(|b| b.to_ascii_uppercase()) == first)
+    };
+    loop {
+        let alleles: Vec<&String> = std::iter::once(&dl.reference).chain(alts.iter()).collect();
+        if !shared(&alleles, &|a: &str| a.bytes().last()) {
+            break;
+        }
+        dl.reference.pop();
+        alts.iter_mut().for_each(|alt| {
+            alt.pop();
+        });
+    }
+    loop {
+        let alleles: Vec<&String> = std::iter::once(&dl.reference).chain(alts.iter()).collect();
+        if !shared(&alleles, &|a: &str| a.bytes().next()) {
+            break;
+        }
+        dl.reference.remove(0);
+        alts.iter_mut().for_each(|alt| {
+            alt.remove(0);
+        });
+        dl.position += 1;
+    }
+}
+
+/// Trims the alleles of every data line of the parser and, if `split` is set, splits multiallelic
+/// data lines first.
+pub fn normalize<R: BufRead, W: Write>(
+    parser: VCFParser<R>,
+    writer: W,
+    split: bool,
+) -> anyhow::Result<()> {
+    let normalizer = Normalizer::new(&parser.header);
+    transform(parser, writer, |dl| {
+        let mut lines = if split {
+            normalizer.split(&dl)
+        } else {
+            vec![dl]
+        };
+        lines.iter_mut().for_each(trim_alleles);
+        Ok(lines)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        header::Number,
+        norm::*,
+        test_util::{parser, SMALL_VCF},
+    };
+
+    fn normalized(split: bool) -> Vec<String> {
+        let mut output = vec![];
+        normalize(parser(SMALL_VCF), &mut output, split).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_split() {
+        let lines = normalized(true);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[2],
+            "20\t1110696\trs6040355\tA\tG\t67\tPASS\tNS=2;DP=10;AF=0.333;AA=T;DB\t\
+             GT:GQ:DP:HQ\t1|0:21:6:23,27\t0|1:2:0:18,2\t0/0:35:4"
+        );
+        assert_eq!(
+            lines[3],
+            "20\t1110696\trs6040355\tA\tT\t67\tPASS\tNS=2;DP=10;AF=0.667;AA=T;DB\t\
+             GT:GQ:DP:HQ\t0|1:21:6:23,27\t1|0:2:0:18,2\t1/1:35:4"
+        );
+        assert_eq!(
+            lines[6],
+            "20\t1234569\tmicrosat1\tC\tCT\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t0/0:35:4\t0/1:17:2\t0/0:40:3"
+        );
+        let lines = normalized(false);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[4].split('\t').nth(4), Some("G,GTCT"));
+    }
+
+    #[test]
+    fn test_split_values() {
+        let split = |value: &[u8], number: &Number, allele: usize| {
+            String::from_utf8(split_values(value, number, allele, 3)).unwrap()
+        };
+        assert_eq!(split(b"0.25,0.5", &Number::Allele, 2), "0.5");
+        assert_eq!(split(b"5,2,3", &Number::Reference, 1), "5,2");
+        assert_eq!(split(b"5,2,3", &Number::Reference, 2), "5,3");
+        assert_eq!(split(b"1,2,3,4,5,6", &Number::Genotype, 1), "1,2,3");
+        assert_eq!(split(b"1,2,3,4,5,6", &Number::Genotype, 2), "1,4,6");
+        // haploid genotypes, missing values and values of unexpected length
+        assert_eq!(split(b"1,2,3", &Number::Genotype, 2), "1,3");
+        assert_eq!(split(b".", &Number::Genotype, 2), ".");
+        assert_eq!(split(b"1,2", &Number::Reference, 2), "1,2");
+    }
+
+    #[test]
+    fn test_trim() {
+        let column_names = vec![];
+        let trimmed = |line: &str| {
+            let mut dl = DataLine::new(line, &column_names).unwrap();
+            trim_alleles(&mut dl);
+            format!("{} {} {}", dl.position, dl.reference, dl.alternative)
+        };
+        assert_eq!(trimmed("1\t5\t.\tGCACA\tGCA\t.\t.\t."), "5 GCA G");
+        assert_eq!(trimmed("1\t5\t.\tAAT\tAAC\t.\t.\t."), "7 T C");
+        assert_eq!(trimmed("1\t5\t.\tA\tAT\t.\t.\t."), "5 A AT");
+        assert_eq!(trimmed("1\t5\t.\tAT\t<DEL>\t.\t.\t."), "5 AT <DEL>");
+        assert_eq!(trimmed("1\t5\t.\tAT\tA,*\t.\t.\t."), "5 AT A,*");
+        assert_eq!(trimmed("1\t1\t.\tCTCC\tCCC,CCCC\t.\t.\t."), "1 CT C,CC");
+        assert_eq!(split_genotype(b"2|.", 2), b"1|.");
+        assert_eq!(split_genotype(b"1/2/3", 3), b"0/0/1");
+        assert_eq!(split_genotype(b"10/2", 10), b"1/0");
+    }
+}
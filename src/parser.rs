@@ -1,8 +1,11 @@
 use crate::{
     body::DataLine,
     header::{parse_column_names, parse_version, Header, HeaderLine},
+    merkle::ContigTrees,
+    text::checked_str,
 };
 use std::{
+    borrow::Cow,
     io,
     io::{BufRead, BufReader, Read, Write},
 };
@@ -17,73 +20,266 @@ pub struct VCFParser<R: BufRead> {
 pub struct VCFReader<R: BufRead> {
     pub column_names: Vec<String>,
     pub reader: R,
+
+    /// The `##MERKLE` lines found after the data lines so far, see `VCFWriter::with_merkle`.
+    pub trailer_lines: Vec<HeaderLine>,
+
+    pub limits: Limits,
+
+    pub utf8_mode: Utf8Mode,
+
+    /// Whether the last line read, of the header or the data, ended with a newline.
+    pub final_newline: bool,
 }
 
 pub const FIXED_COLUMNS: &[&str] = &["CHROM", "POS", "ID", "REF", "ALT", "QUAL", "FILTER", "INFO"];
 
+/// Hard limits on the input of a parser, so that a hostile input cannot exhaust the memory of the
+/// process, e.g., the heap of an enclave, with a single giant line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// The maximum length in bytes of any line, without its newline.
+    pub max_line_bytes: usize,
+
+    /// The maximum length in bytes of the header, including the column names line.
+    pub max_header_bytes: usize,
+
+    /// The maximum number of `##` lines in the header, and separately after the data lines.
+    pub max_header_lines: usize,
+
+    /// The maximum number of sample columns.
+    pub max_samples: usize,
+
+    /// The maximum number of alternate alleles of a data line.
+    pub max_alleles: usize,
+}
+
+impl Default for Limits {
+    /// Limits that fit the heap of an enclave: lines of up to 1 MiB and a header of up to 16 MiB.
+    fn default() -> Self {
+        Limits {
+            max_line_bytes: 1 << 20,
+            max_header_bytes: 1 << 24,
+            max_header_lines: 1 << 16,
+            max_samples: 1 << 18,
+            max_alleles: 1 << 12,
+        }
+    }
+}
+
+/// How a parser handles bytes that are not valid UTF-8, e.g., Latin-1 descriptions in old files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Utf8Mode {
+    /// Free text, i.e., descriptions, the payloads of unknown header lines and the INFO and
+    /// sample values, is kept as bytes and only fails when read as text, see `Text::as_str`. Any
+    /// other invalid text is an error, naming the column of data lines. Reading goes on after an
+    /// invalid data line.
+    Strict,
+
+    /// Invalid bytes are replaced with `U+FFFD`.
+    Lossy,
+}
+
 impl<R: Read> VCFParser<BufReader<R>> {
     pub fn new(read: R) -> anyhow::Result<Self> {
-        let mut reader = BufReader::new(read);
+        VCFParser::with_limits(read, Limits::default())
+    }
 
-        let mut line = String::new();
-        let mut version = "".to_string();
-        let mut header_lines = vec![];
-        let mut column_names = vec![];
+    pub fn with_limits(read: R, limits: Limits) -> anyhow::Result<Self> {
+        VCFParser::from_source(BufReader::new(read), limits, Utf8Mode::Strict)
+    }
+
+    /// Creates a parser replacing bytes that are not valid UTF-8, see `Utf8Mode::Lossy`.
+    pub fn lossy(read: R) -> anyhow::Result<Self> {
+        VCFParser::from_source(BufReader::new(read), Limits::default(), Utf8Mode::Lossy)
+    }
+}
+
+impl<R: BufRead> VCFParser<R> {
+    /// Parses the header from any buffered source, e.g., a `ChannelReader` over a sealed channel
+    /// with an untrusted host. Lines may end with LF or CRLF and a leading byte order mark is
+    /// skipped.
+    pub fn from_source(mut reader: R, limits: Limits, utf8_mode: Utf8Mode) -> anyhow::Result<Self> {
+        let mut bytes = vec![];
+        let mut header = Header::new("".to_string(), vec![], vec![]);
+        let mut header_bytes = 0;
+        let mut final_newline = false;
         loop {
-            line.clear();
-            let read_bytes = reader.read_line(&mut line)?;
+            bytes.clear();
+            let read_bytes = read_line(&mut reader, &mut bytes, limits.max_line_bytes)?;
             if read_bytes == 0 {
                 break;
             }
-
-            // remove newline
-            if line.ends_with('\n') {
-                line.pop();
+            final_newline = read_bytes > bytes.len();
+            if header_bytes == 0 && bytes.starts_with(BOM) {
+                bytes.drain(..BOM.len());
+            }
+            header_bytes += read_bytes;
+            if header_bytes > limits.max_header_bytes {
+                return Err(anyhow::anyhow!(
+                    "header longer than the limit of {} bytes",
+                    limits.max_header_bytes
+                ));
             }
 
-            if line.starts_with("##fileformat=") {
-                version = parse_version(&line)?;
-            } else if line.starts_with("##") {
-                header_lines.push(line.parse::<HeaderLine>()?);
-            } else if line.starts_with('#') {
-                column_names = parse_column_names(&line)?;
+            let line = decode(&bytes, utf8_mode);
+            if line.starts_with(b"##fileformat=") {
+                header.version.value = parse_version(checked_str(&line)?)?;
+            } else if line.starts_with(b"##") {
+                if header.header_lines.len() == limits.max_header_lines {
+                    return Err(too_many_header_lines(limits.max_header_lines));
+                }
+                let hl = HeaderLine::from_bytes(&line)?;
+                header.keep_original(&line, &hl);
+                header.header_lines.push(hl);
+            } else if line.starts_with(b"#") {
+                header.column_names = parse_column_names(checked_str(&line)?)?;
+                if header.column_names.len() > limits.max_samples {
+                    return Err(anyhow::anyhow!(
+                        "{} samples, more than the limit of {}",
+                        header.column_names.len(),
+                        limits.max_samples
+                    ));
+                }
                 break;
             } else {
                 return Err(anyhow::anyhow!(
                     "Invalid line while parsing header: `{}`",
-                    line
+                    String::from_utf8_lossy(&line)
                 ));
             }
         }
 
         Ok(VCFParser {
             reader: VCFReader {
-                column_names: column_names.clone(),
+                column_names: header.column_names.clone(),
                 reader,
+                trailer_lines: vec![],
+                limits,
+                utf8_mode,
+                final_newline,
             },
-            header: Header::new(version, header_lines, column_names),
+            header,
         })
     }
 }
 
+fn too_many_header_lines(max_header_lines: usize) -> anyhow::Error {
+    anyhow::anyhow!("more header lines than the limit of {}", max_header_lines)
+}
+
+/// The UTF-8 byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Reads a line of bytes, without its LF or CRLF ending, and returns the number of bytes read.
+/// Fails instead of reading more than `max_bytes` bytes before the newline.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    max_bytes: usize,
+) -> anyhow::Result<usize> {
+    let read_bytes = reader
+        .by_ref()
+        .take((max_bytes as u64).saturating_add(2))
+        .read_until(b'\n', line)?;
+    let complete = line.ends_with(b"\n");
+    if complete {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    if line.len() > max_bytes {
+        // the rest of the line is skipped, so that reading goes on with the next line
+        if !complete {
+            skip_line(reader)?;
+        }
+        return Err(anyhow::anyhow!(
+            "line longer than the limit of {} bytes",
+            max_bytes
+        ));
+    }
+    Ok(read_bytes)
+}
+
+/// Skips the rest of the current line, without holding it in memory.
+fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        match buffer.iter().position(|b| *b == b'\n') {
+            Some(index) => {
+                reader.consume(index + 1);
+                return Ok(());
+            }
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Decodes a line according to the UTF-8 mode. Strict decoding keeps the bytes as they are.
+fn decode(line: &[u8], utf8_mode: Utf8Mode) -> Cow<'_, [u8]> {
+    match utf8_mode {
+        Utf8Mode::Strict => Cow::Borrowed(line),
+        Utf8Mode::Lossy => match String::from_utf8_lossy(line) {
+            Cow::Borrowed(_) => Cow::Borrowed(line),
+            Cow::Owned(line) => Cow::Owned(line.into_bytes()),
+        },
+    }
+}
+
 impl<R: BufRead> VCFReader<R> {
+    /// Reads the next data line. The `##MERKLE` lines after the data lines are kept in
+    /// `trailer_lines` and a data line after them is an error.
     pub fn next_item(&mut self) -> Option<anyhow::Result<DataLine>> {
-        let mut line = String::new();
-        let result = self.reader.read_line(&mut line);
-        match result {
-            Ok(read_bytes) => {
-                if read_bytes == 0 {
-                    None
-                } else {
-                    // remove newline
-                    if line.ends_with('\n') {
-                        line.pop();
-                    }
-                    Some(DataLine::new(&line, &self.column_names))
+        let mut bytes = vec![];
+        loop {
+            bytes.clear();
+            let read_bytes =
+                match read_line(&mut self.reader, &mut bytes, self.limits.max_line_bytes) {
+                    Ok(0) => return None,
+                    Ok(read_bytes) => read_bytes,
+                    Err(e) => return Some(Err(e)),
+                };
+            self.final_newline = read_bytes > bytes.len();
+            let line = decode(&bytes, self.utf8_mode);
+            if line.starts_with(b"##") {
+                if let Err(e) = self.push_trailer_line(&line) {
+                    return Some(Err(e));
                 }
+            } else if !self.trailer_lines.is_empty() {
+                return Some(Err(anyhow::anyhow!(
+                    "data line after the trailing `##MERKLE` lines"
+                )));
+            } else {
+                return Some(DataLine::with_max_alleles(
+                    &line,
+                    &self.column_names,
+                    self.limits.max_alleles,
+                ));
+            }
+        }
+    }
+
+    fn push_trailer_line(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        if self.trailer_lines.len() == self.limits.max_header_lines {
+            return Err(too_many_header_lines(self.limits.max_header_lines));
+        }
+        match HeaderLine::from_bytes(line)? {
+            hl @ HeaderLine::Merkle { .. } => self.trailer_lines.push(hl),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid line after the data lines: `{}`",
+                    String::from_utf8_lossy(line)
+                ))
             }
-            Err(e) => Some(Err(anyhow::anyhow!("Could not read: `{:?}`", e))),
         }
+        Ok(())
     }
 
     pub fn iter(&mut self) -> Iter<'_, R> {
@@ -136,38 +332,297 @@ impl<R: BufRead> Iterator for IntoIter<R> {
 
 pub struct VCFWriter<W: Write> {
     writer: W,
+    merkle: Option<ContigTrees>,
+    final_newline: bool,
 }
 
 impl<W: Write> VCFWriter<W> {
     pub fn new(mut writer: W, header: &Header) -> anyhow::Result<VCFWriter<W>> {
-        // write version
-        writeln!(writer, "{}", header.version)?;
+        header.write_lines(&mut writer)?;
+        write_column_line(&mut writer, header)?;
+        Ok(VCFWriter {
+            writer,
+            merkle: None,
+            final_newline: false,
+        })
+    }
+
+    /// Creates a writer that also computes a Merkle tree over the data lines of every contig.
+    /// The roots are written in `##MERKLE` lines after the data lines by `finish`.
+    pub fn with_merkle(writer: W, header: &Header) -> anyhow::Result<VCFWriter<W>> {
+        let mut vcf_writer = VCFWriter::new(writer, header)?;
+        vcf_writer.merkle = Some(ContigTrees::new());
+        Ok(vcf_writer)
+    }
+
+    /// The Merkle trees of the data lines written so far, if enabled.
+    pub fn merkle(&self) -> Option<&ContigTrees> {
+        self.merkle.as_ref()
+    }
 
-        // write header lines
-        for hl in &header.header_lines {
-            writeln!(writer, "{}", hl)?;
+    pub fn write_data_line(&mut self, dl: &DataLine) -> io::Result<()> {
+        if let Some(merkle) = self.merkle.as_mut() {
+            merkle.push(dl);
         }
+        self.writer.write_all(b"\n")?;
+        dl.write_to(&mut self.writer)
+    }
 
-        // write fixed columns
-        for (index, column) in FIXED_COLUMNS.iter().enumerate() {
-            if index == 0 {
-                write!(writer, "#{}", column)?;
-            } else {
-                write!(writer, "\t{}", column)?;
+    /// Sets whether `finish` ends the output with a newline, e.g., to keep that of the input.
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        self.final_newline = final_newline;
+    }
+
+    /// Writes the `##MERKLE` lines, if enabled, and the final newline, if set, and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(merkle) = &self.merkle {
+            for hl in merkle.header_lines() {
+                write!(self.writer, "\n{}", hl)?;
             }
         }
-        // ... and custom columns
-        if !header.column_names.is_empty() {
-            write!(writer, "\tFORMAT")?;
-            for cn in &header.column_names {
-                write!(writer, "\t{}", cn)?;
-            }
+        if self.final_newline {
+            writeln!(self.writer)?;
         }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
 
-        Ok(VCFWriter { writer })
+/// Writes the `#CHROM` line, without its newline.
+fn write_column_line<W: Write>(writer: &mut W, header: &Header) -> io::Result<()> {
+    // write fixed columns
+    for (index, column) in FIXED_COLUMNS.iter().enumerate() {
+        if index == 0 {
+            write!(writer, "#{}", column)?;
+        } else {
+            write!(writer, "\t{}", column)?;
+        }
+    }
+    // ... and custom columns
+    if !header.column_names.is_empty() {
+        write!(writer, "\tFORMAT")?;
+        for cn in &header.column_names {
+            write!(writer, "\t{}", cn)?;
+        }
     }
+    Ok(())
+}
 
-    pub fn write_data_line(&mut self, dl: &DataLine) -> io::Result<()> {
-        write!(self.writer, "\n{}", dl)
+/// Writes the header of the parser and, for every data line, the data lines returned by `f`,
+/// e.g., the decrypted line. Changes to the header are made on the parser beforehand. The output
+/// ends with a newline only if the input does.
+pub fn transform<R, W, F, I>(parser: VCFParser<R>, writer: W, mut f: F) -> anyhow::Result<()>
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(DataLine) -> anyhow::Result<I>,
+    I: IntoIterator<Item = DataLine>,
+{
+    let VCFParser { header, mut reader } = parser;
+    let mut writer = VCFWriter::new(writer, &header)?;
+    for dl in reader.iter() {
+        for dl in f(dl?)? {
+            writer.write_data_line(&dl)?;
+        }
+    }
+    writer.set_final_newline(reader.final_newline);
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::*;
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+##INFO=<ID=GENE,Number=1,Type=String,Description=\"Gene name\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t14370\trs6054257\tG\tA\t29\tPASS\tGENE=AB
+20\t17330\t.\tT\tA\t3\tq10\t.";
+
+    #[test]
+    fn test_line_endings() {
+        let crlf = format!("\u{FEFF}{}\r\n", SMALL_VCF.replace('\n', "\r\n"));
+        let parser = VCFParser::new(crlf.as_bytes()).unwrap();
+        assert_eq!(parser.header.version.value, "VCFv4.3");
+        assert_eq!(parser.header.header_lines.len(), 1);
+        let mut output = vec![];
+        let mut writer = VCFWriter::new(&mut output, &parser.header).unwrap();
+        for dl in parser.reader {
+            writer.write_data_line(&dl.unwrap()).unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), SMALL_VCF);
+    }
+
+    #[test]
+    fn test_transform() {
+        for input in [SMALL_VCF.to_string(), format!("{}\n", SMALL_VCF)] {
+            let mut parser = VCFParser::new(input.as_bytes()).unwrap();
+            parser.header.header_lines.clear();
+            let mut output = vec![];
+            transform(parser, &mut output, |dl| {
+                Ok(if dl.position == 14370 {
+                    vec![]
+                } else {
+                    vec![dl.clone(), dl]
+                })
+            })
+            .unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let line = SMALL_VCF.lines().last().unwrap();
+            assert!(output.starts_with("##fileformat=VCFv4.3\n#CHROM\t"));
+            assert!(output.contains(&format!("\n{}\n{}", line, line)));
+            assert_eq!(output.ends_with('\n'), input.ends_with('\n'));
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_line_bytes: 100,
+            ..Limits::default()
+        };
+        let parser = VCFParser::with_limits(SMALL_VCF.as_bytes(), limits).unwrap();
+        assert_eq!(parser.reader.into_iter().count(), 2);
+
+        // the rest of a long line is skipped, and not read as more lines
+        let line = SMALL_VCF.lines().last().unwrap();
+        let giant = format!("{}\n20\t1\t{}\n{}", SMALL_VCF, "A".repeat(1000), line);
+        let reader = BufReader::with_capacity(16, giant.as_bytes());
+        let parser = VCFParser::with_limits(reader, limits).unwrap();
+        let results: Vec<_> = parser.reader.into_iter().collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            results[2].as_ref().unwrap_err().to_string(),
+            "line longer than the limit of 100 bytes"
+        );
+        assert_eq!(results[3].as_ref().unwrap().position, 17330);
+
+        let limits = Limits {
+            max_header_bytes: 100,
+            ..Limits::default()
+        };
+        assert!(VCFParser::with_limits(SMALL_VCF.as_bytes(), limits).is_err());
+
+        let limits = Limits {
+            max_samples: 1,
+            ..Limits::default()
+        };
+        let samples = SMALL_VCF.replace("\tINFO\n", "\tINFO\tFORMAT\tSAMP001\tSAMP002\n");
+        assert!(VCFParser::with_limits(samples.as_bytes(), limits).is_err());
+
+        let limits = Limits {
+            max_header_lines: 0,
+            ..Limits::default()
+        };
+        assert!(VCFParser::with_limits(SMALL_VCF.as_bytes(), limits).is_err());
+        let limits = Limits {
+            max_header_lines: 1,
+            ..Limits::default()
+        };
+        let trailers = format!(
+            "{}{}",
+            SMALL_VCF,
+            "\n##MERKLE=<ID=20,Records=2,Root=00>".repeat(2)
+        );
+        let parser = VCFParser::with_limits(trailers.as_bytes(), limits).unwrap();
+        let results: Vec<_> = parser.reader.into_iter().collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[2].is_err());
+
+        let limits = Limits {
+            max_alleles: 1,
+            ..Limits::default()
+        };
+        let multiallelic = format!("{}\n20\t1\t.\tG\tA,C\t.\t.\t.", SMALL_VCF);
+        let parser = VCFParser::with_limits(multiallelic.as_bytes(), limits).unwrap();
+        let results: Vec<_> = parser.reader.into_iter().collect();
+        assert!(results[1].is_ok());
+        assert_eq!(
+            results[2].as_ref().unwrap_err().to_string(),
+            "2 alternate alleles at 20:1, more than the limit of 1"
+        );
+    }
+
+    #[test]
+    fn test_unbounded_lines() {
+        let limits = Limits {
+            max_line_bytes: usize::MAX,
+            ..Limits::default()
+        };
+        let parser = VCFParser::with_limits(SMALL_VCF.as_bytes(), limits).unwrap();
+        assert_eq!(parser.reader.into_iter().map(Result::unwrap).count(), 2);
+    }
+
+    #[test]
+    fn test_tab_line() {
+        // a line of tabs as long as the default limit, split in no more than the expected columns
+        let line = "\t".repeat(Limits::default().max_line_bytes);
+        let tabs = format!("{}\n{}", SMALL_VCF, line);
+        let parser = VCFParser::new(tabs.as_bytes()).unwrap();
+        let results: Vec<_> = parser.reader.into_iter().collect();
+        assert_eq!(
+            results[2].as_ref().unwrap_err().to_string(),
+            "invalid number of columns found, expected 8, found more"
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        // a Latin-1 description or INFO value is kept as bytes, and fails only when read as text
+        let latin1 = SMALL_VCF
+            .replace("Gene name", "G\u{E8}ne")
+            .replace("GENE=AB", "GENE=A\u{C9}");
+        let bytes: Vec<u8> = latin1.chars().map(|c| c as u8).collect();
+        let VCFParser { header, reader } = VCFParser::new(bytes.as_slice()).unwrap();
+        match &header.header_lines[0] {
+            HeaderLine::Info { description, .. } => {
+                assert_eq!(description.as_bytes(), b"G\xE8ne");
+                assert!(description.as_str().is_err());
+            }
+            hl => panic!("unexpected header line {:?}", hl),
+        }
+        let results: Vec<_> = reader.into_iter().collect();
+        let dl = results[0].as_ref().unwrap();
+        assert_eq!(dl.info_bytes("GENE"), Some(&b"A\xC9"[..]));
+        assert!(dl.info_value("GENE").is_err());
+        assert!(results[1].is_ok());
+
+        // and written back as read
+        let mut written = vec![];
+        let mut writer = VCFWriter::new(&mut written, &header).unwrap();
+        for dl in results {
+            writer.write_data_line(&dl.unwrap()).unwrap();
+        }
+        assert_eq!(written, bytes);
+
+        // any valid character is kept as is
+        let private_use = SMALL_VCF.replace("GENE=AB", "GENE=\u{10FFFD}");
+        let parser = VCFParser::new(private_use.as_bytes()).unwrap();
+        let dl = parser.reader.into_iter().next().unwrap().unwrap();
+        assert_eq!(dl.info_value("GENE").unwrap(), Some("\u{10FFFD}"));
+
+        // the other columns and sample names are read as text
+        let chrom = SMALL_VCF.replace("20\t17330", "2\u{C9}\t17330");
+        let bytes: Vec<u8> = chrom.chars().map(|c| c as u8).collect();
+        let parser = VCFParser::new(bytes.as_slice()).unwrap();
+        let results: Vec<_> = parser.reader.into_iter().collect();
+        assert!(results[0].is_ok());
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "invalid UTF-8 in column `CHROM`"
+        );
+        let sample = SMALL_VCF.replace("\tINFO\n", "\tINFO\tFORMAT\tS\u{C9}\n");
+        let bytes: Vec<u8> = sample.chars().map(|c| c as u8).collect();
+        assert!(VCFParser::new(bytes.as_slice()).is_err());
+
+        let bytes: Vec<u8> = latin1.chars().map(|c| c as u8).collect();
+        let parser = VCFParser::lossy(bytes.as_slice()).unwrap();
+        assert!(parser.header.header_lines[0]
+            .to_string()
+            .contains("G\u{FFFD}ne"));
+        let dl = parser.reader.into_iter().next().unwrap().unwrap();
+        assert_eq!(dl.info_value("GENE").unwrap(), Some("A\u{FFFD}"));
     }
 }
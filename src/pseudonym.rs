@@ -0,0 +1,333 @@
+use crate::{
+    container::{EncryptedReader, EncryptedWriter},
+    crypto::{hex_encode, random_bytes, Key},
+    header::{EncryptionScope, Header, HeaderLine, PedigreeType},
+    parser::{transform, VCFParser},
+};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    collections::HashSet,
+    fmt::{Display, Error, Formatter},
+    io::{BufRead, Read, Seek, Write},
+    str::FromStr,
+};
+
+/// The number of random bytes of a generated pseudonym, written in hex after an `S`.
+const PSEUDONYM_LEN: usize = 8;
+
+/// A mapping from sample names to pseudonyms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleMapping {
+    names: LinkedHashMap<String, String>,
+}
+
+impl SampleMapping {
+    pub fn new() -> Self {
+        SampleMapping::default()
+    }
+
+    /// Generates a random pseudonym for every sample referenced in the header: the sample columns,
+    /// `##SAMPLE` lines and `##PEDIGREE` lines.
+    pub fn generate(header: &Header) -> Self {
+        SampleMapping::generate_with(header, || {
+            format!("S{}", hex_encode(&random_bytes::<PSEUDONYM_LEN>())).to_uppercase()
+        })
+    }
+
+    /// Maps every sample referenced in the header to a pseudonym of `next_pseudonym`, drawing
+    /// again when it is the pseudonym of another sample or the name of any sample.
+    fn generate_with(header: &Header, mut next_pseudonym: impl FnMut() -> String) -> Self {
+        let names = sample_names(header);
+        let mut taken: HashSet<String> = names.iter().cloned().collect();
+        let mut mapping = SampleMapping::new();
+        for name in names {
+            let pseudonym = loop {
+                let pseudonym = next_pseudonym();
+                if taken.insert(pseudonym.clone()) {
+                    break pseudonym;
+                }
+            };
+            mapping.names.insert(name, pseudonym);
+        }
+        mapping
+    }
+
+    /// Adds the pseudonym of a sample, returning its previous pseudonym, if any.
+    pub fn insert(&mut self, name: String, pseudonym: String) -> Option<String> {
+        self.names.insert(name, pseudonym)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.names.get(name).map(|p| p.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The mapping from pseudonyms back to sample names.
+    pub fn reversed(&self) -> SampleMapping {
+        SampleMapping {
+            names: self
+                .names
+                .iter()
+                .map(|(name, pseudonym)| (pseudonym.to_string(), name.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Writes the mapping in an encrypted container, see `EncryptedWriter`.
+    pub fn write_encrypted<W: Write>(&self, writer: W, key: Key) -> anyhow::Result<W> {
+        let mut writer = EncryptedWriter::new(writer, key)?;
+        write!(writer, "{}", self)?;
+        Ok(writer.finish()?)
+    }
+
+    /// Reads a mapping written by `write_encrypted`.
+    pub fn read_encrypted<R: Read + Seek>(reader: R, key: Key) -> anyhow::Result<Self> {
+        let mut mapping_str = String::new();
+        EncryptedReader::new(reader, key)?.read_to_string(&mut mapping_str)?;
+        mapping_str.parse()
+    }
+}
+
+impl Display for SampleMapping {
+    /// Formats the mapping as one sample per line, its name and its pseudonym separated by a tab.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for (name, pseudonym) in &self.names {
+            writeln!(f, "{}\t{}", name, pseudonym)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SampleMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(mapping_str: &str) -> anyhow::Result<Self> {
+        let mut mapping = SampleMapping::new();
+        for line in mapping_str.lines() {
+            if line.is_empty() {
+                continue;
+            }
+            let (name, pseudonym) = line
+                .split_once('\t')
+                .ok_or_else(|| anyhow::anyhow!("invalid sample mapping line `{}`", line))?;
+            if mapping.names.values().any(|p| p == pseudonym) {
+                return Err(anyhow::anyhow!("duplicate pseudonym `{}`", pseudonym));
+            }
+            if mapping
+                .insert(name.to_string(), pseudonym.to_string())
+                .is_some()
+            {
+                return Err(anyhow::anyhow!("duplicate sample `{}` in mapping", name));
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+/// Returns the distinct sample names referenced in the header, in order of appearance.
+fn sample_names(header: &Header) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let mut add = |name: &str| {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    };
+    for name in &header.column_names {
+        add(name);
+    }
+    for hl in &header.header_lines {
+        match hl {
+            HeaderLine::Sample { id, .. } => add(id),
+            HeaderLine::Pedigree { id, relation } => {
+                add(id);
+                match relation {
+                    PedigreeType::Original(original) => add(original),
+                    PedigreeType::Parents {
+                        father_id,
+                        mother_id,
+                    } => {
+                        add(father_id);
+                        add(mother_id);
+                    }
+                    PedigreeType::Ancestors(ancestors) => ancestors.iter().for_each(|a| add(a)),
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Replaces sample names with pseudonyms in the sample columns, `##SAMPLE` and `##PEDIGREE` lines,
+/// and the `##ENCRYPTED` lines of samples encrypted with their own keys. Data lines are not
+/// changed. Using the reversed mapping restores the original names.
+#[derive(Debug)]
+pub struct Pseudonymizer {
+    mapping: SampleMapping,
+}
+
+impl Pseudonymizer {
+    pub fn new(mapping: SampleMapping) -> Self {
+        Pseudonymizer { mapping }
+    }
+
+    /// Creates the reverse transform of a pseudonymizer using the given mapping.
+    pub fn reverse(mapping: &SampleMapping) -> Self {
+        Pseudonymizer::new(mapping.reversed())
+    }
+
+    /// Reads all data lines of the parser and writes them with the renamed header.
+    pub fn pseudonymize<R: BufRead, W: Write>(
+        &self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        self.pseudonymize_header(&mut parser.header)?;
+        transform(parser, writer, |dl| Ok([dl]))
+    }
+
+    /// Renames every sample referenced in the header, failing if any has no pseudonym.
+    pub fn pseudonymize_header(&self, header: &mut Header) -> anyhow::Result<()> {
+        let rename = |name: &mut String| -> anyhow::Result<()> {
+            *name = self
+                .mapping
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("no pseudonym for sample `{}`", name))?
+                .to_string();
+            Ok(())
+        };
+        for name in header.column_names.iter_mut() {
+            rename(name)?;
+        }
+        for hl in header.header_lines.iter_mut() {
+            match hl {
+                HeaderLine::Sample { id, .. } => rename(id)?,
+                HeaderLine::Pedigree { id, relation } => {
+                    rename(id)?;
+                    match relation {
+                        PedigreeType::Original(original) => rename(original)?,
+                        PedigreeType::Parents {
+                            father_id,
+                            mother_id,
+                        } => {
+                            rename(father_id)?;
+                            rename(mother_id)?;
+                        }
+                        PedigreeType::Ancestors(ancestors) => {
+                            for ancestor in ancestors.iter_mut() {
+                                rename(ancestor)?;
+                            }
+                        }
+                    }
+                }
+                HeaderLine::Encrypted {
+                    id, scope, other, ..
+                } => {
+                    if *scope == EncryptionScope::Sample {
+                        rename(id)?;
+                    }
+                    if let Some(sample) = other.get_mut("Sample") {
+                        rename(sample)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pseudonym::*;
+    use std::io::Cursor;
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+##SAMPLE=<ID=AQ9FP5YM5JY,Description=\"Nebula test sample\">
+##PEDIGREE=<ID=AQ9FP5YM5JY,Father=DAD001,Mother=MOM001>
+##PEDIGREE=<ID=TUMOR001,Original=AQ9FP5YM5JY>
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tAQ9FP5YM5JY\tTUMOR001
+20\t14370\trs6054257\tG\tA\t29\tPASS\t.\tGT\t0|0\t0|1";
+
+    #[test]
+    fn test_pseudonymize() {
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mapping = SampleMapping::generate(&parser.header);
+        assert_eq!(mapping.len(), 4);
+        let mut output = vec![];
+        Pseudonymizer::new(mapping.clone())
+            .pseudonymize(parser, &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        for name in &["AQ9FP5YM5JY", "DAD001", "MOM001", "TUMOR001"] {
+            assert!(!output.contains(name));
+        }
+        let pseudonym = mapping.get("AQ9FP5YM5JY").unwrap();
+        assert!(output.contains(&format!(
+            "##PEDIGREE=<ID={},Father={},Mother={}>",
+            pseudonym,
+            mapping.get("DAD001").unwrap(),
+            mapping.get("MOM001").unwrap()
+        )));
+        assert!(output.contains(&format!("\tFORMAT\t{}\t", pseudonym)));
+        assert!(output.ends_with("GT\t0|0\t0|1"));
+
+        let mut restored = vec![];
+        Pseudonymizer::reverse(&mapping)
+            .pseudonymize(VCFParser::new(output.as_bytes()).unwrap(), &mut restored)
+            .unwrap();
+        assert_eq!(String::from_utf8(restored).unwrap(), SMALL_VCF);
+
+        // every sample needs a pseudonym
+        let mut partial = SampleMapping::new();
+        partial.insert("AQ9FP5YM5JY".to_string(), "S1".to_string());
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        assert!(Pseudonymizer::new(partial)
+            .pseudonymize(parser, vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mapping = SampleMapping::generate(&parser.header);
+        let pseudonym = mapping.get("DAD001").unwrap();
+        assert_eq!(pseudonym.len(), 1 + 2 * PSEUDONYM_LEN);
+        assert!(pseudonym.starts_with('S'));
+
+        // pseudonyms already taken, by a sample or as a pseudonym, are drawn again
+        let mut pseudonyms = ["P1", "TUMOR001", "P1", "P2", "P3", "P2", "P4"].iter();
+        let mapping =
+            SampleMapping::generate_with(&parser.header, || pseudonyms.next().unwrap().to_string());
+        assert_eq!(
+            mapping.to_string(),
+            "AQ9FP5YM5JY\tP1\nTUMOR001\tP2\nDAD001\tP3\nMOM001\tP4\n"
+        );
+    }
+
+    #[test]
+    fn test_mapping() {
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mapping = SampleMapping::generate(&parser.header);
+        assert_eq!(
+            SampleMapping::from_str(&mapping.to_string()).unwrap(),
+            mapping
+        );
+        assert!(SampleMapping::from_str("A\tS1\nB\tS1").is_err());
+        assert!(SampleMapping::from_str("A S1").is_err());
+
+        let key = Key::generate();
+        let encrypted = mapping.write_encrypted(vec![], key.clone()).unwrap();
+        assert!(!String::from_utf8_lossy(&encrypted).contains("AQ9FP5YM5JY"));
+        let decrypted = SampleMapping::read_encrypted(Cursor::new(encrypted), key).unwrap();
+        assert_eq!(decrypted, mapping);
+    }
+}
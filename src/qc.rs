@@ -0,0 +1,506 @@
+use crate::{
+    body::{AltType, DataLine, SampleType, VariantType},
+    parser::VCFParser,
+    stats::is_transition,
+};
+use linked_hash_map::LinkedHashMap;
+use serde::Serialize;
+use std::{
+    fmt::{Display, Error, Formatter},
+    io::BufRead,
+};
+
+/// The metrics of a sample, accumulated over the data lines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SampleQc {
+    /// Genotypes, including missing ones.
+    pub genotypes: u64,
+
+    /// Genotypes without any called allele, e.g., `./.`, or without a GT value.
+    pub missing: u64,
+    pub hom_ref: u64,
+    pub het: u64,
+    pub hom_alt: u64,
+
+    /// Genotypes with at least one alternate allele.
+    pub non_ref: u64,
+    pub transitions: u64,
+    pub transversions: u64,
+
+    /// Alternate alleles of which the sample carries the only copy in the data line.
+    pub singletons: u64,
+    // wide enough that no file can overflow the sums
+    #[serde(skip)]
+    depth_sum: u128,
+    #[serde(skip)]
+    depth_count: u64,
+    #[serde(skip)]
+    gq_sum: u128,
+    #[serde(skip)]
+    gq_count: u64,
+}
+
+impl SampleQc {
+    /// The fraction of called genotypes, or `0` without genotypes.
+    pub fn call_rate(&self) -> f64 {
+        if self.genotypes == 0 {
+            0.0
+        } else {
+            (self.genotypes - self.missing) as f64 / self.genotypes as f64
+        }
+    }
+
+    pub fn missingness(&self) -> f64 {
+        if self.genotypes == 0 {
+            0.0
+        } else {
+            self.missing as f64 / self.genotypes as f64
+        }
+    }
+
+    /// The ratio of heterozygous to homozygous alternate genotypes, or `0` without homozygous
+    /// alternate genotypes.
+    pub fn het_hom_ratio(&self) -> f64 {
+        ratio(self.het, self.hom_alt)
+    }
+
+    pub fn ts_tv(&self) -> f64 {
+        ratio(self.transitions, self.transversions)
+    }
+
+    /// The mean FORMAT/DP, over the genotypes with a depth.
+    pub fn mean_depth(&self) -> Option<f64> {
+        mean(self.depth_sum, self.depth_count)
+    }
+
+    /// The mean FORMAT/GQ, over the genotypes with a quality.
+    pub fn mean_gq(&self) -> Option<f64> {
+        mean(self.gq_sum, self.gq_count)
+    }
+
+    /// The thresholds that the sample does not meet.
+    pub fn flags(&self, thresholds: &QcThresholds) -> Vec<QcFlag> {
+        let mut flags = vec![];
+        if thresholds
+            .min_call_rate
+            .is_some_and(|min| self.call_rate() < min)
+        {
+            flags.push(QcFlag::LowCallRate);
+        }
+        if thresholds
+            .max_het_hom_ratio
+            .is_some_and(|max| self.het_hom_ratio() > max)
+        {
+            flags.push(QcFlag::HighHetHomRatio);
+        }
+        if thresholds.min_ts_tv.is_some_and(|min| self.ts_tv() < min) {
+            flags.push(QcFlag::LowTsTv);
+        }
+        if thresholds.max_ts_tv.is_some_and(|max| self.ts_tv() > max) {
+            flags.push(QcFlag::HighTsTv);
+        }
+        if let Some(min) = thresholds.min_mean_depth {
+            if self.mean_depth().is_none_or(|depth| depth < min) {
+                flags.push(QcFlag::LowMeanDepth);
+            }
+        }
+        if thresholds
+            .max_singletons
+            .is_some_and(|max| self.singletons > max)
+        {
+            flags.push(QcFlag::HighSingletons);
+        }
+        flags
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn mean(sum: u128, count: u64) -> Option<f64> {
+    if count == 0 {
+        None
+    } else {
+        Some(sum as f64 / count as f64)
+    }
+}
+
+/// The limits of the metrics of a sample, unchecked if not set. By default, only samples with a
+/// call rate below 95% are flagged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QcThresholds {
+    pub min_call_rate: Option<f64>,
+    pub max_het_hom_ratio: Option<f64>,
+    pub min_ts_tv: Option<f64>,
+    pub max_ts_tv: Option<f64>,
+    pub min_mean_depth: Option<f64>,
+    pub max_singletons: Option<u64>,
+}
+
+impl Default for QcThresholds {
+    fn default() -> Self {
+        QcThresholds {
+            min_call_rate: Some(0.95),
+            max_het_hom_ratio: None,
+            min_ts_tv: None,
+            max_ts_tv: None,
+            min_mean_depth: None,
+            max_singletons: None,
+        }
+    }
+}
+
+/// A threshold that a sample does not meet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QcFlag {
+    LowCallRate,
+    HighHetHomRatio,
+    LowTsTv,
+    HighTsTv,
+    LowMeanDepth,
+    HighSingletons,
+}
+
+impl Display for QcFlag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let flag = match self {
+            QcFlag::LowCallRate => "low_call_rate",
+            QcFlag::HighHetHomRatio => "high_het_hom_ratio",
+            QcFlag::LowTsTv => "low_ts_tv",
+            QcFlag::HighTsTv => "high_ts_tv",
+            QcFlag::LowMeanDepth => "low_mean_depth",
+            QcFlag::HighSingletons => "high_singletons",
+        };
+        write!(f, "{}", flag)
+    }
+}
+
+/// The counts, metrics and flags of a sample, e.g., a row of the JSON output of `qc`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampleQcRow {
+    pub sample: String,
+    #[serde(flatten)]
+    pub counts: SampleQc,
+    pub call_rate: f64,
+    pub missingness: f64,
+    pub het_hom_ratio: f64,
+    pub ts_tv: f64,
+    pub mean_depth: Option<f64>,
+    pub mean_gq: Option<f64>,
+
+    /// The thresholds that the sample does not meet.
+    pub flags: Vec<QcFlag>,
+}
+
+/// The metrics of every sample, keyed by the column names of the header.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SampleQcReport {
+    pub samples: LinkedHashMap<String, SampleQc>,
+}
+
+impl SampleQcReport {
+    pub fn new(column_names: &[String]) -> Self {
+        SampleQcReport {
+            samples: column_names
+                .iter()
+                .map(|name| (name.clone(), SampleQc::default()))
+                .collect(),
+        }
+    }
+
+    /// Adds the genotypes of a data line, which must have one sample per column name. Genotypes
+    /// are classified by their called alleles, so `./1` and haploid `1` are homozygous alternate.
+    pub fn add(&mut self, dl: &DataLine) {
+        let alts = match &dl.alternative {
+            AltType::Entries(alts) => alts.as_slice(),
+            AltType::Missing => &[],
+        };
+        let (gt_index, dp_index, gq_index) = (
+            dl.format_index("GT"),
+            dl.format_index("DP"),
+            dl.format_index("GQ"),
+        );
+        let genotypes: Vec<Vec<usize>> = dl
+            .samples
+            .iter()
+            .map(|sample| match (sample, gt_index) {
+                (SampleType::Entries(values), Some(index)) => values
+                    .get(index)
+                    .and_then(|gt| gt.as_str().ok())
+                    .map_or(vec![], parse_genotype),
+                _ => vec![],
+            })
+            .collect();
+
+        // the copies of each allele over all the samples, for singletons
+        let mut copies = vec![0u64; alts.len() + 1];
+        for allele in genotypes.iter().flatten() {
+            if let Some(count) = copies.get_mut(*allele) {
+                *count += 1;
+            }
+        }
+
+        for ((qc, sample), alleles) in self
+            .samples
+            .iter_mut()
+            .map(|(_, qc)| qc)
+            .zip(&dl.samples)
+            .zip(&genotypes)
+        {
+            qc.genotypes += 1;
+            if let SampleType::Entries(values) = sample {
+                let value = |index: Option<usize>| {
+                    index
+                        .and_then(|i| values.get(i))
+                        .and_then(|v| v.as_str().ok()?.parse::<u64>().ok())
+                };
+                if let Some(depth) = value(dp_index) {
+                    qc.depth_sum += depth as u128;
+                    qc.depth_count += 1;
+                }
+                if let Some(gq) = value(gq_index) {
+                    qc.gq_sum += gq as u128;
+                    qc.gq_count += 1;
+                }
+            }
+            if alleles.is_empty() {
+                qc.missing += 1;
+                continue;
+            }
+            if alleles.iter().all(|a| *a == 0) {
+                qc.hom_ref += 1;
+                continue;
+            }
+            qc.non_ref += 1;
+            if alleles.iter().all(|a| *a == alleles[0]) {
+                qc.hom_alt += 1;
+            } else {
+                qc.het += 1;
+            }
+            let mut seen = vec![];
+            for allele in alleles.iter().filter(|a| **a > 0) {
+                if seen.contains(allele) {
+                    continue;
+                }
+                seen.push(*allele);
+                if copies.get(*allele) == Some(&1) {
+                    qc.singletons += 1;
+                }
+                if let Some(alt) = alts.get(allele - 1) {
+                    if VariantType::new(&dl.reference, alt) == VariantType::Snv {
+                        if is_transition(&dl.reference, alt) {
+                            qc.transitions += 1;
+                        } else {
+                            qc.transversions += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The samples that do not meet the thresholds, with their flags, in column order.
+    pub fn outliers(&self, thresholds: &QcThresholds) -> Vec<(&str, Vec<QcFlag>)> {
+        self.samples
+            .iter()
+            .map(|(name, qc)| (name.as_str(), qc.flags(thresholds)))
+            .filter(|(_, flags)| !flags.is_empty())
+            .collect()
+    }
+
+    /// The row of every sample, in column order.
+    pub fn rows(&self, thresholds: &QcThresholds) -> Vec<SampleQcRow> {
+        self.samples
+            .iter()
+            .map(|(name, qc)| SampleQcRow {
+                sample: name.clone(),
+                counts: qc.clone(),
+                call_rate: qc.call_rate(),
+                missingness: qc.missingness(),
+                het_hom_ratio: qc.het_hom_ratio(),
+                ts_tv: qc.ts_tv(),
+                mean_depth: qc.mean_depth(),
+                mean_gq: qc.mean_gq(),
+                flags: qc.flags(thresholds),
+            })
+            .collect()
+    }
+
+    /// Formats the report as a tab separated table, one sample per row, with the flags of each
+    /// sample in the last column.
+    pub fn to_table(&self, thresholds: &QcThresholds) -> String {
+        let optional = |value: Option<f64>| value.map_or(".".to_string(), |v| format!("{:.2}", v));
+        let mut table = "sample\tcall_rate\tmissingness\thom_ref\thet\thom_alt\tnon_ref\t\
+                         het_hom_ratio\tts\ttv\tts_tv\tsingletons\tmean_dp\tmean_gq\tflags\n"
+            .to_string();
+        for row in self.rows(thresholds) {
+            let flags: Vec<String> = row.flags.iter().map(|f| f.to_string()).collect();
+            let qc = &row.counts;
+            table.push_str(&format!(
+                "{}\t{:.4}\t{:.4}\t{}\t{}\t{}\t{}\t{:.2}\t{}\t{}\t{:.2}\t{}\t{}\t{}\t{}\n",
+                row.sample,
+                row.call_rate,
+                row.missingness,
+                qc.hom_ref,
+                qc.het,
+                qc.hom_alt,
+                qc.non_ref,
+                row.het_hom_ratio,
+                qc.transitions,
+                qc.transversions,
+                row.ts_tv,
+                qc.singletons,
+                optional(row.mean_depth),
+                optional(row.mean_gq),
+                if flags.is_empty() {
+                    ".".to_string()
+                } else {
+                    flags.join(",")
+                }
+            ));
+        }
+        table
+    }
+}
+
+/// The called alleles of a genotype, e.g., `[0, 1]` for `0|1` and `[1]` for `./1`, or none if
+/// the genotype is missing.
+fn parse_genotype(genotype: &str) -> Vec<usize> {
+    genotype
+        .split(['/', '|'])
+        .filter_map(|allele| allele.parse().ok())
+        .collect()
+}
+
+/// Computes the metrics of every sample of the parser.
+pub fn sample_qc<R: BufRead>(parser: VCFParser<R>) -> anyhow::Result<SampleQcReport> {
+    let mut report = SampleQcReport::new(&parser.header.column_names);
+    for dl in parser.reader {
+        report.add(&dl?);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        qc::*,
+        test_util::{parser, read, LARGE_VCF, SMALL_VCF},
+    };
+
+    /// `SMALL_VCF` with a missing genotype in NA00002 and NA00003, a partly missing one in
+    /// NA00003 and a homozygous alternate one in NA00002.
+    fn with_missing() -> String {
+        read(SMALL_VCF)
+            .replace("1|0:48:8:51,51", "1|1:48:8:51,51")
+            .replace("0|0:48:4:51,51", ".:48:4:51,51")
+            .replace("0/0:41:3", "./.:.:.")
+            .replace("0/0:61:2", "./1:61:2")
+    }
+
+    #[test]
+    fn test_sample_qc() {
+        let report = sample_qc(parser(SMALL_VCF)).unwrap();
+        let names: Vec<&String> = report.samples.keys().collect();
+        assert_eq!(names, vec!["NA00001", "NA00002", "NA00003"]);
+
+        let qc = &report.samples["NA00001"];
+        assert_eq!(qc.genotypes, 5);
+        assert_eq!(qc.missing, 0);
+        assert_eq!((qc.hom_ref, qc.het, qc.hom_alt, qc.non_ref), (3, 2, 0, 2));
+        assert_eq!((qc.transitions, qc.transversions), (1, 1));
+        assert_eq!(qc.singletons, 0);
+        assert_eq!(qc.mean_depth(), Some(4.2));
+        assert_eq!(qc.mean_gq(), Some(41.4));
+        assert_eq!(qc.het_hom_ratio(), 0.0);
+
+        let qc = &report.samples["NA00002"];
+        assert_eq!((qc.het, qc.non_ref, qc.singletons), (4, 4, 2));
+        assert_eq!(qc.call_rate(), 1.0);
+
+        let report = sample_qc(VCFParser::new(with_missing().as_bytes()).unwrap()).unwrap();
+        let qc = &report.samples["NA00002"];
+        assert_eq!((qc.missing, qc.hom_ref, qc.het, qc.hom_alt), (1, 0, 3, 1));
+        assert_eq!(qc.call_rate(), 0.8);
+        assert_eq!(qc.het_hom_ratio(), 3.0);
+
+        let qc = &report.samples["NA00003"];
+        assert_eq!((qc.missing, qc.hom_alt, qc.singletons), (1, 4, 0));
+        assert_eq!(qc.mean_depth(), Some(3.5));
+        assert_eq!(qc.mean_gq(), Some(44.75));
+        assert_eq!(SampleQc::default().mean_gq(), None);
+    }
+
+    #[test]
+    fn test_outliers() {
+        let report = sample_qc(VCFParser::new(with_missing().as_bytes()).unwrap()).unwrap();
+        assert_eq!(
+            report.outliers(&QcThresholds::default()),
+            vec![
+                ("NA00002", vec![QcFlag::LowCallRate]),
+                ("NA00003", vec![QcFlag::LowCallRate])
+            ]
+        );
+        let thresholds = QcThresholds {
+            min_call_rate: None,
+            max_het_hom_ratio: Some(2.0),
+            min_mean_depth: Some(3.6),
+            ..QcThresholds::default()
+        };
+        assert_eq!(
+            report.outliers(&thresholds),
+            vec![
+                ("NA00002", vec![QcFlag::HighHetHomRatio]),
+                ("NA00003", vec![QcFlag::LowMeanDepth])
+            ]
+        );
+
+        let table = report.to_table(&thresholds);
+        assert!(table.starts_with("sample\tcall_rate\t"));
+        assert!(table.contains("\nNA00002\t0.8000\t0.2000\t0\t3\t1\t4\t3.00\t"));
+        assert!(table.ends_with("\t3.50\t44.75\tlow_mean_depth\n"));
+
+        // rows tell why a sample is flagged, without the sums behind the means
+        let json = serde_json::to_value(report.rows(&thresholds)).unwrap();
+        assert_eq!(json[1]["sample"], "NA00002");
+        assert_eq!(json[1]["het"], 3);
+        assert_eq!(json[1]["het_hom_ratio"], 3.0);
+        assert_eq!(json[1]["mean_depth"], 3.8);
+        assert_eq!(json[1]["flags"], serde_json::json!(["high_het_hom_ratio"]));
+        assert_eq!(json[0]["flags"], serde_json::json!([]));
+        assert!(json[0].get("depth_sum").is_none());
+        let keys: Vec<&String> = json[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys.len(), 17);
+    }
+
+    #[test]
+    fn test_huge_depth() {
+        let vcf = read(SMALL_VCF)
+            .replace(
+                "0|0:48:1:51,51",
+                "0|0:18446744073709551615:18446744073709551615:51,51",
+            )
+            .replace("0|0:49:3:58,50", "0|0:49:18446744073709551615:58,50");
+        let report = sample_qc(VCFParser::new(vcf.as_bytes()).unwrap()).unwrap();
+        let qc = &report.samples["NA00001"];
+        let max = u64::MAX as u128;
+        assert_eq!(qc.mean_depth(), Some((2 * max + 17) as f64 / 5.0));
+        assert_eq!(qc.mean_gq(), Some((max + 159) as f64 / 5.0));
+    }
+
+    #[test]
+    fn test_file() {
+        let report = sample_qc(parser(LARGE_VCF)).unwrap();
+        assert_eq!(report.samples.len(), 3);
+        for qc in report.samples.values() {
+            assert_eq!(qc.genotypes, 4633);
+            assert!(qc.ts_tv() > 1.0);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{
+    body::{DataLine, InfoType, SampleType},
+    header::{self, Header, HeaderLine},
+    parser::VCFParser,
+};
+use std::io::{self, BufRead, Write};
+
+/// A part of a query format.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Chrom,
+    Pos,
+    Id,
+    Ref,
+    Alt,
+    Qual,
+    Filter,
+
+    /// The whole INFO column.
+    InfoColumn,
+
+    /// The value of an INFO key. Flags are rendered as `1` when set and `0` otherwise.
+    Info {
+        key: String,
+        flag: bool,
+    },
+
+    /// The name of the sample, in a sample block.
+    Sample,
+
+    /// The value of a FORMAT key, in a sample block.
+    Format(String),
+
+    /// A block rendered once per sample.
+    Samples(Vec<Token>),
+}
+
+/// A bcftools-style query format, e.g., `%CHROM\t%POS\t%REF\t%ALT\t[ %GP]\n`, compiled against a
+/// header. `%` introduces a fixed column (`%CHROM`, `%POS`, `%ID`, `%REF`, `%ALT`, `%QUAL`,
+/// `%FILTER`), the INFO column (`%INFO`) or an INFO key (`%INFO/AF` or `%AF`). `[...]` blocks are
+/// rendered once per sample and may contain `%SAMPLE` and FORMAT keys (`%GT` or `%FORMAT/GT`).
+/// `\t`, `\n` and `\\` are escapes. Missing values are rendered as `.`.
+#[derive(Debug, PartialEq)]
+pub struct QueryFormat {
+    tokens: Vec<Token>,
+    column_names: Vec<String>,
+}
+
+impl QueryFormat {
+    /// Compiles the format, failing on syntax errors and on keys not defined in the header.
+    pub fn new(format_str: &str, header: &Header) -> anyhow::Result<Self> {
+        let mut tokens = vec![];
+        let mut block: Option<Vec<Token>> = None;
+        let mut literal = String::new();
+        let mut chars = format_str.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some(c) => literal.push(c),
+                    None => {
+                        return Err(anyhow::anyhow!(
+                            "invalid query format `{}`, (trailing `\\`)",
+                            format_str
+                        ))
+                    }
+                },
+                '%' => {
+                    let mut name = String::new();
+                    while let Some(c) = chars.peek() {
+                        if !c.is_ascii_alphanumeric() && !matches!(c, '_' | '/' | '.') {
+                            break;
+                        }
+                        name.push(*c);
+                        chars.next();
+                    }
+                    let token = compile_key(&name, header, block.is_some())?;
+                    let target = block.as_mut().unwrap_or(&mut tokens);
+                    push_literal(target, &mut literal);
+                    target.push(token);
+                }
+                '[' if block.is_none() => {
+                    push_literal(&mut tokens, &mut literal);
+                    block = Some(vec![]);
+                }
+                ']' if block.is_some() => {
+                    let mut samples = block.take().unwrap();
+                    push_literal(&mut samples, &mut literal);
+                    tokens.push(Token::Samples(samples));
+                }
+                '[' | ']' => {
+                    return Err(anyhow::anyhow!(
+                        "invalid query format `{}`, (unbalanced `{}`)",
+                        format_str,
+                        ch
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if block.is_some() {
+            return Err(anyhow::anyhow!(
+                "invalid query format `{}`, (unbalanced `[`)",
+                format_str
+            ));
+        }
+        push_literal(&mut tokens, &mut literal);
+        Ok(QueryFormat {
+            tokens,
+            column_names: header.column_names.clone(),
+        })
+    }
+
+    /// Writes the data line in the query format.
+    pub fn render<W: Write>(&self, dl: &DataLine, writer: &mut W) -> io::Result<()> {
+        for token in &self.tokens {
+            match token {
+                Token::Samples(block) => {
+                    for index in 0..dl.samples.len() {
+                        for token in block {
+                            self.render_token(token, dl, Some(index), writer)?;
+                        }
+                    }
+                }
+                token => self.render_token(token, dl, None, writer)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn render_token<W: Write>(
+        &self,
+        token: &Token,
+        dl: &DataLine,
+        sample: Option<usize>,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        match token {
+            Token::Literal(s) => write!(writer, "{}", s),
+            Token::Chrom => write!(writer, "{}", dl.chromosome),
+            Token::Pos => write!(writer, "{}", dl.position),
+            Token::Id => write!(writer, "{}", dl.id),
+            Token::Ref => write!(writer, "{}", dl.reference),
+            Token::Alt => write!(writer, "{}", dl.alternative),
+            Token::Qual => write!(writer, "{}", dl.quality),
+            Token::Filter => write!(writer, "{}", dl.filter),
+            Token::InfoColumn => dl.info.write_to(writer),
+            Token::Info { key, flag: true } => {
+                let set = match &dl.info {
+                    InfoType::Entries(entries) => entries.iter().any(|e| e == key.as_str()),
+                    InfoType::Missing => false,
+                };
+                write!(writer, "{}", if set { "1" } else { "0" })
+            }
+            Token::Info { key, flag: false } => {
+                writer.write_all(dl.info_bytes(key).unwrap_or(b"."))
+            }
+            Token::Sample => match sample.and_then(|i| self.column_names.get(i)) {
+                Some(name) => write!(writer, "{}", name),
+                None => write!(writer, "."),
+            },
+            Token::Format(key) => {
+                let value = match (dl.format_index(key), sample.map(|i| &dl.samples[i])) {
+                    (Some(index), Some(SampleType::Entries(entries))) => {
+                        entries.get(index).map(|e| e.as_bytes())
+                    }
+                    _ => None,
+                };
+                writer.write_all(value.unwrap_or(b"."))
+            }
+            Token::Samples(_) => Ok(()),
+        }
+    }
+}
+
+fn push_literal(tokens: &mut Vec<Token>, literal: &mut String) {
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(std::mem::take(literal)));
+    }
+}
+
+/// Compiles a `%` key, looking up INFO and FORMAT keys in the header.
+fn compile_key(name: &str, header: &Header, in_block: bool) -> anyhow::Result<Token> {
+    let token = match name {
+        "" => return Err(anyhow::anyhow!("invalid query format, (`%` without a key)")),
+        "CHROM" => Token::Chrom,
+        "POS" => Token::Pos,
+        "ID" => Token::Id,
+        "REF" => Token::Ref,
+        "ALT" => Token::Alt,
+        "QUAL" => Token::Qual,
+        "FILTER" => Token::Filter,
+        "INFO" => Token::InfoColumn,
+        "SAMPLE" if in_block => Token::Sample,
+        _ => {
+            if let Some(key) = name.strip_prefix("INFO/") {
+                compile_info_key(key, header)?
+            } else if let Some(key) = name.strip_prefix("FORMAT/") {
+                compile_format_key(key, header, in_block)?
+            } else if is_format_key(header, name) && (in_block || info_flag(header, name).is_none())
+            {
+                compile_format_key(name, header, in_block)?
+            } else {
+                compile_info_key(name, header)?
+            }
+        }
+    };
+    Ok(token)
+}
+
+fn compile_info_key(key: &str, header: &Header) -> anyhow::Result<Token> {
+    match info_flag(header, key) {
+        Some(flag) => Ok(Token::Info {
+            key: key.to_string(),
+            flag,
+        }),
+        None => Err(anyhow::anyhow!("unknown INFO key `{}`", key)),
+    }
+}
+
+fn compile_format_key(key: &str, header: &Header, in_block: bool) -> anyhow::Result<Token> {
+    if !is_format_key(header, key) {
+        return Err(anyhow::anyhow!("unknown FORMAT key `{}`", key));
+    }
+    if !in_block {
+        return Err(anyhow::anyhow!(
+            "invalid query format, (FORMAT key `{}` outside of a `[...]` sample block)",
+            key
+        ));
+    }
+    Ok(Token::Format(key.to_string()))
+}
+
+fn is_format_key(header: &Header, key: &str) -> bool {
+    header
+        .header_lines
+        .iter()
+        .any(|hl| matches!(hl, HeaderLine::Format { id, .. } if id == key))
+}
+
+/// Returns whether the INFO key is a flag, or `None` if the header does not define it.
+fn info_flag(header: &Header, key: &str) -> Option<bool> {
+    header.header_lines.iter().find_map(|hl| match hl {
+        HeaderLine::Info { id, typ, .. } if id == key => Some(*typ == header::InfoType::Flag),
+        _ => None,
+    })
+}
+
+/// Renders every data line of the parser in the query format.
+pub fn query<R: BufRead, W: Write>(
+    parser: VCFParser<R>,
+    format_str: &str,
+    mut writer: W,
+) -> anyhow::Result<()> {
+    let format = QueryFormat::new(format_str, &parser.header)?;
+    for dl in parser.reader {
+        format.render(&dl?, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        query::*,
+        test_util::{parser, LARGE_VCF, SMALL_VCF},
+    };
+
+    fn render(format_str: &str) -> anyhow::Result<String> {
+        let mut output = vec![];
+        query(parser(SMALL_VCF), format_str, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(
+            render("%CHROM\\t%POS\\t%REF\\t%ALT\\t[ %HQ]\\n").unwrap(),
+            "20\t14370\tG\tA\t 51,51 51,51 .,.\n\
+             20\t17330\tT\tA\t 58,50 65,3 .\n\
+             20\t1110696\tA\tG,T\t 23,27 18,2 .\n\
+             20\t1230237\tT\t.\t 56,60 51,51 .\n\
+             20\t1234567\tGTC\tG,GTCT\t . . .\n"
+        );
+        let output = render("%ID %QUAL %FILTER %INFO/DP %AF %AA %DB[ %SAMPLE=%GT]\\n").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "rs6054257 29 PASS 14 0.5 . 1 NA00001=0|0 NA00002=1|0 NA00003=1/1"
+        );
+        assert_eq!(
+            lines[1],
+            ". 3 q10 11 0.017 . 0 NA00001=0|0 NA00002=0|1 NA00003=0/0"
+        );
+        assert_eq!(
+            lines[4],
+            "microsat1 50 PASS 9 . G 0 NA00001=0/1 NA00002=0/2 NA00003=1/1"
+        );
+        // FORMAT keys take precedence over INFO keys in brackets
+        let output = render("GT:[ %FORMAT/GT] \\t DP:[%DP,]\\n").unwrap();
+        assert!(output.starts_with("GT: 0|0 1|0 1/1 \t DP:1,8,5,\nGT: 0|0 0|1 0/0 \t DP:3,5,3,\n"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(render("%CHROM[ %GT").is_err());
+        assert!(render("%CHROM]").is_err());
+        assert!(render("[[%GT]]").is_err());
+        assert!(render("%GT").is_err());
+        assert!(render("%SAMPLE").is_err());
+        assert!(render("%XX").is_err());
+        assert!(render("[%FORMAT/XX]").is_err());
+        assert!(render("% ").is_err());
+        assert!(render("%POS\\").is_err());
+    }
+
+    #[test]
+    fn test_file() {
+        let mut output = vec![];
+        query(
+            parser(LARGE_VCF),
+            "%CHROM\\t%POS\\t%ID\\t[%GT ]\\n",
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("20\t34001111\trs565014200\t0|0 0|0 0|0 \n"));
+    }
+}
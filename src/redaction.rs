@@ -0,0 +1,465 @@
+use crate::{
+    body::{DataLine, FormatType, IdType, InfoType, SampleType},
+    encryption::{map_field_values, Field},
+    header::{self, Header, HeaderLine},
+    parser::{transform, VCFParser},
+    text::Text,
+};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    fmt::{Display, Error, Formatter},
+    fs,
+    io::{BufRead, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// A rule of a redaction profile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// Removes an INFO or FORMAT key from the header and the data lines.
+    Drop(Field),
+
+    /// Replaces the values of a field with the missing value `.`.
+    Blank(Field),
+
+    /// Removes a metadata key, e.g., `Ethnicity`, from the `##SAMPLE` lines.
+    DropSampleMeta(String),
+
+    /// Removes the header lines with the given key, e.g., `pedigreeDB`.
+    DropHeaderLine(String),
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    /// Parses rules such as `drop INFO/DP`, `blank ID`, `drop SAMPLE/Disease` or
+    /// `drop HEADER/pedigreeDB`.
+    fn from_str(rule_str: &str) -> anyhow::Result<Self> {
+        let (action, target) = rule_str
+            .split_once(char::is_whitespace)
+            .map(|(action, target)| (action, target.trim()))
+            .ok_or_else(|| anyhow::anyhow!("invalid redaction rule `{}`", rule_str))?;
+        let rule = match (action, target) {
+            ("drop", "ID") => {
+                return Err(anyhow::anyhow!(
+                    "invalid redaction rule `{}`, (the ID column can only be blanked)",
+                    rule_str
+                ))
+            }
+            ("drop", "HEADER/INFO") | ("drop", "HEADER/FORMAT") | ("drop", "HEADER/META") => {
+                return Err(anyhow::anyhow!(
+                    "invalid redaction rule `{}`, (INFO, FORMAT and SAMPLE keys must be dropped individually)",
+                    rule_str
+                ))
+            }
+            ("drop", "HEADER/FILTER") | ("drop", "HEADER/contig") | ("drop", "HEADER/ALT") => {
+                return Err(anyhow::anyhow!(
+                    "invalid redaction rule `{}`, (FILTER, contig and ALT lines declare values of the data lines)",
+                    rule_str
+                ))
+            }
+            ("drop", "HEADER/ENCRYPTED") | ("drop", "HEADER/MERKLE") => {
+                return Err(anyhow::anyhow!(
+                    "invalid redaction rule `{}`, (ENCRYPTED lines are needed to decrypt the file and MERKLE lines to verify it)",
+                    rule_str
+                ))
+            }
+            ("drop", _) if target.starts_with("SAMPLE/") => {
+                Rule::DropSampleMeta(target["SAMPLE/".len()..].to_string())
+            }
+            ("drop", _) if target.starts_with("HEADER/") => {
+                Rule::DropHeaderLine(target["HEADER/".len()..].to_string())
+            }
+            ("drop", _) => Rule::Drop(target.parse()?),
+            ("blank", _) => Rule::Blank(target.parse()?),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "invalid redaction rule `{}`, (rules must start with `drop` or `blank`)",
+                    rule_str
+                ))
+            }
+        };
+        match &rule {
+            Rule::DropSampleMeta(key) | Rule::DropHeaderLine(key) if key.is_empty() => Err(
+                anyhow::anyhow!("invalid redaction rule `{}`, (empty key)", rule_str),
+            ),
+            _ => Ok(rule),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Rule::Drop(field) => write!(f, "drop {}", field),
+            Rule::Blank(field) => write!(f, "blank {}", field),
+            Rule::DropSampleMeta(key) => write!(f, "drop SAMPLE/{}", key),
+            Rule::DropHeaderLine(key) => write!(f, "drop HEADER/{}", key),
+        }
+    }
+}
+
+/// A declarative list of redaction rules, one per line. Empty lines and lines starting with `#`
+/// are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RedactionProfile {
+    pub rules: Vec<Rule>,
+}
+
+impl FromStr for RedactionProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(profile_str: &str) -> anyhow::Result<Self> {
+        let mut rules = vec![];
+        for line in profile_str.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = line.parse()?;
+            if rules.contains(&rule) {
+                return Err(anyhow::anyhow!("duplicate redaction rule `{}`", rule));
+            }
+            rules.push(rule);
+        }
+        Ok(RedactionProfile { rules })
+    }
+}
+
+impl Display for RedactionProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a redaction removed: the header lines, and the number of values each rule removed or
+/// blanked in the header and the data lines.
+#[derive(Debug, Default, PartialEq)]
+pub struct RedactionReport {
+    pub header_lines: Vec<HeaderLine>,
+    pub data_lines: usize,
+    pub values: LinkedHashMap<String, usize>,
+}
+
+impl RedactionReport {
+    fn count(&mut self, rule: &Rule, count: usize) {
+        *self.values.entry(rule.to_string()).or_insert(0) += count;
+    }
+}
+
+impl Display for RedactionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for hl in &self.header_lines {
+            writeln!(f, "removed\t{}", hl)?;
+        }
+        writeln!(f, "data lines\t{}", self.data_lines)?;
+        for (rule, count) in &self.values {
+            writeln!(f, "{}\t{}", rule, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl RedactionProfile {
+    /// Reads a profile from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Reads all data lines of the parser and writes them redacted, along with the redacted header.
+    pub fn redact<R: BufRead, W: Write>(
+        &self,
+        mut parser: VCFParser<R>,
+        writer: W,
+    ) -> anyhow::Result<RedactionReport> {
+        let mut report = RedactionReport::default();
+        self.redact_header(&mut parser.header, &mut report)?;
+        transform(parser, writer, |mut dl| {
+            self.redact_data_line(&mut dl, &mut report)?;
+            Ok([dl])
+        })?;
+        Ok(report)
+    }
+
+    /// Removes the header lines of dropped fields and keys, including their `##ENCRYPTED` and
+    /// `##META` lines, so that no removed key stays declared. Flags cannot be blanked, since they
+    /// have no value, nor can encrypted fields, since their values could no longer be decrypted.
+    pub fn redact_header(
+        &self,
+        header: &mut Header,
+        report: &mut RedactionReport,
+    ) -> anyhow::Result<()> {
+        for rule in &self.rules {
+            if let Rule::Blank(field) = rule {
+                if header.is_encrypted(&field.scope(), field.id()) {
+                    return Err(anyhow::anyhow!(
+                        "`{}` cannot be blanked, (its values are encrypted, drop it instead)",
+                        field
+                    ));
+                }
+            }
+            if let Rule::Blank(Field::Info(id)) = rule {
+                let is_flag = header.header_lines.iter().any(|hl| match hl {
+                    HeaderLine::Info { id: hl_id, typ, .. } => {
+                        hl_id == id && *typ == header::InfoType::Flag
+                    }
+                    _ => false,
+                });
+                if is_flag {
+                    return Err(blank_flag_error(id));
+                }
+            }
+            if let Rule::DropSampleMeta(key) = rule {
+                let mut count = 0;
+                for hl in header.header_lines.iter_mut() {
+                    if let HeaderLine::Sample { meta, doi, .. } = hl {
+                        let removed =
+                            meta.remove(key).is_some() || (key == "DOI" && doi.take().is_some());
+                        if removed {
+                            count += 1;
+                        }
+                    }
+                }
+                report.count(rule, count);
+            }
+        }
+        let (kept, removed) = header
+            .header_lines
+            .drain(..)
+            .partition(|hl| !self.rules.iter().any(|rule| drops_header_line(rule, hl)));
+        header.header_lines = kept;
+        report.header_lines.extend(removed);
+        Ok(())
+    }
+
+    /// Drops and blanks the values of the data line.
+    pub fn redact_data_line(
+        &self,
+        dl: &mut DataLine,
+        report: &mut RedactionReport,
+    ) -> anyhow::Result<()> {
+        for rule in &self.rules {
+            let count = match rule {
+                Rule::Drop(Field::Info(id)) => drop_info(dl, id),
+                Rule::Drop(Field::Format(id)) => drop_format(dl, id),
+                Rule::Blank(Field::Id) => match std::mem::replace(&mut dl.id, IdType::Missing) {
+                    IdType::Entries(entries) => entries.len(),
+                    IdType::Missing => 0,
+                },
+                Rule::Blank(Field::Info(id)) if matches!(&dl.info, InfoType::Entries(entries) if entries.iter().any(|e| e == id.as_str())) =>
+                {
+                    return Err(blank_flag_error(id));
+                }
+                Rule::Blank(field) => {
+                    let mut count = 0;
+                    map_field_values(dl, field, |value, _, _| {
+                        if value != b"." {
+                            count += 1;
+                        }
+                        Ok(Text::from("."))
+                    })?;
+                    count
+                }
+                _ => continue,
+            };
+            report.count(rule, count);
+        }
+        report.data_lines += 1;
+        Ok(())
+    }
+}
+
+/// Returns whether the rule removes the header line.
+fn drops_header_line(rule: &Rule, hl: &HeaderLine) -> bool {
+    match (rule, hl) {
+        (Rule::Drop(Field::Info(id)), HeaderLine::Info { id: hl_id, .. })
+        | (Rule::Drop(Field::Format(id)), HeaderLine::Format { id: hl_id, .. }) => id == hl_id,
+        (
+            Rule::Drop(field),
+            HeaderLine::Encrypted {
+                id: hl_id, scope, ..
+            },
+        ) => field.id() == hl_id && *scope == field.scope(),
+        (Rule::DropSampleMeta(key), HeaderLine::Meta { id, .. }) => id == key,
+        (Rule::DropHeaderLine(key), hl) => hl.key() == key,
+        _ => false,
+    }
+}
+
+fn blank_flag_error(id: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "INFO flag `{}` cannot be blanked, (flags have no value, drop them instead)",
+        id
+    )
+}
+
+/// Removes an INFO key from the data line, returning the number of removed entries.
+fn drop_info(dl: &mut DataLine, id: &str) -> usize {
+    let mut count = 0;
+    if let InfoType::Entries(entries) = &mut dl.info {
+        entries.retain(|e| {
+            let drop = e.as_bytes().split(|b| *b == b'=').next() == Some(id.as_bytes());
+            if drop {
+                count += 1;
+            }
+            !drop
+        });
+        if entries.is_empty() {
+            dl.info = InfoType::Missing;
+        }
+    }
+    count
+}
+
+/// Removes a FORMAT key and its values from the data line, returning the number of removed values.
+fn drop_format(dl: &mut DataLine, id: &str) -> usize {
+    let index = match dl.format_index(id) {
+        Some(index) => index,
+        None => return 0,
+    };
+    let mut count = 0;
+    for sample in dl.samples.iter_mut() {
+        if let SampleType::Entries(entries) = sample {
+            if index < entries.len() {
+                if entries.remove(index) != "." {
+                    count += 1;
+                }
+                if entries.is_empty() {
+                    *sample = SampleType::Missing;
+                }
+            }
+        }
+    }
+    if let Some(FormatType::Entries(entries)) = &mut dl.format {
+        entries.remove(index);
+        if entries.is_empty() {
+            dl.format = Some(FormatType::Missing);
+            dl.samples.iter_mut().for_each(|s| *s = SampleType::Missing);
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod test {
+    use crate::redaction::*;
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+##pedigreeDB=https://pedigree.example.org
+##META=<ID=Ethnicity,Type=String,Number=.,Values=[AFR,EUR]>
+##SAMPLE=<ID=SAMP001,Assay=WholeGenome,Ethnicity=AFR,Disease=None,Description=\"Patient germline genome\">
+##PEDIGREE=<ID=SAMP001,Original=SAMP002>
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele Frequency\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype Quality\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMP001\tSAMP002
+20\t14370\trs6054257\tG\tA\t29\tPASS\tDP=14;AF=0.5\tGT:GQ\t0|0:48\t1|0:.
+20\t17330\t.\tT\tA\t3\tq10\tDP=11\tGQ\t49\t3";
+
+    const PROFILE: &str = "# external release
+drop INFO/DP
+blank INFO/AF
+drop FORMAT/GQ
+drop SAMPLE/Ethnicity
+drop SAMPLE/Disease
+drop HEADER/pedigreeDB
+blank ID
+";
+
+    #[test]
+    fn test_profile() {
+        let profile = RedactionProfile::from_str(PROFILE).unwrap();
+        assert_eq!(profile.rules.len(), 7);
+        assert_eq!(profile.rules[0], Rule::Drop(Field::Info("DP".to_string())));
+        assert_eq!(
+            profile.rules[5],
+            Rule::DropHeaderLine("pedigreeDB".to_string())
+        );
+        assert_eq!(
+            RedactionProfile::from_str(&profile.to_string()).unwrap(),
+            profile
+        );
+        assert!(Rule::from_str("drop ID").is_err());
+        assert!(Rule::from_str("drop HEADER/INFO").is_err());
+        assert!(Rule::from_str("drop HEADER/FILTER").is_err());
+        assert!(Rule::from_str("drop HEADER/contig").is_err());
+        assert!(Rule::from_str("drop HEADER/ALT").is_err());
+        assert!(Rule::from_str("drop HEADER/ENCRYPTED").is_err());
+        assert!(Rule::from_str("drop HEADER/MERKLE").is_err());
+        assert!(Rule::from_str("drop SAMPLE/").is_err());
+        assert!(Rule::from_str("remove INFO/DP").is_err());
+        assert!(RedactionProfile::from_str("blank ID\nblank ID").is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let profile = RedactionProfile::from_str(PROFILE).unwrap();
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        let mut output = vec![];
+        let report = profile.redact(parser, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[1],
+            "##SAMPLE=<ID=SAMP001,Assay=WholeGenome,Description=\"Patient germline genome\">"
+        );
+        assert_eq!(
+            lines[3],
+            "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele Frequency\">"
+        );
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[6], "20\t14370\t.\tG\tA\t29\tPASS\tAF=.\tGT\t0|0\t1|0");
+        assert_eq!(lines[7], "20\t17330\t.\tT\tA\t3\tq10\t.\t.\t.\t.");
+        assert!(!output.contains("pedigree.example.org"));
+        assert!(!output.contains("Ethnicity"));
+
+        assert_eq!(report.header_lines.len(), 4);
+        assert_eq!(report.data_lines, 2);
+        assert_eq!(report.values["drop INFO/DP"], 2);
+        assert_eq!(report.values["blank INFO/AF"], 1);
+        assert_eq!(report.values["drop FORMAT/GQ"], 3);
+        assert_eq!(report.values["drop SAMPLE/Disease"], 1);
+        assert_eq!(report.values["blank ID"], 1);
+        assert!(report
+            .to_string()
+            .contains("removed\t##pedigreeDB=https://pedigree.example.org\n"));
+    }
+
+    #[test]
+    fn test_blank_flag() {
+        let redact = |vcf: &str| {
+            let profile = RedactionProfile::from_str("blank INFO/DB").unwrap();
+            profile.redact(VCFParser::new(vcf.as_bytes()).unwrap(), vec![])
+        };
+        let vcf = SMALL_VCF.replace("DP=11", "DP=11;DB");
+        assert!(redact(&vcf).is_err());
+        let vcf = vcf.replace(
+            "##INFO=<ID=AF,",
+            "##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">\n##INFO=<ID=AF,",
+        );
+        assert!(redact(&vcf).is_err());
+        assert!(redact(SMALL_VCF).is_ok());
+    }
+
+    #[test]
+    fn test_blank_encrypted() {
+        let encrypted = SMALL_VCF.replace(
+            "##FORMAT=<ID=GT,",
+            "##ENCRYPTED=<ID=AF,Scope=INFO,OriginalType=Float,OriginalNumber=A,Cipher=AES-256-GCM,KeyId=k1>\n##FORMAT=<ID=GT,",
+        );
+        let redact = |profile: &str| {
+            let profile = RedactionProfile::from_str(profile).unwrap();
+            profile.redact(VCFParser::new(encrypted.as_bytes()).unwrap(), vec![])
+        };
+        assert_eq!(
+            redact("blank INFO/AF").unwrap_err().to_string(),
+            "`INFO/AF` cannot be blanked, (its values are encrypted, drop it instead)"
+        );
+        assert!(redact("drop INFO/AF").is_ok());
+        assert!(redact("blank ID").is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+use crate::{
+    body::DataLine,
+    header::Version,
+    parser::{IntoIter, VCFReader},
+};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Error, Formatter},
+    fs::File,
+    io::{BufRead, BufReader},
+    str::FromStr,
+};
+
+/// A region of a chromosome in 1-based, inclusive coordinates, like the positions of data lines.
+/// A region without an end extends to the end of the chromosome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub chromosome: String,
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl Region {
+    /// A whole chromosome.
+    pub fn chromosome(chromosome: &str) -> Self {
+        Region {
+            chromosome: chromosome.to_string(),
+            start: 1,
+            end: None,
+        }
+    }
+
+    /// Converts a BED interval, 0-based and half-open, to a region.
+    pub fn from_bed(chromosome: &str, start: u64, end: u64) -> anyhow::Result<Self> {
+        if end <= start {
+            return Err(anyhow::anyhow!(
+                "invalid BED interval `{}:{}-{}`, (empty)",
+                chromosome,
+                start,
+                end
+            ));
+        }
+        Ok(Region {
+            chromosome: chromosome.to_string(),
+            start: start + 1,
+            end: Some(end),
+        })
+    }
+
+    /// Parses a region like `from_str`, but a string naming one of the contigs is taken as the
+    /// whole contig first, as htslib does, e.g., `HLA-A*01:01:01:01`, which would otherwise be
+    /// position 1 of `HLA-A*01:01:01`.
+    pub fn with_contigs<S: AsRef<str>>(region_str: &str, contigs: &[S]) -> anyhow::Result<Self> {
+        if contigs.iter().any(|contig| contig.as_ref() == region_str) {
+            return Ok(Region::chromosome(region_str));
+        }
+        region_str.parse()
+    }
+
+    /// Returns whether the region overlaps the positions `start` to `end`, inclusive.
+    pub fn overlaps(&self, chromosome: &str, start: u64, end: u64) -> bool {
+        self.chromosome == chromosome && start <= self.end.unwrap_or(u64::MAX) && end >= self.start
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    /// Parses `chr20`, `20:1000-2000`, `20:1000-` or `20:1000`, a single position. Positions may
+    /// contain `,` separators, e.g., `20:1,000,000-`. The string is split at its last `:` only if
+    /// what follows is a range, so that other contig names containing `:` are kept whole, see
+    /// `with_contigs`.
+    fn from_str(region_str: &str) -> anyhow::Result<Self> {
+        let invalid =
+            |reason: &str| anyhow::anyhow!("invalid region `{}`, ({})", region_str, reason);
+        let is_range = |range: &str| {
+            let (start, end) = range.split_once('-').unwrap_or((range, ""));
+            let is_position = |p: &str| p.bytes().all(|b| b.is_ascii_digit() || b == b',');
+            !start.is_empty() && is_position(start) && is_position(end)
+        };
+        let (chromosome, range) = match region_str.rsplit_once(':') {
+            Some((chromosome, range)) if is_range(range) => (chromosome, Some(range)),
+            _ => (region_str, None),
+        };
+        if chromosome.is_empty() || chromosome.contains(char::is_whitespace) {
+            return Err(invalid("invalid chromosome"));
+        }
+        let range = match range {
+            Some(range) => range,
+            None => return Ok(Region::chromosome(chromosome)),
+        };
+        let position = |position_str: &str| {
+            position_str
+                .replace(',', "")
+                .parse::<u64>()
+                .ok()
+                .filter(|p| *p > 0)
+                .ok_or_else(|| invalid("invalid position"))
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, "")) => (position(start)?, None),
+            Some((start, end)) => (position(start)?, Some(position(end)?)),
+            None => (position(range)?, Some(position(range)?)),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid("end before start"));
+        }
+        Ok(Region {
+            chromosome: chromosome.to_string(),
+            start,
+            end,
+        })
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match (self.start, self.end) {
+            (1, None) => write!(f, "{}", self.chromosome),
+            (start, None) => write!(f, "{}:{}-", self.chromosome, start),
+            (start, Some(end)) => write!(f, "{}:{}-{}", self.chromosome, start, end),
+        }
+    }
+}
+
+/// Reads the intervals of a BED file as regions. Empty lines, comments and `track` and `browser`
+/// lines are skipped, and columns after the third are ignored.
+pub fn read_bed<R: BufRead>(reader: R) -> anyhow::Result<Vec<Region>> {
+    let mut regions = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 3 {
+            return Err(anyhow::anyhow!("invalid BED line `{}`", line));
+        }
+        let coordinate = |s: &str| {
+            s.trim()
+                .parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("invalid BED line `{}`", line))
+        };
+        regions.push(Region::from_bed(
+            columns[0],
+            coordinate(columns[1])?,
+            coordinate(columns[2])?,
+        )?);
+    }
+    Ok(regions)
+}
+
+/// Which positions of a data line must be in a region for it to overlap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    /// Only `POS`.
+    Position,
+
+    /// Any position from `POS` to the end of the variant, see `DataLine::end`.
+    Variant,
+}
+
+/// Selects the data lines overlapping any of a set of regions, or, for the complement, none of
+/// them. Unlike an index query, the input is streamed, so it works on any `VCFReader`, in any
+/// order.
+#[derive(Debug, Clone)]
+pub struct RegionFilter {
+    /// The regions of each chromosome as sorted, merged `(start, end)` intervals.
+    intervals: HashMap<String, Vec<(u64, u64)>>,
+    overlap: Overlap,
+    complement: bool,
+
+    /// The version of the file, for the end of variants.
+    version: Version,
+}
+
+impl RegionFilter {
+    pub fn new(regions: &[Region], overlap: Overlap, complement: bool) -> Self {
+        let mut intervals: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+        for region in regions {
+            intervals
+                .entry(region.chromosome.clone())
+                .or_default()
+                .push((region.start, region.end.unwrap_or(u64::MAX)));
+        }
+        for chromosome_intervals in intervals.values_mut() {
+            chromosome_intervals.sort_unstable();
+            let mut merged: Vec<(u64, u64)> = vec![];
+            for (start, end) in chromosome_intervals.drain(..) {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *chromosome_intervals = merged;
+        }
+        RegionFilter {
+            intervals,
+            overlap,
+            complement,
+            version: Version::default(),
+        }
+    }
+
+    /// Sets the version of the file, which is `VCFv4.3` by default.
+    pub fn with_version(mut self, version: &Version) -> Self {
+        self.version = version.clone();
+        self
+    }
+
+    /// A filter on the intervals of a BED file.
+    pub fn from_bed(path: &str, overlap: Overlap, complement: bool) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("could not open BED file `{}`: {}", path, e))?;
+        Ok(RegionFilter::new(
+            &read_bed(BufReader::new(file))?,
+            overlap,
+            complement,
+        ))
+    }
+
+    /// Returns whether the data line overlaps a region, ignoring the complement.
+    pub fn overlaps(&self, dl: &DataLine) -> bool {
+        let intervals = match self.intervals.get(&dl.chromosome) {
+            Some(intervals) => intervals,
+            None => return false,
+        };
+        let start = dl.position;
+        let end = match self.overlap {
+            Overlap::Position => start,
+            Overlap::Variant => dl.end(&self.version),
+        };
+        // the first interval ending at or after the start is the only candidate, as the intervals
+        // are sorted and disjoint
+        let index = intervals.partition_point(|(_, interval_end)| *interval_end < start);
+        intervals
+            .get(index)
+            .is_some_and(|(interval_start, _)| *interval_start <= end)
+    }
+
+    /// Returns whether the data line is selected, i.e., whether it overlaps a region or, for the
+    /// complement, does not.
+    pub fn matches(&self, dl: &DataLine) -> bool {
+        self.overlaps(dl) != self.complement
+    }
+}
+
+/// An iterator over the data lines selected by a `RegionFilter`. Errors are passed through.
+pub struct RegionFilteredLines<I> {
+    lines: I,
+    filter: RegionFilter,
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> RegionFilteredLines<I> {
+    pub fn new(lines: I, filter: RegionFilter) -> Self {
+        RegionFilteredLines { lines, filter }
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<DataLine>>> Iterator for RegionFilteredLines<I> {
+    type Item = anyhow::Result<DataLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(dl) if !self.filter.matches(&dl) => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> VCFReader<R> {
+    /// Returns the data lines selected by the region filter, like `bcftools view -t`.
+    pub fn targets(self, filter: RegionFilter) -> RegionFilteredLines<IntoIter<R>> {
+        RegionFilteredLines::new(self.into_iter(), filter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parser::VCFParser, region::*};
+
+    const SMALL_VCF: &str = "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+19\t14370\t.\tG\tA\t29\tPASS\t.
+20\t999\tdel\tGTTTT\tG\t3\tq10\t.
+20\t1500\t.\tT\tA\t3\tq10\t.
+20\t2500\tsv\tN\t<DEL>\t.\tPASS\tEND=3200
+20\t5000\t.\tA\tG\t67\tPASS\t.";
+
+    fn positions(regions: &[Region], overlap: Overlap, complement: bool) -> Vec<u64> {
+        let parser = VCFParser::new(SMALL_VCF.as_bytes()).unwrap();
+        parser
+            .reader
+            .targets(RegionFilter::new(regions, overlap, complement))
+            .map(|dl| dl.unwrap().position)
+            .collect()
+    }
+
+    #[test]
+    fn test_region() {
+        let region = Region::from_str("20:1,000,000-").unwrap();
+        assert_eq!((region.start, region.end), (1_000_000, None));
+        assert_eq!(region.to_string(), "20:1000000-");
+        assert_eq!(
+            Region::from_str("chr20").unwrap(),
+            Region::chromosome("chr20")
+        );
+        assert_eq!(Region::from_str("chr20").unwrap().to_string(), "chr20");
+        let region = Region::from_str("20:1000-2000").unwrap();
+        assert_eq!((region.start, region.end), (1000, Some(2000)));
+        assert_eq!(region.to_string(), "20:1000-2000");
+        assert_eq!(Region::from_str("20:7").unwrap().end, Some(7));
+        assert_eq!(
+            Region::from_str("HLA-A*01:01:1-5").unwrap().chromosome,
+            "HLA-A*01:01"
+        );
+        for region_str in &["", ":1-2", "20:0-5", "20:5-1", "20:,-5", "20 :1"] {
+            assert!(Region::from_str(region_str).is_err(), "{}", region_str);
+        }
+
+        // contig names containing `:` are kept whole unless followed by a range
+        for chromosome in &["HLA-A*01:01:01:01N", "20:a-5", "20:-5", "20:1-2-3"] {
+            assert_eq!(
+                Region::from_str(chromosome).unwrap(),
+                Region::chromosome(chromosome)
+            );
+        }
+        let contigs = ["HLA-A*01:01:01:01", "HLA-A*01:01:01"];
+        assert_eq!(
+            Region::with_contigs("HLA-A*01:01:01:01", &contigs).unwrap(),
+            Region::chromosome("HLA-A*01:01:01:01")
+        );
+        let region = Region::with_contigs("HLA-A*01:01:01:01:5-10", &contigs).unwrap();
+        assert_eq!(
+            (region.chromosome.as_str(), region.start, region.end),
+            ("HLA-A*01:01:01:01", 5, Some(10))
+        );
+        assert_eq!(
+            Region::from_str("HLA-A*01:01:01:01").unwrap().chromosome,
+            "HLA-A*01:01:01"
+        );
+    }
+
+    #[test]
+    fn test_bed() {
+        let bed = "track name=test\n# comment\n20\t999\t2000\tname\n\n20\t4999\t5000\n";
+        let regions = read_bed(bed.as_bytes()).unwrap();
+        assert_eq!(
+            regions,
+            vec![
+                Region::from_str("20:1000-2000").unwrap(),
+                Region::from_str("20:5000").unwrap()
+            ]
+        );
+        assert!(read_bed("20\t10\t10".as_bytes()).is_err());
+        assert!(read_bed("20\t10".as_bytes()).is_err());
+        assert!(read_bed("20\ta\t10".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let regions = vec![
+            Region::from_str("20:1000-2000").unwrap(),
+            Region::from_str("20:3000-3100").unwrap(),
+        ];
+        assert_eq!(positions(&regions, Overlap::Position, false), vec![1500]);
+        assert_eq!(
+            positions(&regions, Overlap::Variant, false),
+            vec![999, 1500, 2500]
+        );
+        assert_eq!(
+            positions(&regions, Overlap::Variant, true),
+            vec![14370, 5000]
+        );
+
+        let regions = vec![
+            Region::chromosome("19"),
+            Region::from_str("20:4000-").unwrap(),
+        ];
+        assert_eq!(
+            positions(&regions, Overlap::Position, false),
+            vec![14370, 5000]
+        );
+        assert_eq!(positions(&[], Overlap::Position, true).len(), 5);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    body::{AltType, DataLine, FilterType, QualType, VariantType},
+    parser::VCFParser,
+};
+use linked_hash_map::LinkedHashMap;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Error, Formatter},
+    io::BufRead,
+};
+
+/// Depths of at least this value are counted together, like `bcftools stats`.
+pub const MAX_DEPTH: u64 = 500;
+
+/// Summary statistics of data lines, similar to `bcftools stats`. Data lines with alleles of
+/// several types are counted once for every type.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub samples: usize,
+    pub records: u64,
+
+    /// Data lines without alternate alleles.
+    pub no_alts: u64,
+    pub snps: u64,
+    pub mnps: u64,
+    pub indels: u64,
+
+    /// Data lines with alleles that are neither SNPs, MNPs nor indels, e.g., `REF=AC ALT=GTT`.
+    pub others: u64,
+
+    /// Data lines with symbolic or breakend alleles, e.g., `<DEL>`.
+    pub symbolic: u64,
+    pub multiallelic_sites: u64,
+
+    /// Multiallelic sites whose alternate alleles are all SNPs.
+    pub multiallelic_snp_sites: u64,
+    pub transitions: u64,
+    pub transversions: u64,
+
+    /// Data lines by chromosome, in file order.
+    pub contigs: LinkedHashMap<String, u64>,
+
+    /// Data lines by filter, where `PASS` and `.` (missing) are filters too.
+    pub filters: LinkedHashMap<String, u64>,
+
+    /// Data lines by QUAL, missing values excluded.
+    pub quality: BTreeMap<u32, u64>,
+
+    /// Data lines by INFO/DP, with depths of at least `MAX_DEPTH` counted as `MAX_DEPTH`.
+    pub depth: BTreeMap<u64, u64>,
+
+    /// Indel alleles by length, negative for deletions.
+    pub indel_lengths: BTreeMap<i64, u64>,
+}
+
+impl Stats {
+    pub fn new(samples: usize) -> Self {
+        Stats {
+            samples,
+            ..Stats::default()
+        }
+    }
+
+    pub fn add(&mut self, dl: &DataLine) {
+        self.records += 1;
+        *self.contigs.entry(dl.chromosome.clone()).or_insert(0) += 1;
+        match &dl.filter {
+            FilterType::Pass => *self.filters.entry("PASS".to_string()).or_insert(0) += 1,
+            FilterType::Missing => *self.filters.entry(".".to_string()).or_insert(0) += 1,
+            FilterType::Entries(entries) => {
+                for entry in entries {
+                    *self.filters.entry(entry.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        if let QualType::Integer(quality) = dl.quality {
+            *self.quality.entry(quality).or_insert(0) += 1;
+        }
+        if let Some(depth) = dl
+            .info_value("DP")
+            .ok()
+            .flatten()
+            .and_then(|dp| dp.parse::<u64>().ok())
+        {
+            *self.depth.entry(depth.min(MAX_DEPTH)).or_insert(0) += 1;
+        }
+
+        let alts = match &dl.alternative {
+            AltType::Entries(alts) => alts.as_slice(),
+            AltType::Missing => &[],
+        };
+        let types: Vec<VariantType> = dl
+            .variant_types()
+            .into_iter()
+            .filter(|variant_type| variant_type.is_variant())
+            .collect();
+        if types.is_empty() {
+            self.no_alts += 1;
+            return;
+        }
+        let has = |f: fn(&VariantType) -> bool| types.iter().any(f) as u64;
+        self.snps += has(|t| *t == VariantType::Snv);
+        self.mnps += has(|t| *t == VariantType::Mnp);
+        self.indels += has(|t| matches!(t, VariantType::Insertion | VariantType::Deletion));
+        self.others += has(|t| *t == VariantType::Complex);
+        self.symbolic += has(|t| matches!(t, VariantType::Symbolic(_) | VariantType::Breakend));
+        if types.len() > 1 {
+            self.multiallelic_sites += 1;
+            // like bcftools, only sites whose alleles are all SNPs
+            self.multiallelic_snp_sites += types.iter().all(|t| *t == VariantType::Snv) as u64;
+        }
+        for alt in alts {
+            match VariantType::new(&dl.reference, alt) {
+                VariantType::Snv if is_transition(&dl.reference, alt) => self.transitions += 1,
+                VariantType::Snv => self.transversions += 1,
+                VariantType::Insertion | VariantType::Deletion => {
+                    let length = alt.len() as i64 - dl.reference.len() as i64;
+                    *self.indel_lengths.entry(length).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The ratio of transitions to transversions, or `0` without transversions.
+    pub fn ts_tv(&self) -> f64 {
+        if self.transversions == 0 {
+            0.0
+        } else {
+            self.transitions as f64 / self.transversions as f64
+        }
+    }
+}
+
+/// Whether the single differing base of a SNP is a purine to purine or pyrimidine to pyrimidine
+/// change.
+pub(crate) fn is_transition(reference: &str, alt: &str) -> bool {
+    reference
+        .bytes()
+        .zip(alt.bytes())
+        .find(|(r, a)| !r.eq_ignore_ascii_case(a))
+        .is_some_and(|(r, a)| {
+            matches!(
+                (r.to_ascii_uppercase(), a.to_ascii_uppercase()),
+                (b'A', b'G') | (b'G', b'A') | (b'C', b'T') | (b'T', b'C')
+            )
+        })
+}
+
+impl Display for Stats {
+    /// Formats the statistics like `bcftools stats`, as tab separated sections.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "# SN, Summary numbers:")?;
+        writeln!(f, "# SN\t[2]id\t[3]key\t[4]value")?;
+        for (key, value) in &[
+            ("number of samples", self.samples as u64),
+            ("number of records", self.records),
+            ("number of no-ALTs", self.no_alts),
+            ("number of SNPs", self.snps),
+            ("number of MNPs", self.mnps),
+            ("number of indels", self.indels),
+            ("number of others", self.others),
+            ("number of symbolic", self.symbolic),
+            ("number of multiallelic sites", self.multiallelic_sites),
+            (
+                "number of multiallelic SNP sites",
+                self.multiallelic_snp_sites,
+            ),
+        ] {
+            writeln!(f, "SN\t0\t{}:\t{}", key, value)?;
+        }
+        writeln!(f, "# TSTV, transitions/transversions:")?;
+        writeln!(f, "# TSTV\t[2]id\t[3]ts\t[4]tv\t[5]ts/tv")?;
+        writeln!(
+            f,
+            "TSTV\t0\t{}\t{}\t{:.2}",
+            self.transitions,
+            self.transversions,
+            self.ts_tv()
+        )?;
+        writeln!(f, "# CN, Records by contig:")?;
+        writeln!(f, "# CN\t[2]id\t[3]contig\t[4]number of records")?;
+        for (contig, count) in &self.contigs {
+            writeln!(f, "CN\t0\t{}\t{}", contig, count)?;
+        }
+        writeln!(f, "# FLT, Records by filter:")?;
+        writeln!(f, "# FLT\t[2]id\t[3]filter\t[4]number of records")?;
+        for (filter, count) in &self.filters {
+            writeln!(f, "FLT\t0\t{}\t{}", filter, count)?;
+        }
+        writeln!(f, "# QUAL, Stats by quality:")?;
+        writeln!(f, "# QUAL\t[2]id\t[3]Quality\t[4]number of records")?;
+        for (quality, count) in &self.quality {
+            writeln!(f, "QUAL\t0\t{}\t{}", quality, count)?;
+        }
+        writeln!(f, "# IDD, InDel distribution:")?;
+        writeln!(
+            f,
+            "# IDD\t[2]id\t[3]length (deletions negative)\t[4]number of sites"
+        )?;
+        for (length, count) in &self.indel_lengths {
+            writeln!(f, "IDD\t0\t{}\t{}", length, count)?;
+        }
+        writeln!(f, "# DP, Depth distribution:")?;
+        writeln!(f, "# DP\t[2]id\t[3]bin\t[4]number of sites")?;
+        for (depth, count) in &self.depth {
+            if *depth == MAX_DEPTH {
+                writeln!(f, "DP\t0\t>{}\t{}", MAX_DEPTH - 1, count)?;
+            } else {
+                writeln!(f, "DP\t0\t{}\t{}", depth, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Computes the statistics of every data line of the parser.
+pub fn stats<R: BufRead>(parser: VCFParser<R>) -> anyhow::Result<Stats> {
+    let mut stats = Stats::new(parser.header.column_names.len());
+    for dl in parser.reader {
+        stats.add(&dl?);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        stats::*,
+        test_util::{parser, LARGE_VCF, SMALL_VCF},
+    };
+
+    #[test]
+    fn test_stats() {
+        let stats = stats(parser(SMALL_VCF)).unwrap();
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.records, 5);
+        assert_eq!(stats.no_alts, 1);
+        assert_eq!((stats.snps, stats.mnps, stats.indels), (3, 0, 1));
+        assert_eq!((stats.others, stats.symbolic), (0, 0));
+        assert_eq!(stats.multiallelic_sites, 2);
+        assert_eq!(stats.multiallelic_snp_sites, 1);
+        assert_eq!((stats.transitions, stats.transversions), (2, 2));
+        assert_eq!(stats.contigs.get("20"), Some(&5));
+        assert_eq!(stats.filters.get("PASS"), Some(&4));
+        assert_eq!(stats.filters.get("q10"), Some(&1));
+        assert_eq!(stats.quality.len(), 5);
+        assert_eq!(stats.depth.get(&14), Some(&1));
+        assert_eq!(stats.indel_lengths.get(&-2), Some(&1));
+        assert_eq!(stats.indel_lengths.get(&1), Some(&1));
+
+        let text = stats.to_string();
+        assert!(text.contains("SN\t0\tnumber of records:\t5\n"));
+        assert!(text.contains("TSTV\t0\t2\t2\t1.00\n"));
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["contigs"]["20"], 5);
+        assert_eq!(json["indel_lengths"]["-2"], 1);
+    }
+
+    #[test]
+    fn test_other_alleles() {
+        let mut stats = Stats::new(0);
+        for line_str in &[
+            "21\t1\t.\tAC\tGT,<DEL>,ATTT\t.\tq10;s50\tDP=600",
+            "21\t2\t.\tA\tG,AT\t.\t.\t.",
+        ] {
+            stats.add(&DataLine::new(line_str, &[]).unwrap());
+        }
+        assert_eq!((stats.mnps, stats.others, stats.symbolic), (1, 1, 1));
+        // sites with SNPs and other alleles are not multiallelic SNP sites
+        assert_eq!(
+            (stats.multiallelic_sites, stats.multiallelic_snp_sites),
+            (2, 0)
+        );
+        assert_eq!(stats.filters.get("s50"), Some(&1));
+        assert_eq!(stats.depth.get(&MAX_DEPTH), Some(&1));
+        assert!(stats.to_string().contains("DP\t0\t>499\t1\n"));
+    }
+
+    #[test]
+    fn test_file() {
+        let stats = stats(parser(LARGE_VCF)).unwrap();
+        assert_eq!(stats.records, 4633);
+        assert_eq!(stats.samples, 3);
+        assert!(stats.ts_tv() > 1.0);
+    }
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the unit tests.
+
+use crate::parser::VCFParser;
+use std::{fs::File, io::BufReader};
+
+/// The example of the VCFv4.3 specification: 5 data lines of 3 samples on chromosome 20.
+pub const SMALL_VCF: &str = "test/resources/valid/small-4.3.vcf";
+
+/// 4633 data lines of 3 samples on chromosome 20.
+pub const LARGE_VCF: &str = "test/resources/valid/file.vcf";
+
+/// Parses a test file, e.g., `SMALL_VCF`.
+pub fn parser(path: &str) -> VCFParser<BufReader<File>> {
+    VCFParser::new(File::open(path).unwrap()).unwrap()
+}
+
+/// Reads a test file, e.g., to modify it before parsing.
+pub fn read(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap()
+}
@@ -0,0 +1,138 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, Write},
+};
+
+/// Free text of a header or data line, e.g., a description or an INFO value, kept as the bytes
+/// read. Old files often have Latin-1 text, which is only an error once read as a `str`.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Text(Vec<u8>);
+
+impl Text {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Reads the text as a `str`, failing if it is not valid UTF-8.
+    pub fn as_str(&self) -> anyhow::Result<&str> {
+        checked_str(&self.0)
+    }
+
+    /// Reads the text as a `str`, replacing bytes that are not valid UTF-8 with `U+FFFD`.
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Reads bytes as a `str`, failing if they are not valid UTF-8.
+pub fn checked_str(bytes: &[u8]) -> anyhow::Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        anyhow::anyhow!(
+            "invalid UTF-8 at byte {} of `{}`",
+            e.valid_up_to(),
+            String::from_utf8_lossy(bytes)
+        )
+    })
+}
+
+/// Writes the texts separated by the given byte, e.g., the `;` of the INFO column.
+pub(crate) fn write_joined<W: Write + ?Sized>(
+    writer: &mut W,
+    texts: &[Text],
+    separator: u8,
+) -> io::Result<()> {
+    for (index, text) in texts.iter().enumerate() {
+        if index > 0 {
+            writer.write_all(&[separator])?;
+        }
+        writer.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Self {
+        Text(s.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Text {
+    fn from(s: String) -> Self {
+        Text(s.into_bytes())
+    }
+}
+
+impl From<&[u8]> for Text {
+    fn from(bytes: &[u8]) -> Self {
+        Text(bytes.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Text {
+    fn from(bytes: Vec<u8>) -> Self {
+        Text(bytes)
+    }
+}
+
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl Display for Text {
+    /// Writes the text, replacing bytes that are not valid UTF-8 with `U+FFFD`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_str_lossy())
+    }
+}
+
+impl Debug for Text {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.to_str_lossy(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text::*;
+
+    #[test]
+    fn test_text() {
+        let latin1 = Text::from(&b"G\xE8ne"[..]);
+        assert_eq!(latin1.as_bytes(), b"G\xE8ne");
+        assert_eq!(
+            latin1.as_str().unwrap_err().to_string(),
+            "invalid UTF-8 at byte 1 of `G\u{FFFD}ne`"
+        );
+        assert_eq!(latin1.to_string(), "G\u{FFFD}ne");
+
+        let utf8 = Text::from("G\u{E8}ne");
+        assert_eq!(utf8.as_str().unwrap(), "G\u{E8}ne");
+        assert_eq!(utf8, "G\u{E8}ne");
+        assert_ne!(utf8, latin1);
+
+        let mut joined = vec![];
+        write_joined(&mut joined, &[latin1, utf8], b';').unwrap();
+        assert_eq!(joined, b"G\xE8ne;G\xC3\xA8ne");
+    }
+}
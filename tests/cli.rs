@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+const SMALL_VCF: &str = "test/resources/valid/small-4.3.vcf";
+const FILE_VCF: &str = "test/resources/valid/file.vcf";
+
+fn vcflib(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vcflib"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = vcflib(args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A new empty directory for the files of a test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vcflib-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_view() {
+    let output = stdout(&[
+        "view",
+        "-H",
+        "-r",
+        "20:1,000,000-",
+        "-s",
+        "NA00002",
+        SMALL_VCF,
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("20\t1110696\trs6040355\t"));
+    assert!(lines[0].ends_with("GT:GQ:DP:HQ\t2|1:2:0:18,2"));
+
+    let output = stdout(&["view", "-s", "^NA00001,NA00003", "-e", "QUAL<40", SMALL_VCF]);
+    assert!(output.starts_with("##fileformat=VCFv4.3\n"));
+    assert!(output.contains("\tFORMAT\tNA00002\n"));
+    assert_eq!(output.lines().filter(|l| !l.starts_with('#')).count(), 3);
+
+    let output = vcflib(&["view", "-s", "NA00009", SMALL_VCF]);
+    assert!(!output.status.success());
+
+    // the output ends with a newline only if the input does
+    for path in &[SMALL_VCF, FILE_VCF] {
+        let input = fs::read_to_string(path).unwrap();
+        assert_eq!(stdout(&["view", path]), input);
+        let output = stdout(&["view", "-H", path]);
+        assert_eq!(output.ends_with('\n'), input.ends_with('\n'));
+    }
+}
+
+#[test]
+fn test_query() {
+    // the Readme workflow, filtering by a list of IDs
+    let dir = temp_dir("query");
+    let ids = dir.join("ids.txt");
+    fs::write(&ids, "# dbSNP\nrs6054257\nrs6040355\n").unwrap();
+    let ids_expr = format!("ID=@{}", ids.display());
+    assert_eq!(
+        stdout(&["query", "-i", &ids_expr, "-f", "%ID\\t[ %GT]\\n", SMALL_VCF]),
+        "rs6054257\t 0|0 1|0 1/1\nrs6040355\t 1|2 2|1 2/2\n"
+    );
+
+    let output = vcflib(&[
+        "view",
+        "-H",
+        "--ids",
+        "test/resources/valid/ids.txt",
+        FILE_VCF,
+    ]);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rs84825"));
+    assert!(!vcflib(&["query", "-f", "%XX", SMALL_VCF]).status.success());
+}
+
+#[test]
+fn test_stats() {
+    let output = stdout(&["stats", "test/resources/valid/file.vcf.gz"]);
+    assert!(output.contains("SN\t0\tnumber of records:\t4633\n"));
+    assert!(output.contains("SN\t0\tnumber of samples:\t3\n"));
+
+    let output = stdout(&["stats", "--json", SMALL_VCF]);
+    assert!(output.contains("\"records\": 5,"));
+}
+
+#[test]
+fn test_qc() {
+    let output = stdout(&["qc", SMALL_VCF]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("sample\tcall_rate\t"));
+    assert!(lines[1].starts_with("NA00001\t1.0000\t"));
+
+    let output = stdout(&["qc", "--outliers", "--min-depth", "4", SMALL_VCF]);
+    assert_eq!(output.matches("low_mean_depth").count(), 2);
+    assert!(output.lines().skip(1).all(|l| !l.ends_with("\t.")));
+
+    let output = stdout(&["qc", "--outliers", "--json", "--min-depth", "4", SMALL_VCF]);
+    let rows: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 2);
+    assert_eq!(rows[0]["sample"], "NA00002");
+    assert_eq!(rows[0]["mean_depth"], 3.8);
+    assert_eq!(rows[0]["flags"], serde_json::json!(["low_mean_depth"]));
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(stdout(&["validate", SMALL_VCF]), "valid: 5 data lines\n");
+
+    let dir = temp_dir("validate");
+    let invalid = dir.join("invalid.vcf");
+    let vcf = fs::read_to_string(SMALL_VCF).unwrap();
+    let vcf = vcf
+        .replace("NS=3;DP=14", "XX=3;DP=14")
+        .replace("\tT\t.\t", "\tT\t<DEL:ME>\t");
+    fs::write(&invalid, vcf).unwrap();
+    let output = vcflib(&["validate", invalid.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`INFO/XX` is not defined"));
+    assert!(stderr.contains("data line 4: `ALT/<DEL:ME>` is not defined"));
+
+    // a reference with the REF of the first two data lines of chromosome 20
+    let fasta = dir.join("ref.fa");
+    let mut sequence = "N".repeat(17330);
+    sequence.replace_range(14369..14370, "G");
+    sequence.replace_range(17329..17330, "A");
+    let lines: Vec<&str> = sequence
+        .as_bytes()
+        .chunks(60)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+    fs::write(&fasta, format!(">20\n{}\n", lines.join("\n"))).unwrap();
+    let output = vcflib(&[
+        "validate",
+        "--fasta-ref",
+        fasta.to_str().unwrap(),
+        SMALL_VCF,
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("contig `20` has length 62435964, expected 17330"));
+    assert!(stderr.contains("data line 2: `20:17330` has REF `T`, expected `A`"));
+    assert!(stderr.contains(
+        "data line 3: `20:1110696` has REF `A` past the end of the sequence, of length 17330"
+    ));
+    assert!(!stderr.contains("data line 1:"));
+    assert!(dir.join("ref.fa.fai").exists());
+
+    // a Latin-1 description is free text, which is kept as bytes
+    let latin1 = dir.join("latin1.vcf");
+    let mut bytes = fs::read(SMALL_VCF).unwrap();
+    let index = bytes.windows(4).position(|w| w == b"Data").unwrap();
+    bytes[index + 1] = 0xE4;
+    fs::write(&latin1, bytes).unwrap();
+    let latin1 = latin1.to_str().unwrap();
+    assert_eq!(stdout(&["validate", latin1]), "valid: 5 data lines\n");
+    assert_eq!(
+        stdout(&["validate", "--lossy", latin1]),
+        "valid: 5 data lines\n"
+    );
+}
+
+#[test]
+fn test_compress_index() {
+    let dir = temp_dir("compress");
+    let vcf = dir.join("file.vcf");
+    fs::copy(FILE_VCF, &vcf).unwrap();
+    let vcf = vcf.to_str().unwrap();
+    stdout(&["compress", vcf]);
+    let gz = format!("{}.gz", vcf);
+    // the BGZF end of file marker
+    assert!(fs::read(&gz)
+        .unwrap()
+        .ends_with(&[0x1b, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+    if std::path::Path::new("/dev/full").exists() {
+        assert!(!vcflib(&["compress", "-o", "/dev/full", vcf])
+            .status
+            .success());
+        assert!(!vcflib(&["view", "-o", "/dev/full", vcf]).status.success());
+    }
+    stdout(&["index", &gz]);
+    let tbi = fs::read(format!("{}.tbi", gz)).unwrap();
+    assert!(tbi.starts_with(&[0x1f, 0x8b]));
+    assert!(!vcflib(&["index", &gz]).status.success());
+    stdout(&["index", "--force", &gz]);
+
+    assert_eq!(
+        stdout(&["view", "-H", &gz]),
+        stdout(&["view", "-H", FILE_VCF])
+    );
+    assert!(!vcflib(&["index", FILE_VCF]).status.success());
+}
+
+#[test]
+fn test_encrypt_decrypt() {
+    let dir = temp_dir("encrypt");
+    let key = dir.join("key.hex");
+    fs::write(&key, "11".repeat(32)).unwrap();
+    let key = key.to_str().unwrap();
+    let encrypted = dir.join("encrypted.vcf.gz");
+    let encrypted = encrypted.to_str().unwrap();
+    stdout(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        "INFO/DP,FORMAT/HQ",
+        "-O",
+        "z",
+        "-o",
+        encrypted,
+        SMALL_VCF,
+    ]);
+    let output = stdout(&["view", encrypted]);
+    assert!(output.contains("##ENCRYPTED=<ID=DP,Scope=INFO"));
+    assert!(!output.contains("DP=14;"));
+
+    let decrypted = stdout(&["decrypt", "-k", key, encrypted]);
+    assert_eq!(decrypted, fs::read_to_string(SMALL_VCF).unwrap());
+
+    fs::write(dir.join("wrong.hex"), "22".repeat(32)).unwrap();
+    let wrong = dir.join("wrong.hex");
+    assert!(
+        !vcflib(&["decrypt", "-k", wrong.to_str().unwrap(), encrypted])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn test_norm() {
+    let output = stdout(&["norm", "-m", SMALL_VCF]);
+    let lines: Vec<&str> = output.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(lines.len(), 7);
+    assert!(lines[6].starts_with("20\t1234569\tmicrosat1\tC\tCT\t"));
+}